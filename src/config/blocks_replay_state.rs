@@ -0,0 +1,28 @@
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::*;
+
+/// Masterchain block up to which all transactions were processed
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct BlocksReplayState {
+    pub last_processed_mc_seqno: u32,
+}
+
+impl BlocksReplayState {
+    pub fn try_load<P>(path: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        load_json(path)
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        save_json_atomically(path, self)
+    }
+}
@@ -8,12 +8,18 @@ use rand::Rng;
 use secstr::SecUtf8;
 use serde::{Deserialize, Serialize};
 
+pub use self::blocks_replay_state::*;
 pub use self::eth_config::*;
+pub use self::pending_messages_state::*;
+pub use self::staking_accounting::*;
 pub use self::stored_keys::*;
 pub use self::verification_state::*;
 use crate::utils::*;
 
+mod blocks_replay_state;
 mod eth_config;
+mod pending_messages_state;
+mod staking_accounting;
 mod stored_keys;
 mod verification_state;
 
@@ -34,21 +40,128 @@ pub struct AppConfig {
     /// Bridge related settings
     pub bridge_settings: BridgeConfig,
 
+    /// Other stakers hosted by this relay. Their keys must be encrypted
+    /// with the same master password
+    #[serde(default)]
+    pub additional_stakers: Vec<StakerProfileConfig>,
+
     /// TON node settings
     #[serde(default)]
     pub node_settings: NodeConfig,
 
+    /// TON blocks source. Default: `type: node`
+    #[serde(default)]
+    pub ton_source: TonSourceConfig,
+
     /// Prometheus metrics exporter settings.
     /// Completely disable when not specified
     #[serde(default)]
     pub metrics_settings: Option<MetricsConfig>,
 
+    /// Admin API settings.
+    /// Completely disable when not specified
+    #[serde(default)]
+    pub admin_api_settings: Option<AdminApiConfig>,
+
     /// log4rs settings.
     /// See [docs](https://docs.rs/log4rs/1.0.0/log4rs/) for more details
     #[serde(default = "default_logger_settings")]
     pub logger_settings: serde_yaml::Value,
 }
 
+impl AppConfig {
+    /// All hosted stakers. The first one is specified by `staker_address`
+    /// and the corresponding bridge settings.
+    ///
+    /// Fails if profiles share the staker address or state files
+    pub fn staker_profiles(&self) -> Result<Vec<StakerProfileConfig>> {
+        let settings = &self.bridge_settings;
+        let primary = StakerProfileConfig {
+            staker_address: self.staker_address.clone(),
+            keys_path: settings.keys_path.clone(),
+            next_keys_path: settings.next_keys_path.clone(),
+            staking_accounting_path: settings.staking_accounting_path.clone(),
+            address_verification_state_path: settings.address_verification.state_path.clone(),
+            message_sender: settings.message_delivery.sender.clone(),
+        };
+
+        let profiles = std::iter::once(primary)
+            .chain(self.additional_stakers.iter().cloned())
+            .collect::<Vec<_>>();
+        check_unique_staker_profiles(&profiles)?;
+        check_wallet_senders(&profiles)?;
+        Ok(profiles)
+    }
+}
+
+fn check_unique_staker_profiles(profiles: &[StakerProfileConfig]) -> Result<()> {
+    let mut addresses = std::collections::HashSet::new();
+    let mut paths = std::collections::HashSet::new();
+
+    for profile in profiles {
+        if !addresses.insert(profile.staker_address.to_string()) {
+            return Err(
+                ConfigError::DuplicateStakerAddress(profile.staker_address.to_string()).into(),
+            );
+        }
+
+        let profile_paths = [
+            Some(&profile.keys_path),
+            profile.next_keys_path.as_ref(),
+            Some(&profile.staking_accounting_path),
+            Some(&profile.address_verification_state_path),
+        ];
+        for path in profile_paths.into_iter().flatten() {
+            if !paths.insert(path) {
+                return Err(ConfigError::DuplicateStakerPath(path.clone()).into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check_wallet_senders(profiles: &[StakerProfileConfig]) -> Result<()> {
+    for profile in profiles {
+        if let MessageSenderConfig::Wallet(wallet) = &profile.message_sender {
+            if !wallet.elections_only {
+                return Err(ConfigError::WalletScopeNotAcknowledged(
+                    profile.staker_address.to_string(),
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Staker identity with its own keys and state files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StakerProfileConfig {
+    /// Staker address from which keys were submitted
+    #[serde(with = "serde_address")]
+    pub staker_address: ton_block::MsgAddressInt,
+
+    /// Path to the file with keystore data
+    pub keys_path: PathBuf,
+
+    /// Path to the file with the next keystore data, used for the keys rotation.
+    /// Default: none
+    #[serde(default)]
+    pub next_keys_path: Option<PathBuf>,
+
+    /// Path to the file with per-round participation and rewards
+    pub staking_accounting_path: PathBuf,
+
+    /// Path to the file with ETH address verification transaction state
+    pub address_verification_state_path: PathBuf,
+
+    /// How relay messages are sent. Default: `direct`
+    #[serde(default)]
+    pub message_sender: MessageSenderConfig,
+}
+
 /// Main application config (brief). Used for simple commands that require only password
 #[derive(Serialize, Deserialize)]
 pub struct BriefAppConfig {
@@ -64,6 +177,11 @@ pub struct BridgeConfig {
     /// Path to the file with keystore data
     pub keys_path: PathBuf,
 
+    /// Path to the file with the next keystore data, used for the keys rotation.
+    /// Default: none
+    #[serde(default)]
+    pub next_keys_path: Option<PathBuf>,
+
     /// Bridge contract address
     #[serde(with = "serde_address")]
     pub bridge_address: ton_block::MsgAddressInt,
@@ -72,12 +190,69 @@ pub struct BridgeConfig {
     #[serde(default)]
     pub ignore_elections: bool,
 
+    /// Elections participation rules
+    #[serde(default)]
+    pub election_policy: ElectionPolicyConfig,
+
+    /// Delays of permissionless elections start/end calls
+    #[serde(default)]
+    pub elections_calls: ElectionsCallsConfig,
+
     /// EVM networks settings
     pub networks: Vec<EthConfig>,
 
     /// ETH address verification settings
     #[serde(default)]
     pub address_verification: AddressVerificationConfig,
+
+    /// Account events queues settings
+    #[serde(default)]
+    pub events_queue: EventsQueueConfig,
+
+    /// Transactions subscriptions settings
+    #[serde(default)]
+    pub transaction_handlers: TransactionHandlersConfig,
+
+    /// Event voting settings
+    #[serde(default)]
+    pub event_voting: EventVotingConfig,
+
+    /// External messages delivery settings
+    #[serde(default)]
+    pub message_delivery: MessageDeliveryConfig,
+
+    /// Missed blocks replay settings
+    #[serde(default)]
+    pub blocks_replay: BlocksReplayConfig,
+
+    /// Staking alerts settings
+    #[serde(default)]
+    pub staking_alerts: StakingAlertsConfig,
+
+    /// Path to the file with per-round participation and rewards.
+    /// Default: `./staking-accounting.json`
+    #[serde(default = "default_staking_accounting_path")]
+    pub staking_accounting_path: PathBuf,
+
+    /// Min interval in seconds between reward claims for past rounds. Default: 5
+    #[serde(default = "default_reward_claim_interval_sec")]
+    pub reward_claim_interval_sec: u64,
+
+    /// Max age in seconds of the shared shard states snapshot. Default: 5
+    #[serde(default = "default_shard_accounts_cache_max_age_sec")]
+    pub shard_accounts_cache_max_age_sec: u32,
+}
+
+fn default_staking_accounting_path() -> PathBuf {
+    "staking-accounting.json".into()
+}
+
+fn default_reward_claim_interval_sec() -> u64 {
+    5
+}
+
+fn default_shard_accounts_cache_max_age_sec() -> u32 {
+    5
 }
 
 /// ETH address verification settings
@@ -94,6 +269,14 @@ pub struct AddressVerificationConfig {
     /// Path to the file with transaction state.
     /// Default: `./verification-state.json`
     pub state_path: PathBuf,
+
+    /// Max number of submitted transactions, including re-submissions
+    /// of reverted or dropped ones. Default: 3
+    pub max_attempts: u32,
+
+    /// RPC endpoint of the bridge configuration network. The EVM subscriber
+    /// of this network is used when not specified. Default: none
+    pub endpoint: Option<url::Url>,
 }
 
 impl Default for AddressVerificationConfig {
@@ -102,10 +285,384 @@ impl Default for AddressVerificationConfig {
             min_balance_gwei: 50000000,
             gas_price_gwei: 300,
             state_path: "verification-state.json".into(),
+            max_attempts: 3,
+            endpoint: None,
+        }
+    }
+}
+
+/// Event voting settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventVotingConfig {
+    /// Max number of simultaneously processed events.
+    /// Events closer to the quorum are processed first, then older events. Default: 32
+    pub max_concurrent_tasks: usize,
+
+    /// Whether to skip ETH event votes when the event already has enough
+    /// confirmations or rejections. Default: true
+    pub skip_votes_after_quorum: bool,
+
+    /// Max random delay before sending ETH event vote. Default: 0
+    pub vote_jitter_ms: u64,
+}
+
+impl Default for EventVotingConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_tasks: 32,
+            skip_votes_after_quorum: true,
+            vote_jitter_ms: 0,
+        }
+    }
+}
+
+/// External messages delivery settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessageDeliveryConfig {
+    /// Path to the file with pending messages.
+    /// Default: `./pending-messages.json`
+    pub pending_messages_path: PathBuf,
+
+    /// Events confirmations and rejections
+    pub event_votes: MessageDeliveryPolicy,
+
+    /// `becomeRelayNextRound`, `startElectionOnNewRound` and `endElection`
+    pub elections: MessageDeliveryPolicy,
+
+    /// `getRewardForRelayRound`
+    pub rewards: MessageDeliveryPolicy,
+
+    /// Relay keys confirmation
+    pub verification: MessageDeliveryPolicy,
+
+    /// How relay messages are sent. Default: `direct`
+    pub sender: MessageSenderConfig,
+}
+
+impl Default for MessageDeliveryConfig {
+    fn default() -> Self {
+        Self {
+            pending_messages_path: "pending-messages.json".into(),
+            event_votes: Default::default(),
+            elections: Default::default(),
+            rewards: Default::default(),
+            verification: Default::default(),
+            sender: Default::default(),
+        }
+    }
+}
+
+/// Relay messages sender
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum MessageSenderConfig {
+    /// External messages signed by the relay key are sent directly to contracts
+    Direct,
+    /// Internal messages with attached value are sent through the wallet
+    /// contract controlled by the relay key.
+    ///
+    /// Only `startElectionOnNewRound` and `endElection` calls can be sent by anyone,
+    /// so they are the only messages sent through the wallet. Event votes,
+    /// `becomeRelayNextRound`, `getRewardForRelayRound` and `confirmTonAccount`
+    /// are authorised by the relay pubkey and are still sent directly
+    Wallet(WalletSenderConfig),
+}
+
+impl Default for MessageSenderConfig {
+    fn default() -> Self {
+        Self::Direct
+    }
+}
+
+/// Wallet sender settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WalletSenderConfig {
+    /// Wallet contract address in the basechain
+    #[serde(with = "serde_address")]
+    pub address: ton_block::MsgAddressInt,
+
+    /// Wallet contract type
+    pub wallet_type: WalletType,
+
+    /// Amount of nanotons attached to each message. Default: 1000000000 (1 TON)
+    #[serde(default = "default_attached_amount")]
+    pub attached_amount: u64,
+
+    /// Wallet id. Only used for `wallet_v3`. Default: 698983191
+    #[serde(default = "default_subwallet_id")]
+    pub subwallet_id: u32,
+
+    /// Acknowledges that only the elections start and end calls are sent
+    /// through the wallet. Must be `true`. Default: false
+    #[serde(default)]
+    pub elections_only: bool,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletType {
+    /// Standard wallet v3 (seqno based)
+    WalletV3,
+    /// EverWallet or SafeMultisig with a single custodian (`sendTransaction` method)
+    EverWallet,
+}
+
+fn default_attached_amount() -> u64 {
+    1_000_000_000
+}
+
+fn default_subwallet_id() -> u32 {
+    698983191
+}
+
+/// External message delivery policy
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessageDeliveryPolicy {
+    /// Message expiration timeout. Default: 60
+    pub ttl_sec: u32,
+
+    /// Max number of sending attempts. Unlimited if `null`. Default: 10
+    pub max_attempts: Option<u32>,
+
+    /// Max number of attempts which resulted in an aborted transaction. Default: 3
+    pub max_failed_attempts: u32,
+
+    /// Delay before the next attempt. Doubled after each attempt. Default: 1
+    pub min_backoff_sec: u64,
+
+    /// Max delay before the next attempt. Default: 60
+    pub max_backoff_sec: u64,
+
+    /// Total delivery time limit. Unlimited if `null`. Default: 3600
+    pub deadline_sec: Option<u64>,
+}
+
+impl Default for MessageDeliveryPolicy {
+    fn default() -> Self {
+        Self {
+            ttl_sec: 60,
+            max_attempts: Some(10),
+            max_failed_attempts: 3,
+            min_backoff_sec: 1,
+            max_backoff_sec: 60,
+            deadline_sec: Some(3600),
+        }
+    }
+}
+
+/// Account events queues settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventsQueueConfig {
+    /// Max number of pending events for each listener. Default: 1024
+    pub capacity: usize,
+
+    /// What to do when the queue is full. Default: `buffer`
+    ///
+    /// * `buffer` - events are kept in order in the overflow buffer and forwarded
+    ///   to the queue when there is a free space. Blocks processing is not paused.
+    /// * `drop` - new events are dropped while the queue is full.
+    ///
+    /// Dropped events are counted in the `events_queue_dropped` metric.
+    pub full_policy: EventsQueueFullPolicy,
+
+    /// Max number of events waiting for a free space in the queue
+    /// with the `buffer` policy. New events are dropped when it is full.
+    /// Default: None (unbounded, no events are lost)
+    pub max_overflow: Option<usize>,
+}
+
+impl Default for EventsQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            full_policy: EventsQueueFullPolicy::Buffer,
+            max_overflow: None,
+        }
+    }
+}
+
+/// Missed blocks replay settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlocksReplayConfig {
+    /// Whether to replay blocks which were applied while relay was offline.
+    /// Live transactions are not dispatched until the replay is finished,
+    /// so they are kept in memory during the whole replay.
+    /// Replay fails if some blocks or states are no longer available.
+    /// Default: false
+    pub enabled: bool,
+
+    /// Path to the file with the last processed masterchain block.
+    /// Default: `./blocks-replay-state.json`
+    pub state_path: PathBuf,
+
+    /// Max number of masterchain blocks to replay. Default: 10000
+    pub max_blocks: u32,
+}
+
+impl Default for BlocksReplayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            state_path: "blocks-replay-state.json".into(),
+            max_blocks: 10000,
+        }
+    }
+}
+
+/// Elections participation rules
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ElectionPolicyConfig {
+    /// First relay round to participate in. Default: None
+    pub first_round: Option<u32>,
+
+    /// Last relay round to participate in. Default: None
+    pub last_round: Option<u32>,
+
+    /// Number of rounds to skip after the round in which staker was slashed. Default: 0
+    pub rounds_to_skip_after_slashing: u32,
+
+    /// Required deposit margin above the min relay deposit in percents. Default: 0
+    pub min_deposit_margin_percent: u32,
+}
+
+impl Default for ElectionPolicyConfig {
+    fn default() -> Self {
+        Self {
+            first_round: None,
+            last_round: None,
+            rounds_to_skip_after_slashing: 0,
+            min_deposit_margin_percent: 0,
+        }
+    }
+}
+
+/// Delays of permissionless elections start/end calls, so that
+/// relays don't send identical messages at the same time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ElectionsCallsConfig {
+    /// Max delay derived from the staker address. Default: 30
+    pub max_relay_delay_sec: u32,
+
+    /// Max random delay added to the relay delay. Default: 2000
+    pub jitter_ms: u64,
+}
+
+impl Default for ElectionsCallsConfig {
+    fn default() -> Self {
+        Self {
+            max_relay_delay_sec: 30,
+            jitter_ms: 2000,
+        }
+    }
+}
+
+/// Slashing, deposit and keys alerts settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StakingAlertsConfig {
+    /// Optional URL to which alerts are sent as JSON in POST requests. Default: None
+    pub webhook_url: Option<url::Url>,
+
+    /// Webhook request timeout in seconds. Default: 10
+    pub webhook_timeout_sec: u64,
+
+    /// Alert when relay lock expires in less than this number of seconds. Default: 86400
+    pub lock_expiry_threshold_sec: u32,
+
+    /// Interval in seconds between time-based checks. Default: 60
+    pub check_interval_sec: u64,
+}
+
+impl Default for StakingAlertsConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            webhook_timeout_sec: 10,
+            lock_expiry_threshold_sec: 86400,
+            check_interval_sec: 60,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventsQueueFullPolicy {
+    /// Keep new events in order until there is free space in the queue.
+    /// They are forwarded in a separate task, so blocks processing is not paused.
+    /// Events are dropped only when the overflow is bounded and full
+    #[serde(alias = "block")]
+    Buffer,
+    /// Drop new events (they will only be found after restart)
+    Drop,
+}
+
+/// Transactions subscriptions settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransactionHandlersConfig {
+    /// Handler execution time after which a warning is printed. Default: 50
+    pub slow_handler_threshold_ms: u64,
+
+    /// Whether to handle transactions in a separate task so that slow handlers
+    /// don't delay blocks processing. Default: false
+    pub background_dispatch: bool,
+
+    /// Max number of shard blocks waiting for the background dispatch.
+    /// Blocks processing is paused when the queue is full. Default: 64
+    pub background_queue_capacity: usize,
+}
+
+impl Default for TransactionHandlersConfig {
+    fn default() -> Self {
+        Self {
+            slow_handler_threshold_ms: 50,
+            background_dispatch: false,
+            background_queue_capacity: 64,
         }
     }
 }
 
+/// TON blocks source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TonSourceConfig {
+    /// Embedded full node (see `node_settings`)
+    Node,
+    /// Blocks and shard states from the directory with JSON files.
+    /// Lightweight stand-in for an external indexer, mostly used for tests
+    File(FileTonSourceConfig),
+}
+
+impl Default for TonSourceConfig {
+    fn default() -> Self {
+        Self::Node
+    }
+}
+
+/// File blocks source settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileTonSourceConfig {
+    /// Directory with `{mc_seqno}.json` files
+    pub path: PathBuf,
+
+    /// Interval between checks for new blocks. Default: 1
+    #[serde(default = "default_source_poll_interval_sec")]
+    pub poll_interval_sec: u64,
+}
+
+fn default_source_poll_interval_sec() -> u64 {
+    1
+}
+
 /// TON node settings
 #[derive(Serialize, Deserialize)]
 #[serde(default, deny_unknown_fields)]
@@ -200,6 +757,27 @@ impl Default for NodeConfig {
     }
 }
 
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct AdminApiConfig {
+    /// Listen address of the admin API. Must not be exposed publicly.
+    /// Default: `127.0.0.1:10001`
+    pub listen_address: SocketAddr,
+
+    /// Token which must be passed in the `Authorization: Bearer <token>` header.
+    /// Required if the listen address is not a loopback one. Default: none
+    pub token: Option<String>,
+}
+
+impl Default for AdminApiConfig {
+    fn default() -> Self {
+        Self {
+            listen_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 10001),
+            token: None,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(default)]
 pub struct MetricsConfig {
@@ -269,4 +847,10 @@ fn default_logger_settings() -> serde_yaml::Value {
 enum ConfigError {
     #[error("Failed to find public ip")]
     PublicIpNotFound,
+    #[error("Staker {0} is specified more than once")]
+    DuplicateStakerAddress(String),
+    #[error("Path {0:?} is used by more than one staker")]
+    DuplicateStakerPath(PathBuf),
+    #[error("Wallet sender of staker {0} only covers elections calls, set `elections_only: true` to acknowledge")]
+    WalletScopeNotAcknowledged(String),
 }
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use anyhow::Result;
+use nekoton_utils::*;
+use serde::{Deserialize, Serialize};
+use ton_types::UInt256;
+
+use crate::utils::*;
+
+/// External messages which were broadcasted but not yet delivered or expired
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PendingMessagesState {
+    pub messages: Vec<StoredPendingMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredPendingMessage {
+    #[serde(with = "serde_uint256")]
+    pub account: UInt256,
+    #[serde(with = "serde_uint256")]
+    pub message_hash: UInt256,
+    pub expire_at: u32,
+    /// Last transaction of the destination account when the message was broadcasted.
+    /// Not specified for messages saved by the older versions
+    #[serde(default)]
+    pub last_transaction_lt: Option<u64>,
+    /// Message purpose
+    pub kind: String,
+    /// Serialized message
+    #[serde(with = "serde_bytes_base64")]
+    pub boc: Vec<u8>,
+}
+
+impl PendingMessagesState {
+    pub fn try_load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(load_json(path)?.unwrap_or_default())
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        save_json_atomically(path, self)
+    }
+
+    pub fn remove(&mut self, account: &UInt256, message_hash: &UInt256) {
+        self.messages
+            .retain(|item| &item.account != account || &item.message_hash != message_hash);
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result;
+use nekoton_utils::*;
+use serde::{Deserialize, Serialize};
+use ton_types::UInt256;
+
+use crate::utils::*;
+
+/// Relay participation and rewards by relay rounds
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StakingAccounting {
+    pub rounds: BTreeMap<u32, RoundAccounting>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoundAccounting {
+    /// Whether the relay was in this round. `None` if unknown
+    pub participated: Option<bool>,
+    /// Whether the relay was elected for this round. `None` if unknown
+    pub elected: Option<bool>,
+    /// Relay share of the round reward
+    #[serde(default, with = "serde_optional_u128_string")]
+    pub reward_amount: Option<u128>,
+    pub reward_claimed: bool,
+    /// Reward claim made by this relay instance
+    pub reward_claim: Option<RewardClaim>,
+    /// Whether the staker was slashed during this round
+    pub slashed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RewardClaim {
+    #[serde(with = "serde_uint256")]
+    pub transaction_hash: UInt256,
+    pub claimed_at: u32,
+}
+
+impl StakingAccounting {
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(load_json(path)?.unwrap_or_default())
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        save_json_atomically(path, self)
+    }
+
+    pub fn round_mut(&mut self, round_num: u32) -> &mut RoundAccounting {
+        self.rounds.entry(round_num).or_default()
+    }
+
+    pub fn last_slashed_round(&self) -> Option<u32> {
+        self.rounds
+            .iter()
+            .rev()
+            .find(|(_, round)| round.slashed)
+            .map(|(round_num, _)| *round_num)
+    }
+
+    /// Renders rounds since `from_round` as CSV with a header
+    pub fn to_csv(&self, from_round: u32) -> String {
+        fn opt<T: std::fmt::Display>(value: &Option<T>) -> String {
+            value.as_ref().map(ToString::to_string).unwrap_or_default()
+        }
+
+        let mut result = String::from(
+            "round_num,participated,elected,reward_amount,reward_claimed,claim_transaction_hash,claimed_at,slashed\n",
+        );
+        for (round_num, round) in self.rounds.range(from_round..) {
+            let (transaction_hash, claimed_at) = match &round.reward_claim {
+                Some(claim) => (
+                    claim.transaction_hash.to_hex_string(),
+                    claim.claimed_at.to_string(),
+                ),
+                None => Default::default(),
+            };
+
+            result.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                round_num,
+                opt(&round.participated),
+                opt(&round.elected),
+                opt(&round.reward_amount),
+                round.reward_claimed,
+                transaction_hash,
+                claimed_at,
+                round.slashed,
+            ));
+        }
+        result
+    }
+
+    /// Returns rounds since `from_round`
+    pub fn since(&self, from_round: u32) -> Self {
+        Self {
+            rounds: self
+                .rounds
+                .range(from_round..)
+                .map(|(round_num, round)| (*round_num, round.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reward_amount_is_serialized_as_string() {
+        let mut accounting = StakingAccounting::default();
+        accounting.round_mut(1).reward_amount = Some(u128::MAX);
+        accounting.round_mut(2);
+
+        let json = serde_json::to_value(&accounting).unwrap();
+        assert_eq!(
+            json["rounds"]["1"]["reward_amount"],
+            serde_json::json!(u128::MAX.to_string())
+        );
+        assert!(json["rounds"]["2"]["reward_amount"].is_null());
+
+        let parsed: StakingAccounting = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.rounds[&1].reward_amount, Some(u128::MAX));
+        assert_eq!(parsed.rounds[&2].reward_amount, None);
+    }
+
+    #[test]
+    fn numeric_reward_amount_is_accepted() {
+        let parsed: StakingAccounting = serde_json::from_str(
+            r#"{"rounds":{"1":{"participated":true,"elected":null,"reward_amount":1000,"reward_claimed":false,"reward_claim":null,"slashed":false}}}"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.rounds[&1].reward_amount, Some(1000));
+    }
+}
@@ -4,12 +4,25 @@ use anyhow::Result;
 use nekoton_utils::*;
 use serde::{Deserialize, Serialize};
 
+use crate::utils::*;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AddressVerificationState {
+    /// EVM network of the transaction. Zero for the states created by the older versions
+    #[serde(default)]
+    pub chain_id: u32,
     #[serde(with = "serde_hex_array")]
     pub transaction_hash: [u8; 32],
     #[serde(with = "serde_hex_array")]
     pub address: [u8; 20],
+    #[serde(default = "default_transaction_status")]
+    pub status: AddressVerificationStatus,
+    /// Block in which the transaction was included
+    #[serde(default)]
+    pub block_number: Option<u64>,
+    /// Number of submitted transactions
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
 }
 
 impl AddressVerificationState {
@@ -17,26 +30,73 @@ impl AddressVerificationState {
     where
         P: AsRef<Path>,
     {
-        let path = path.as_ref();
-        Ok(if path.exists() {
-            let file = std::fs::OpenOptions::new().read(true).open(path)?;
-            let state = serde_json::from_reader(&file)?;
-            Some(state)
-        } else {
-            None
-        })
+        load_json(path)
     }
 
     pub fn save<P>(&self, path: P) -> Result<()>
     where
         P: AsRef<Path>,
     {
-        let file = std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .read(true)
-            .open(path)?;
-        serde_json::to_writer_pretty(file, self)?;
-        Ok(())
+        save_json_atomically(path, self)
+    }
+
+    pub fn clear<P>(path: P)
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if path.exists() {
+            if let Err(e) = std::fs::remove_file(path) {
+                log::error!("Failed to reset address verification state: {:?}", e);
+            }
+        }
+    }
+}
+
+/// ETH address verification lifecycle
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressVerificationStatus {
+    /// Verification is not required or not started yet
+    NotStarted = 0,
+    /// Waiting for the min balance on the relay ETH address
+    WaitingForBalance = 1,
+    /// Transaction was sent
+    Submitted = 2,
+    /// Transaction was included in a block, but its receipt is not available yet
+    Mined = 3,
+    /// Transaction succeeded, waiting for the confirmation in UserData
+    Succeeded = 4,
+    /// Transaction reverted. It is re-submitted only after UserData is re-checked
+    Reverted = 5,
+    /// Transaction disappeared from the network for several polls. It will be re-submitted
+    Dropped = 6,
+    /// ETH address is confirmed in UserData
+    Confirmed = 7,
+}
+
+impl AddressVerificationStatus {
+    /// Whether the transaction was sent, but not executed yet
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Self::Submitted | Self::Mined)
+    }
+
+    /// Whether the transaction was successfully executed
+    pub fn is_succeeded(&self) -> bool {
+        matches!(self, Self::Succeeded | Self::Confirmed)
     }
 }
+
+impl Default for AddressVerificationStatus {
+    fn default() -> Self {
+        Self::NotStarted
+    }
+}
+
+fn default_transaction_status() -> AddressVerificationStatus {
+    AddressVerificationStatus::Submitted
+}
+
+fn default_attempts() -> u32 {
+    1
+}
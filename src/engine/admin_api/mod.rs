@@ -0,0 +1,228 @@
+use std::convert::Infallible;
+use std::sync::{Arc, Weak};
+
+use anyhow::{Context, Result};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde::Serialize;
+
+use super::staking::*;
+use super::Engine;
+use crate::config::*;
+
+/// Starts local HTTP server with relay management endpoints
+pub fn serve(config: &AdminApiConfig, engine: Weak<Engine>) -> Result<()> {
+    let token: Option<Arc<str>> = config.token.as_deref().map(Arc::from);
+    if token.is_none() && !config.listen_address.ip().is_loopback() {
+        return Err(AdminApiError::TokenRequired(config.listen_address).into());
+    }
+
+    let server = hyper::Server::try_bind(&config.listen_address)
+        .context("Failed to bind admin API server port")?;
+
+    let make_service = hyper::service::make_service_fn(move |_| {
+        let engine = engine.clone();
+        let token = token.clone();
+        async move {
+            Ok::<_, Infallible>(hyper::service::service_fn(move |req| {
+                let response = if is_authorized(&req, token.as_deref()) {
+                    handle_request(&engine, &req)
+                } else {
+                    text_response(StatusCode::UNAUTHORIZED, "Unauthorized")
+                };
+                futures::future::ready(Ok::<_, Infallible>(response))
+            }))
+        }
+    });
+
+    log::info!("Admin API started on {}", config.listen_address);
+
+    tokio::spawn(async move {
+        if let Err(e) = server.serve(make_service).await {
+            log::error!("Admin API stopped: {:?}", e);
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_request(engine: &Weak<Engine>, req: &Request<Body>) -> Response<Body> {
+    let engine = match engine.upgrade() {
+        Some(engine) => engine,
+        None => return text_response(StatusCode::SERVICE_UNAVAILABLE, "Relay is stopped"),
+    };
+
+    // NOTE: verification progress is available before the staking is initialized
+    if (req.method(), req.uri().path()) == (&Method::GET, "/staking/verification") {
+        let staker = match query_param(req, "staker") {
+            Some(staker) => engine
+                .context
+                .stakers
+                .iter()
+                .find(|item| item.staker_account_str == staker),
+            None => Some(engine.context.primary_staker()),
+        };
+        return match staker {
+            Some(staker) => json_response(&staker.address_verification.progress()),
+            None => text_response(StatusCode::NOT_FOUND, "Staker not found"),
+        };
+    }
+
+    let stakings = engine.staking.lock().clone();
+    if stakings.is_empty() {
+        return text_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Staking is not initialized yet",
+        );
+    }
+
+    // Use the first staker by default
+    let staking = match query_param(req, "staker") {
+        Some(staker) => stakings
+            .into_iter()
+            .find(|staking| staking.staker().staker_account_str == staker),
+        None => stakings.into_iter().next(),
+    };
+    let staking = match staking {
+        Some(staking) => staking,
+        None => return text_response(StatusCode::NOT_FOUND, "Staker not found"),
+    };
+
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/staking/elections") => json_response(&staking.election_policy_state()),
+        (&Method::POST, "/staking/elections/skip") => {
+            staking.set_skip_next_election(true);
+            json_response(&staking.election_policy_state())
+        }
+        (&Method::DELETE, "/staking/elections/skip") => {
+            staking.set_skip_next_election(false);
+            json_response(&staking.election_policy_state())
+        }
+        (&Method::GET, "/staking/forecast") => match parse_forecast_rounds(req) {
+            Some(count) => json_response(&staking.forecast(count)),
+            None => text_response(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid rounds count. Max: {}", MAX_FORECAST_ROUNDS),
+            ),
+        },
+        _ => text_response(StatusCode::NOT_FOUND, "Not found"),
+    }
+}
+
+/// Parses optional `rounds` query parameter
+fn parse_forecast_rounds(req: &Request<Body>) -> Option<usize> {
+    match query_param(req, "rounds") {
+        Some(rounds) => rounds
+            .parse()
+            .ok()
+            .filter(|count| (1..=MAX_FORECAST_ROUNDS).contains(count)),
+        None => Some(DEFAULT_FORECAST_ROUNDS),
+    }
+}
+
+/// Checks the bearer token if it is required
+fn is_authorized(req: &Request<Body>, token: Option<&str>) -> bool {
+    let token = match token {
+        Some(token) => token,
+        None => return true,
+    };
+
+    req.headers()
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|value| constant_time_eq(value.as_bytes(), token.as_bytes()))
+        .unwrap_or_default()
+}
+
+/// Compares values without early exit to not leak the token through timings
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Returns the URL-decoded query parameter
+fn query_param(req: &Request<Body>, name: &str) -> Option<String> {
+    url::form_urlencoded::parse(req.uri().query()?.as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn json_response<T>(data: &T) -> Response<Body>
+where
+    T: Serialize,
+{
+    match serde_json::to_string(data) {
+        Ok(data) => {
+            let mut response = Response::new(Body::from(data));
+            response.headers_mut().insert(
+                hyper::header::CONTENT_TYPE,
+                hyper::header::HeaderValue::from_static("application/json"),
+            );
+            response
+        }
+        Err(e) => text_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+fn text_response<T>(status: StatusCode, text: T) -> Response<Body>
+where
+    T: Into<Body>,
+{
+    let mut response = Response::new(text.into());
+    *response.status_mut() = status;
+    response
+}
+
+#[derive(thiserror::Error, Debug)]
+enum AdminApiError {
+    #[error("Admin API token is required for the non-loopback listen address {0}")]
+    TokenRequired(std::net::SocketAddr),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_request(uri: &str, authorization: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(authorization) = authorization {
+            builder = builder.header(hyper::header::AUTHORIZATION, authorization);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn query_params_are_decoded() {
+        let req = make_request("/staking/forecast?staker=0%3Aabcd&rounds=2&name=a+b", None);
+        assert_eq!(query_param(&req, "staker").as_deref(), Some("0:abcd"));
+        assert_eq!(query_param(&req, "rounds").as_deref(), Some("2"));
+        assert_eq!(query_param(&req, "name").as_deref(), Some("a b"));
+        assert_eq!(query_param(&req, "other"), None);
+
+        let req = make_request("/staking/forecast", None);
+        assert_eq!(query_param(&req, "staker"), None);
+    }
+
+    #[test]
+    fn token_is_checked() {
+        let req = make_request("/staking/elections", None);
+        assert!(is_authorized(&req, None));
+        assert!(!is_authorized(&req, Some("secret")));
+
+        let req = make_request("/staking/elections", Some("Bearer secret"));
+        assert!(is_authorized(&req, Some("secret")));
+        assert!(!is_authorized(&req, Some("secret2")));
+        assert!(!is_authorized(&req, Some("secre")));
+
+        let req = make_request("/staking/elections", Some("secret"));
+        assert!(!is_authorized(&req, Some("secret")));
+    }
+
+    #[test]
+    fn public_address_requires_token() {
+        let config = AdminApiConfig {
+            listen_address: "0.0.0.0:10001".parse().unwrap(),
+            token: None,
+        };
+        assert!(serve(&config, Weak::new()).is_err());
+    }
+}
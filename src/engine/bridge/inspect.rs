@@ -0,0 +1,216 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use eth_ton_abi_converter::*;
+use serde::Serialize;
+use ton_types::UInt256;
+
+use super::{make_ton_event_vote_data, BridgeError, EventAction};
+use crate::engine::eth_subscriber::*;
+use crate::engine::ton_contracts::*;
+use crate::utils::*;
+
+/// Relay decision for the single event contract
+#[derive(Debug, Clone, Serialize)]
+pub struct EventInspection {
+    /// Event contract address
+    pub event: String,
+    /// Event type (`ETH` or `TON`)
+    pub event_type: String,
+    /// Event contract status
+    pub status: String,
+    /// Event configuration address
+    pub configuration: String,
+    /// Event round number
+    pub round_number: u32,
+    /// What the relay would vote
+    pub vote: InspectedVote,
+    /// Explanation of the vote
+    pub reason: String,
+    /// ETH ABI encoded data which would be signed. Only for TON events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signed_data: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InspectedVote {
+    Confirm,
+    Reject,
+    Skip,
+}
+
+/// Runs the same checks as `update_eth_event`/`update_ton_event` for the event
+/// from the specified state, but doesn't send anything.
+///
+/// `public_key` is our relay TON public key
+pub async fn inspect_event_contract(
+    public_key: &UInt256,
+    eth_subscribers: &EthSubscriberRegistry,
+    shard_accounts: &ShardAccountsMap,
+    account: UInt256,
+) -> Result<EventInspection> {
+    let contract = shard_accounts
+        .find_account(&account)?
+        .ok_or(BridgeError::EventNotFound)?;
+    let base_event_contract = EventBaseContract(&contract);
+
+    let status = base_event_contract
+        .status()
+        .context("Failed to get event status")?;
+    let round_number = base_event_contract
+        .round_number()
+        .context("Failed to get event round number")?;
+
+    // Determine event type by its init data
+    let (event_type, configuration) = match EthEventContract(&contract).event_init_data() {
+        Ok(init_data) => (EventType::Eth, init_data.configuration),
+        Err(_) => {
+            let init_data = TonEventContract(&contract)
+                .event_init_data()
+                .context("Failed to get event init data")?;
+            (EventType::Ton, init_data.configuration)
+        }
+    };
+
+    let mut inspection = EventInspection {
+        event: format!("0:{:x}", account),
+        event_type: event_type.to_string(),
+        status: format!("{:?}", status),
+        configuration: format!("0:{:x}", configuration),
+        round_number,
+        vote: InspectedVote::Skip,
+        reason: String::new(),
+        signed_data: None,
+    };
+
+    // Check whether our vote is required
+    match base_event_contract.process(public_key, event_type == EventType::Ton)? {
+        EventAction::Nop => {
+            inspection.reason = "Event is still initializing".to_owned();
+            return Ok(inspection);
+        }
+        EventAction::Remove => {
+            inspection.reason =
+                "Event doesn't require our vote (already voted, not in the round or unsupported API version)"
+                    .to_owned();
+            return Ok(inspection);
+        }
+        EventAction::Vote => { /* continue */ }
+    }
+
+    // Find event configuration
+    let configuration_contract = match shard_accounts.find_account(&configuration)? {
+        Some(contract) => contract,
+        None => {
+            inspection.reason = "Event configuration not found".to_owned();
+            return Ok(inspection);
+        }
+    };
+
+    match event_type {
+        EventType::Eth => {
+            inspect_eth_event(
+                eth_subscribers,
+                &contract,
+                &configuration_contract,
+                &mut inspection,
+            )
+            .await
+        }
+        EventType::Ton => inspect_ton_event(
+            &contract,
+            &configuration_contract,
+            account,
+            round_number,
+            &mut inspection,
+        ),
+    }?;
+
+    Ok(inspection)
+}
+
+async fn inspect_eth_event(
+    eth_subscribers: &EthSubscriberRegistry,
+    contract: &ExistingContract,
+    configuration_contract: &ExistingContract,
+    inspection: &mut EventInspection,
+) -> Result<()> {
+    let event_init_data = EthEventContract(contract).event_init_data()?;
+
+    let details = EthEventConfigurationContract(configuration_contract)
+        .get_details()
+        .context("Failed to get ETH event configuration details")?;
+    let event_abi = Arc::new(EthEventAbi::new(&details.basic_configuration.event_abi)?);
+
+    let chain_id = details.network_configuration.chain_id;
+    let eth_subscriber = match eth_subscribers.get_subscriber(chain_id) {
+        Some(subscriber) => subscriber,
+        None => {
+            inspection.reason = format!("ETH subscriber with chain id {} was not found", chain_id);
+            return Ok(());
+        }
+    };
+
+    match eth_subscriber
+        .verify_now(
+            event_init_data.vote_data,
+            details.network_configuration.event_emitter,
+            event_abi,
+            details.network_configuration.event_blocks_to_confirm,
+        )
+        .await
+    {
+        Ok(VerificationStatus::Exists) => {
+            inspection.vote = InspectedVote::Confirm;
+            inspection.reason = format!("Event was found in EVM-{} network", chain_id);
+        }
+        Ok(VerificationStatus::NotExists) => {
+            inspection.vote = InspectedVote::Reject;
+            inspection.reason = format!(
+                "Event was not found in EVM-{} network or its data mismatched",
+                chain_id
+            );
+        }
+        Err(e) => {
+            inspection.reason = format!("Failed to verify ETH event: {:?}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn inspect_ton_event(
+    contract: &ExistingContract,
+    configuration_contract: &ExistingContract,
+    account: UInt256,
+    round_number: u32,
+    inspection: &mut EventInspection,
+) -> Result<()> {
+    let event_init_data = TonEventContract(contract).event_init_data()?;
+
+    let details = TonEventConfigurationContract(configuration_contract)
+        .get_details()
+        .context("Failed to get TON event configuration details")?;
+    let event_abi = decode_ton_event_abi(&details.basic_configuration.event_abi)?;
+
+    match make_ton_event_vote_data(
+        &event_abi,
+        details.network_configuration.proxy,
+        &event_init_data,
+        account,
+        round_number,
+    ) {
+        Ok(data) => {
+            inspection.vote = InspectedVote::Confirm;
+            inspection.reason = "Event data is valid".to_owned();
+            inspection.signed_data = Some(hex::encode(&data));
+        }
+        Err(e) => {
+            inspection.vote = InspectedVote::Reject;
+            inspection.reason = format!("Failed to compute vote data: {:?}", e);
+        }
+    }
+
+    Ok(())
+}
@@ -7,6 +7,7 @@ use std::time::Duration;
 use anyhow::{Context, Result};
 use eth_ton_abi_converter::*;
 use nekoton_abi::*;
+use rand::Rng;
 use tiny_adnl::utils::*;
 use tokio::sync::mpsc;
 use tokio::sync::RwLock;
@@ -15,11 +16,18 @@ use ton_types::UInt256;
 
 use crate::engine::eth_subscriber::*;
 use crate::engine::keystore::*;
+use crate::engine::staker::*;
 use crate::engine::ton_contracts::*;
 use crate::engine::ton_subscriber::*;
-use crate::engine::EngineContext;
+use crate::engine::{EngineContext, MessageKind};
 use crate::utils::*;
 
+pub use self::inspect::*;
+use self::scheduler::*;
+
+mod inspect;
+mod scheduler;
+
 /// Events part of relays logic
 pub struct Bridge {
     /// Shared engine context
@@ -38,25 +46,36 @@ pub struct Bridge {
     // Observers for pending TON events
     ton_events_state: Arc<EventsState<TonEvent>>,
 
+    /// Limits the number of simultaneous event updates
+    events_scheduler: Arc<EventsScheduler>,
+
     connectors_tx: AccountEventsTx<ConnectorEvent>,
     eth_event_configurations_tx: AccountEventsTx<EthEventConfigurationEvent>,
     ton_event_configurations_tx: AccountEventsTx<TonEventConfigurationEvent>,
 
     total_active_eth_event_configurations: AtomicUsize,
     total_active_ton_event_configurations: AtomicUsize,
+
+    /// Number of ETH event votes skipped because the quorum was already reached
+    skipped_eth_event_votes: AtomicUsize,
 }
 
 impl Bridge {
     pub async fn new(context: Arc<EngineContext>, bridge_account: UInt256) -> Result<Arc<Self>> {
         // Create bridge
-        let (bridge_events_tx, bridge_events_rx) = mpsc::unbounded_channel();
-        let (connectors_tx, connectors_rx) = mpsc::unbounded_channel();
-        let (eth_event_configurations_tx, eth_event_configurations_rx) = mpsc::unbounded_channel();
-        let (ton_event_configurations_tx, ton_event_configurations_rx) = mpsc::unbounded_channel();
-        let (eth_events_tx, eth_events_rx) = mpsc::unbounded_channel();
-        let (ton_events_tx, ton_events_rx) = mpsc::unbounded_channel();
+        let queues = &context.events_queues;
+        let (bridge_events_tx, bridge_events_rx) = queues.channel("BridgeContract");
+        let (connectors_tx, connectors_rx) = queues.channel("ConnectorContract");
+        let (eth_event_configurations_tx, eth_event_configurations_rx) =
+            queues.channel("EthEventConfigurationContract");
+        let (ton_event_configurations_tx, ton_event_configurations_rx) =
+            queues.channel("TonEventConfigurationContract");
+        let (eth_events_tx, eth_events_rx) = queues.channel("EthEventContract");
+        let (ton_events_tx, ton_events_rx) = queues.channel("TonEventContract");
 
         let bridge_observer = AccountObserver::new(&bridge_events_tx);
+        let events_scheduler =
+            EventsScheduler::new(context.settings.event_voting.max_concurrent_tasks);
 
         let bridge = Arc::new(Bridge {
             context,
@@ -65,11 +84,13 @@ impl Bridge {
             state: Default::default(),
             eth_events_state: EventsState::new(eth_events_tx),
             ton_events_state: EventsState::new(ton_events_tx),
+            events_scheduler,
             connectors_tx,
             eth_event_configurations_tx,
             ton_event_configurations_tx,
             total_active_eth_event_configurations: Default::default(),
             total_active_ton_event_configurations: Default::default(),
+            skipped_eth_event_votes: Default::default(),
         });
 
         // Prepare listeners
@@ -117,7 +138,7 @@ impl Bridge {
         // Subscribe bridge account to transactions
         bridge
             .context
-            .ton_subscriber
+            .ton_chain
             .add_transactions_subscription([bridge.bridge_account], &bridge.bridge_observer);
 
         // Initialize
@@ -131,6 +152,7 @@ impl Bridge {
 
     pub fn metrics(&self) -> BridgeMetrics {
         BridgeMetrics {
+            scheduler: self.events_scheduler.metrics(),
             pending_eth_event_count: self.eth_events_state.count.load(Ordering::Acquire),
             pending_ton_event_count: self.ton_events_state.count.load(Ordering::Acquire),
             total_active_eth_event_configurations: self
@@ -139,6 +161,7 @@ impl Bridge {
             total_active_ton_event_configurations: self
                 .total_active_ton_event_configurations
                 .load(Ordering::Acquire),
+            skipped_eth_event_votes: self.skipped_eth_event_votes.load(Ordering::Acquire),
         }
     }
 
@@ -158,7 +181,7 @@ impl Bridge {
 
                         // Subscribe observer to transactions
                         self.context
-                            .ton_subscriber
+                            .ton_chain
                             .add_transactions_subscription([event.connector], entry);
                     }
                     hash_map::Entry::Occupied(_) => {
@@ -197,11 +220,15 @@ impl Bridge {
     ) -> Result<()> {
         match event {
             // Create observer on each deployment event
-            EthEventConfigurationEvent::EventDeployed { address } => {
-                if self.add_pending_event(address, &self.eth_events_state) {
+            EthEventConfigurationEvent::EventDeployed {
+                address,
+                transaction_lt,
+            } => {
+                if self.add_pending_event(address, transaction_lt, &self.eth_events_state) {
                     let this = self.clone();
                     self.spawn_background_task("preprocess ETH event", async move {
-                        this.preprocess_event(address, &this.eth_events_state).await
+                        this.preprocess_event(address, transaction_lt, &this.eth_events_state)
+                            .await
                     });
                 }
             }
@@ -224,11 +251,15 @@ impl Bridge {
     ) -> Result<()> {
         match event {
             // Create observer on each deployment event
-            TonEventConfigurationEvent::EventDeployed { address, .. } => {
-                if self.add_pending_event(address, &self.ton_events_state) {
+            TonEventConfigurationEvent::EventDeployed {
+                address,
+                transaction_lt,
+            } => {
+                if self.add_pending_event(address, transaction_lt, &self.ton_events_state) {
                     let this = self.clone();
                     self.spawn_background_task("preprocess TON event", async move {
-                        this.preprocess_event(address, &this.ton_events_state).await
+                        this.preprocess_event(address, transaction_lt, &this.ton_events_state)
+                            .await
                     });
                 } else {
                     // NOTE: Each TON event must be unique on the contracts level,
@@ -256,13 +287,21 @@ impl Bridge {
     ) -> Result<()> {
         use dashmap::mapref::entry::Entry;
 
-        let our_public_key = self.context.keystore.ton.public_key();
-
         // Use flag to update counter outside events map lock to reduce its duration
         let mut event_removed = false;
 
         // Handle only known ETH events
         if let Entry::Occupied(entry) = self.eth_events_state.pending.entry(account) {
+            // Remember our votes. The event is removed when all our voting stakers have voted
+            let all_voted = match &event {
+                (EthEvent::Confirm { public_key } | EthEvent::Reject { public_key }, _)
+                    if self.is_our_key(public_key) =>
+                {
+                    entry.get().mark_voted(*public_key)
+                }
+                _ => false,
+            };
+
             let remove_entry = || {
                 // Remove pending event
                 entry.remove();
@@ -274,21 +313,19 @@ impl Bridge {
                 (_, EventStatus::Confirmed | EventStatus::Rejected) => remove_entry(),
                 // Handle event initialization
                 (EthEvent::ReceiveRoundRelays { keys }, _) => {
-                    // Check if event contains our key
-                    if keys.contains(our_public_key) {
+                    // Check if event contains any of our keys
+                    if self.contains_our_key(&keys) {
                         // Start voting
                         self.spawn_background_task(
                             "update ETH event",
-                            self.clone().update_eth_event(account),
+                            self.clone().schedule_event_update::<EthEvent>(account),
                         );
                     } else {
                         remove_entry();
                     }
                 }
                 // Handle our confirmation or rejection
-                (EthEvent::Confirm { public_key } | EthEvent::Reject { public_key }, _)
-                    if public_key == our_public_key =>
-                {
+                (EthEvent::Confirm { .. } | EthEvent::Reject { .. }, _) if all_voted => {
                     remove_entry()
                 }
                 _ => { /* Ignore other events */ }
@@ -309,13 +346,21 @@ impl Bridge {
     ) -> Result<()> {
         use dashmap::mapref::entry::Entry;
 
-        let our_public_key = self.context.keystore.ton.public_key();
-
         // Use flag to update counter outside events map lock to reduce its duration
         let mut event_removed = false;
 
         // Handle only known TON events
         if let Entry::Occupied(entry) = self.ton_events_state.pending.entry(account) {
+            // Remember our votes. The event is removed when all our voting stakers have voted
+            let all_voted = match &event {
+                (TonEvent::Confirm { public_key } | TonEvent::Reject { public_key }, _)
+                    if self.is_our_key(public_key) =>
+                {
+                    entry.get().mark_voted(*public_key)
+                }
+                _ => false,
+            };
+
             let remove_entry = || {
                 // Remove pending event
                 entry.remove();
@@ -336,21 +381,19 @@ impl Bridge {
                 (_, EventStatus::Rejected) => remove_entry(),
                 // Handle event initialization
                 (TonEvent::ReceiveRoundRelays { keys }, _) => {
-                    // Check if event contains our key
-                    if keys.contains(our_public_key) {
+                    // Check if event contains any of our keys
+                    if self.contains_our_key(&keys) {
                         // Start voting
                         self.spawn_background_task(
                             "update TON event",
-                            self.clone().update_ton_event(account),
+                            self.clone().schedule_event_update::<TonEvent>(account),
                         );
                     } else {
                         remove_entry();
                     }
                 }
                 // Handle our confirmation or rejection
-                (TonEvent::Confirm { public_key } | TonEvent::Reject { public_key }, _)
-                    if public_key == our_public_key =>
-                {
+                (TonEvent::Confirm { .. } | TonEvent::Reject { .. }, _) if all_voted => {
                     remove_entry();
                 }
                 _ => { /* Ignore other events */ }
@@ -366,21 +409,34 @@ impl Bridge {
     }
 
     /// Check deployed event contract in parallel with transactions processing
+    ///
+    /// NOTE: the event is deployed by the configuration transaction with `deployed_lt`,
+    /// so only states which include it are used
     async fn preprocess_event<T: EventExt>(
         self: &Arc<Bridge>,
         account: UInt256,
+        deployed_lt: u64,
         state: &EventsState<T>,
     ) -> Result<()> {
-        // Wait contract state
-        let ton_subscriber = &self.context.ton_subscriber;
-        let contract = ton_subscriber.wait_contract_state(account).await?;
+        let mut states = self
+            .context
+            .ton_chain
+            .subscribe_account_state_with_history(account, EVENT_STATE_HISTORY_DEPTH);
+
+        // Wait contract state with its block context
+        let (update, contract) = states.wait_active_after(deployed_lt).await?;
+        log::debug!(
+            "Preprocessing event {:x} with state from block {}",
+            account,
+            update.block_id
+        );
         let base_event_contract = EventBaseContract(&contract);
 
         // Check further steps based on event statuses
-        match base_event_contract.process(
-            self.context.keystore.ton.public_key(),
-            T::REQUIRE_ALL_SIGNATURES,
-        )? {
+        match self
+            .find_event_voters(&base_event_contract, T::REQUIRE_ALL_SIGNATURES)?
+            .0
+        {
             // Event was not activated yet, so it will be processed in
             // event transactions subscription
             EventAction::Nop => Ok(()),
@@ -393,30 +449,56 @@ impl Bridge {
             // Start processing event.
             // NOTE: it is ok to update_ton_event twice because in fact it will
             // do anything only once
-            EventAction::Vote => T::update_event(self.clone(), account).await,
+            EventAction::Vote => self.clone().schedule_event_update::<T>(account).await,
         }
     }
 
+    /// Waits for a free slot in the scheduler and updates the event
+    async fn schedule_event_update<T: EventExt>(self: Arc<Self>, account: UInt256) -> Result<()> {
+        let deployed_lt = match T::events_state(&self).pending.get(&account) {
+            Some(entry) => entry.deployed_lt,
+            None => return Ok(()),
+        };
+
+        // Compute priority using the latest known event state.
+        // NOTE: it is only a hint, the state is loaded again after the slot is acquired
+        let priority = match self.context.ton_chain.last_known_contract_state(&account) {
+            Ok(Some(contract)) => match EventBaseContract(&contract).confirmations_left() {
+                Ok(confirmations_left) => EventPriority {
+                    confirmations_left,
+                    deployed_lt,
+                },
+                Err(_) => EventPriority::LOWEST,
+            },
+            _ => EventPriority::LOWEST,
+        };
+
+        // NOTE: permit is held until the votes are delivered
+        let _permit = self.events_scheduler.acquire(priority).await;
+        T::update_event(self, account).await
+    }
+
     async fn update_eth_event(self: Arc<Self>, account: UInt256) -> Result<()> {
         if !self.eth_events_state.start_processing(&account) {
             return Ok(());
         }
 
-        let keystore = &self.context.keystore;
-        let ton_subscriber = &self.context.ton_subscriber;
+        let ton_chain = &self.context.ton_chain;
         let eth_subscribers = &self.context.eth_subscribers;
 
         // Wait contract state
-        let contract = ton_subscriber.wait_contract_state(account).await?;
+        let contract = ton_chain.wait_contract_state(account).await?;
 
-        match EventBaseContract(&contract).process(keystore.ton.public_key(), false)? {
-            EventAction::Nop => return Ok(()),
-            EventAction::Remove => {
+        let voters = match self.find_event_voters(&EventBaseContract(&contract), false)? {
+            (EventAction::Nop, _) => return Ok(()),
+            (EventAction::Remove, _) => {
                 self.eth_events_state.remove(&account);
                 return Ok(());
             }
-            EventAction::Vote => { /* continue voting */ }
-        }
+            (EventAction::Vote, voters) => voters,
+        };
+        self.eth_events_state
+            .set_voter_count(&account, voters.len());
 
         let event_init_data = EthEventContract(&contract).event_init_data()?;
 
@@ -497,48 +579,103 @@ impl Bridge {
             }
         };
 
-        // Clone events observer and deliver message to the contract
+        // Spread votes of different relays so that late ones can see the quorum
+        let vote_jitter_ms = self.context.settings.event_voting.vote_jitter_ms;
+        if vote_jitter_ms > 0 {
+            let delay = rand::thread_rng().gen_range(0..=vote_jitter_ms);
+            self.context.clock.sleep(Duration::from_millis(delay)).await;
+        }
+
+        // Skip voting if the event doesn't need our vote anymore
+        if !self.is_eth_event_vote_needed(account).await? {
+            log::info!("ETH event {:x} already reached quorum. Skipping", account);
+            self.skipped_eth_event_votes.fetch_add(1, Ordering::Release);
+            self.eth_events_state.remove(&account);
+            return Ok(());
+        }
+
+        // Clone events observer and deliver message to the contract from each staker
         let eth_event_observer = match self.eth_events_state.pending.get(&account) {
             Some(entry) => entry.observer.clone(),
             None => return Ok(()),
         };
-        let eth_events_state = Arc::downgrade(&self.eth_events_state);
 
-        self.context
-            .deliver_message(
-                eth_event_observer,
-                message,
-                // Stop voting for the contract if it was removed
+        let votes = voters.iter().map(|staker| {
+            let public_key = *staker.keystore.current().ton.public_key();
+            let eth_events_state = Arc::downgrade(&self.eth_events_state);
+
+            self.context.deliver_message(
+                staker,
+                eth_event_observer.clone(),
+                MessageKind::EventVote,
+                message.clone(),
+                // Stop voting for the contract if it was removed or we have already voted
                 move || match eth_events_state.upgrade() {
-                    Some(state) => state.pending.contains_key(&account),
+                    Some(state) => state.is_vote_pending(&account, &public_key),
                     None => false,
                 },
             )
-            .await?;
+        });
+        futures::future::join_all(votes)
+            .await
+            .into_iter()
+            .collect::<Result<()>>()?;
+
+        self.eth_events_state.remove(&account);
         Ok(())
     }
 
+    /// Checks the latest ETH event state right before sending the vote.
+    ///
+    /// NOTE: TON events are not checked because they must collect all signatures
+    async fn is_eth_event_vote_needed(&self, account: UInt256) -> Result<bool> {
+        let settings = &self.context.settings.event_voting;
+
+        if !settings.skip_votes_after_quorum {
+            return Ok(true);
+        }
+
+        // NOTE: event contract existed before, so its absence is not a quorum
+        let contract = self
+            .context
+            .ton_chain
+            .get_contract_state(account)
+            .await?
+            .ok_or(BridgeError::EventNotFound)?;
+        let base_event_contract = EventBaseContract(&contract);
+
+        if !matches!(
+            self.find_event_voters(&base_event_contract, false)?.0,
+            EventAction::Vote
+        ) {
+            return Ok(false);
+        }
+
+        Ok(!base_event_contract.quorum_reached()?)
+    }
+
     async fn update_ton_event(self: Arc<Self>, account: UInt256) -> Result<()> {
         if !self.ton_events_state.start_processing(&account) {
             return Ok(());
         }
 
-        let keystore = &self.context.keystore;
-        let ton_subscriber = &self.context.ton_subscriber;
+        let ton_chain = &self.context.ton_chain;
 
         // Wait contract state
-        let contract = ton_subscriber.wait_contract_state(account).await?;
+        let contract = ton_chain.wait_contract_state(account).await?;
         let base_event_contract = EventBaseContract(&contract);
 
         // Check further steps based on event statuses
-        match base_event_contract.process(keystore.ton.public_key(), true)? {
-            EventAction::Nop => return Ok(()),
-            EventAction::Remove => {
+        let voters = match self.find_event_voters(&base_event_contract, true)? {
+            (EventAction::Nop, _) => return Ok(()),
+            (EventAction::Remove, _) => {
                 self.ton_events_state.remove(&account);
                 return Ok(());
             }
-            EventAction::Vote => { /* continue voting */ }
-        }
+            (EventAction::Vote, voters) => voters,
+        };
+        self.ton_events_state
+            .set_voter_count(&account, voters.len());
         let round_number = base_event_contract.round_number()?;
 
         // Get event details
@@ -553,31 +690,19 @@ impl Bridge {
                 .ton_event_configurations
                 .get(&event_init_data.configuration)
                 .map(|configuration| {
-                    (
+                    make_ton_event_vote_data(
+                        &configuration.event_abi,
                         configuration.details.network_configuration.proxy,
-                        ton_abi::TokenValue::decode_params(
-                            &configuration.event_abi,
-                            event_init_data.vote_data.event_data.clone().into(),
-                            &ton_abi::contract::ABI_VERSION_2_1,
-                            false,
-                        ),
+                        &event_init_data,
+                        account,
+                        round_number,
                     )
                 })
         };
 
         let decoded_data = match data {
             // Decode event data with event abi from configuration
-            Some((proxy, data)) => data.and_then(|data| {
-                Ok(make_mapped_ton_event(
-                    event_init_data.vote_data.event_transaction_lt,
-                    event_init_data.vote_data.event_timestamp,
-                    map_ton_tokens_to_eth_bytes(data)?,
-                    event_init_data.configuration,
-                    account,
-                    proxy,
-                    round_number,
-                ))
-            }),
+            Some(data) => data,
             // Do nothing when configuration was not found
             None => {
                 log::error!(
@@ -592,13 +717,13 @@ impl Bridge {
 
         let account_addr = ton_block::MsgAddrStd::with_address(None, 0, account.into());
 
-        let message = match decoded_data {
+        let make_message = |staker: &StakerContext| match &decoded_data {
             // Confirm with signature
             Ok(data) => {
-                log::info!("Signing event data: {}", hex::encode(&data));
+                log::info!("Signing event data: {}", hex::encode(data));
                 UnsignedMessage::new(ton_event_contract::confirm(), account)
-                    .arg(keystore.eth.sign(&data).to_vec())
-                    .arg(account_addr)
+                    .arg(staker.keystore.current().eth.sign(data).to_vec())
+                    .arg(account_addr.clone())
             }
 
             // Reject if event data is invalid
@@ -608,40 +733,49 @@ impl Bridge {
                     account,
                     e
                 );
-                UnsignedMessage::new(ton_event_contract::reject(), account).arg(account_addr)
+                UnsignedMessage::new(ton_event_contract::reject(), account)
+                    .arg(account_addr.clone())
             }
         };
 
-        // Clone events observer and deliver message to the contract
+        // Clone events observer and deliver message to the contract from each staker
         let ton_event_observer = match self.ton_events_state.pending.get(&account) {
             Some(entry) => entry.observer.clone(),
             None => return Ok(()),
         };
-        let ton_events_state = Arc::downgrade(&self.ton_events_state);
 
-        self.context
-            .deliver_message(
-                ton_event_observer,
-                message,
-                // Stop voting for the contract if it was removed
+        let votes = voters.iter().map(|staker| {
+            let public_key = *staker.keystore.current().ton.public_key();
+            let ton_events_state = Arc::downgrade(&self.ton_events_state);
+
+            self.context.deliver_message(
+                staker,
+                ton_event_observer.clone(),
+                MessageKind::EventVote,
+                make_message(staker),
+                // Stop voting for the contract if it was removed or we have already voted
                 move || match ton_events_state.upgrade() {
-                    Some(state) => state.pending.contains_key(&account),
+                    Some(state) => state.is_vote_pending(&account, &public_key),
                     None => false,
                 },
             )
-            .await?;
+        });
+        futures::future::join_all(votes)
+            .await
+            .into_iter()
+            .collect::<Result<()>>()?;
+
+        self.ton_events_state.remove(&account);
         Ok(())
     }
 
     async fn check_connector_contract(&self, connector_account: UInt256) -> Result<()> {
-        let ton_subscriber = &self.context.ton_subscriber;
+        let ton_chain = &self.context.ton_chain;
 
         // Get event configuration address
         let event_configuration = {
             // Wait until connector contract state is found
-            let contract = ton_subscriber
-                .wait_contract_state(connector_account)
-                .await?;
+            let contract = ton_chain.wait_contract_state(connector_account).await?;
 
             // Extract details
             let connector_details = ConnectorContract(&contract).get_details()?;
@@ -655,9 +789,7 @@ impl Bridge {
         };
 
         // Wait until event configuration state is found
-        let contract = ton_subscriber
-            .wait_contract_state(event_configuration)
-            .await?;
+        let contract = ton_chain.wait_contract_state(event_configuration).await?;
         log::info!("Got configuration contract");
 
         // Extract and process info from contract
@@ -677,9 +809,14 @@ impl Bridge {
         // will be queued in their handlers
         let mut state = self.state.write().await;
 
-        let shard_accounts = self.context.get_all_shard_accounts().await?;
+        // Bridge account is already subscribed, so the state must be newer
+        // than the last processed block to not miss new configurations
+        let shard_accounts = self
+            .context
+            .get_all_shard_accounts(Some(self.context.ton_chain.last_processed_mc_seqno()))
+            .await?;
 
-        let ton_subscriber = &self.context.ton_subscriber;
+        let ton_chain = &self.context.ton_chain;
 
         let contract = shard_accounts
             .find_account(&self.bridge_account)?
@@ -726,7 +863,7 @@ impl Bridge {
             state.connectors.insert(connector_account, observer.clone());
 
             // Subscribe connector for transaction
-            ton_subscriber.add_transactions_subscription([connector_account], &observer);
+            ton_chain.add_transactions_subscription([connector_account], &observer);
 
             // Skip event configuration if it is disabled
             if !enabled {
@@ -856,7 +993,7 @@ impl Bridge {
 
         // Subscribe to TON events
         self.context
-            .ton_subscriber
+            .ton_chain
             .add_transactions_subscription([*account], &observer);
 
         // Done
@@ -875,7 +1012,7 @@ impl Bridge {
             .context("Failed to get TON event configuration details")?;
 
         // Check if configuration is expired
-        let current_timestamp = self.context.ton_subscriber.current_utime();
+        let current_timestamp = self.context.ton_chain.current_utime();
         if details.is_expired(current_timestamp) {
             // Do nothing in that case
             log::warn!(
@@ -920,7 +1057,7 @@ impl Bridge {
 
         // Subscribe to TON events
         self.context
-            .ton_subscriber
+            .ton_chain
             .add_transactions_subscription([*account], &observer);
 
         // Done
@@ -937,8 +1074,6 @@ impl Bridge {
             unique_eth_event_configurations: Arc<AccountsSet>,
             unique_ton_event_configurations: Arc<AccountsSet>,
         ) -> Result<bool> {
-            let our_public_key = bridge.context.keystore.ton.public_key();
-
             accounts.iterate_with_keys(|hash, shard_account| {
                 // Prefetch only contract code hash
                 let code_hash = match read_code_hash(&mut shard_account.account_cell().into())? {
@@ -982,8 +1117,9 @@ impl Bridge {
                 }
 
                 // Process event
-                match EventBaseContract(&contract)
-                    .process(our_public_key, *event_type == EventType::Ton)
+                match bridge
+                    .find_event_voters(&EventBaseContract(&contract), *event_type == EventType::Ton)
+                    .map(|(action, _)| action)
                 {
                     Ok(EventAction::Nop | EventAction::Vote) => match event_type {
                         EventType::Eth => {
@@ -994,10 +1130,11 @@ impl Bridge {
                                 return Ok(true);
                             }
 
-                            if bridge.add_pending_event(hash, &bridge.eth_events_state) {
+                            // NOTE: events which were deployed before start are the oldest
+                            if bridge.add_pending_event(hash, 0, &bridge.eth_events_state) {
                                 bridge.spawn_background_task(
                                     "initial update ETH event",
-                                    bridge.clone().update_eth_event(hash),
+                                    bridge.clone().schedule_event_update::<EthEvent>(hash),
                                 );
                             }
                         }
@@ -1009,10 +1146,10 @@ impl Bridge {
                                 return Ok(true);
                             }
 
-                            if bridge.add_pending_event(hash, &bridge.ton_events_state) {
+                            if bridge.add_pending_event(hash, 0, &bridge.ton_events_state) {
                                 bridge.spawn_background_task(
                                     "initial update TON event",
-                                    bridge.clone().update_ton_event(hash),
+                                    bridge.clone().schedule_event_update::<TonEvent>(hash),
                                 );
                             }
                         }
@@ -1027,8 +1164,12 @@ impl Bridge {
             })
         }
 
-        // Wait all accounts
-        let shard_accounts = self.context.get_all_shard_accounts().await?;
+        // Wait all accounts. Events are already subscribed, so the state must be
+        // newer than the last processed block
+        let shard_accounts = self
+            .context
+            .get_all_shard_accounts(Some(self.context.ton_chain.last_processed_mc_seqno()))
+            .await?;
 
         let start = std::time::Instant::now();
 
@@ -1094,21 +1235,21 @@ impl Bridge {
 
     fn start_ton_event_configurations_gc(self: &Arc<Self>) {
         let bridge = Arc::downgrade(self);
+        let clock = self.context.clock.clone();
 
         tokio::spawn(async move {
             'outer: loop {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                clock.sleep(Duration::from_secs(10)).await;
 
                 // Get bridge if it is still alive
                 let bridge = match bridge.upgrade() {
                     Some(bridge) => bridge,
                     None => return,
                 };
-                let ton_subscriber = &bridge.context.ton_subscriber;
-                let ton_engine = &bridge.context.ton_engine;
+                let ton_chain = &bridge.context.ton_chain;
 
                 // Get current time from masterchain
-                let current_utime = ton_subscriber.current_utime();
+                let current_utime = ton_chain.current_utime();
 
                 // Check expired configurations
                 let has_expired_configurations = {
@@ -1122,10 +1263,10 @@ impl Bridge {
                 }
 
                 // Wait all shards
-                let current_utime = match ton_subscriber.wait_shards(None).await {
+                let current_utime = match ton_chain.wait_shards(None).await {
                     Ok(shards) => {
                         for (_, block_id) in shards.block_ids {
-                            if let Err(e) = ton_engine.wait_state(&block_id, None, false).await {
+                            if let Err(e) = ton_chain.wait_shard_accounts(&block_id).await {
                                 log::error!("Failed to wait shard state: {:?}", e);
                                 continue 'outer;
                             }
@@ -1158,8 +1299,49 @@ impl Bridge {
         });
     }
 
+    fn is_our_key(&self, public_key: &UInt256) -> bool {
+        self.context
+            .stakers
+            .iter()
+            .any(|staker| staker.keystore.current().ton.public_key() == public_key)
+    }
+
+    fn contains_our_key(&self, keys: &[UInt256]) -> bool {
+        keys.iter().any(|key| self.is_our_key(key))
+    }
+
+    /// Checks the event for each hosted staker. Returns stakers which must vote
+    fn find_event_voters(
+        &self,
+        contract: &EventBaseContract<'_>,
+        require_all_signatures: bool,
+    ) -> Result<(EventAction, Vec<Arc<StakerContext>>)> {
+        let mut voters = Vec::new();
+        for staker in &self.context.stakers {
+            match contract.process(
+                staker.keystore.current().ton.public_key(),
+                require_all_signatures,
+            )? {
+                // NOTE: event is still initializing, which doesn't depend on the key
+                EventAction::Nop => return Ok((EventAction::Nop, Vec::new())),
+                EventAction::Vote => voters.push(staker.clone()),
+                EventAction::Remove => { /* staker doesn't vote */ }
+            }
+        }
+
+        Ok(match voters.is_empty() {
+            true => (EventAction::Remove, voters),
+            false => (EventAction::Vote, voters),
+        })
+    }
+
     /// Creates ETH event observer if it doesn't exist and subscribes it to transactions
-    fn add_pending_event<T>(&self, account: UInt256, state: &EventsState<T>) -> bool
+    fn add_pending_event<T>(
+        &self,
+        account: UInt256,
+        deployed_lt: u64,
+        state: &EventsState<T>,
+    ) -> bool
     where
         T: std::fmt::Debug + ReadFromTransaction + 'static,
     {
@@ -1168,11 +1350,14 @@ impl Bridge {
         let new_event = if let Entry::Vacant(entry) = state.pending.entry(account) {
             let observer = AccountObserver::new(&state.events_tx);
             entry.insert(PendingEventState {
+                deployed_lt,
                 processing_started: AtomicBool::new(false),
+                voter_count: AtomicUsize::new(self.context.stakers.len()),
+                voted: Default::default(),
                 observer: observer.clone(),
             });
             self.context
-                .ton_subscriber
+                .ton_chain
                 .add_transactions_subscription([account], &observer);
             true
         } else {
@@ -1202,10 +1387,12 @@ impl Bridge {
 }
 
 pub struct BridgeMetrics {
+    pub scheduler: EventsSchedulerMetrics,
     pub pending_eth_event_count: usize,
     pub pending_ton_event_count: usize,
     pub total_active_eth_event_configurations: usize,
     pub total_active_ton_event_configurations: usize,
+    pub skipped_eth_event_votes: usize,
 }
 
 struct EventsState<T> {
@@ -1239,17 +1426,50 @@ where
             self.count.fetch_sub(1, Ordering::Release);
         }
     }
+
+    /// Remembers the number of our stakers which must vote for the event
+    fn set_voter_count(&self, account: &UInt256, voter_count: usize) {
+        if let Some(entry) = self.pending.get(account) {
+            entry.voter_count.store(voter_count, Ordering::Release);
+        }
+    }
+
+    /// Returns false if event was removed or the staker has already voted
+    fn is_vote_pending(&self, account: &UInt256, public_key: &UInt256) -> bool {
+        match self.pending.get(account) {
+            Some(entry) => !entry.voted.lock().contains(public_key),
+            None => false,
+        }
+    }
 }
 
 struct PendingEventState<T> {
+    /// Logical time of the event deployment. Zero if it was deployed before start
+    deployed_lt: u64,
     processing_started: AtomicBool,
+    /// Number of our stakers which are in the event round and must vote.
+    /// All hosted stakers until the event is checked
+    voter_count: AtomicUsize,
+    /// Public keys of our stakers which have already voted
+    voted: parking_lot::Mutex<FxHashSet<UInt256>>,
     observer: Arc<AccountObserver<(T, EventStatus)>>,
 }
 
+impl<T> PendingEventState<T> {
+    /// Remembers our vote. Returns true if all our voting stakers have voted
+    fn mark_voted(&self, public_key: UInt256) -> bool {
+        let mut voted = self.voted.lock();
+        voted.insert(public_key);
+        voted.len() >= self.voter_count.load(Ordering::Acquire)
+    }
+}
+
 #[async_trait::async_trait]
-trait EventExt {
+trait EventExt: Sized {
     const REQUIRE_ALL_SIGNATURES: bool;
 
+    fn events_state(bridge: &Bridge) -> &EventsState<Self>;
+
     async fn update_event(bridge: Arc<Bridge>, account: UInt256) -> Result<()>;
 }
 
@@ -1257,6 +1477,10 @@ trait EventExt {
 impl EventExt for EthEvent {
     const REQUIRE_ALL_SIGNATURES: bool = false;
 
+    fn events_state(bridge: &Bridge) -> &EventsState<Self> {
+        &bridge.eth_events_state
+    }
+
     async fn update_event(bridge: Arc<Bridge>, account: UInt256) -> Result<()> {
         bridge.update_eth_event(account).await
     }
@@ -1266,6 +1490,10 @@ impl EventExt for EthEvent {
 impl EventExt for TonEvent {
     const REQUIRE_ALL_SIGNATURES: bool = true;
 
+    fn events_state(bridge: &Bridge) -> &EventsState<Self> {
+        &bridge.ton_events_state
+    }
+
     async fn update_event(bridge: Arc<Bridge>, account: UInt256) -> Result<()> {
         bridge.update_ton_event(account).await
     }
@@ -1308,6 +1536,32 @@ impl BridgeState {
     }
 }
 
+/// Decodes TON event data with the configuration ABI and maps it into ETH ABI encoded bytes
+fn make_ton_event_vote_data(
+    event_abi: &[ton_abi::Param],
+    proxy: [u8; 20],
+    event_init_data: &TonEventInitData,
+    account: UInt256,
+    round_number: u32,
+) -> Result<Vec<u8>> {
+    let data = ton_abi::TokenValue::decode_params(
+        event_abi,
+        event_init_data.vote_data.event_data.clone().into(),
+        &ton_abi::contract::ABI_VERSION_2_1,
+        false,
+    )?;
+
+    Ok(make_mapped_ton_event(
+        event_init_data.vote_data.event_transaction_lt,
+        event_init_data.vote_data.event_timestamp,
+        map_ton_tokens_to_eth_bytes(data)?,
+        event_init_data.configuration,
+        account,
+        proxy,
+        round_number,
+    ))
+}
+
 fn add_event_code_hash(
     event_code_hashes: &mut EventCodeHashesMap,
     code: &ton_types::Cell,
@@ -1408,6 +1662,22 @@ impl EventBaseContract<'_> {
     }
 }
 
+impl EventBaseContract<'_> {
+    /// Number of confirmations required to reach the quorum
+    fn confirmations_left(&self) -> Result<u32> {
+        let required_votes = self.required_votes()?;
+        let confirms = self.get_voters(EventVote::Confirm)?.len() as u32;
+        Ok(required_votes.saturating_sub(confirms))
+    }
+
+    /// Whether there are enough confirmations or rejections
+    fn quorum_reached(&self) -> Result<bool> {
+        let required_votes = self.required_votes()? as usize;
+        Ok(self.get_voters(EventVote::Confirm)?.len() >= required_votes
+            || self.get_voters(EventVote::Reject)?.len() >= required_votes)
+    }
+}
+
 enum EventAction {
     /// Delay event processing
     Nop,
@@ -1522,8 +1792,14 @@ impl TxContext<'_> {
 
 #[derive(Debug, Clone)]
 enum TonEventConfigurationEvent {
-    EventDeployed { address: UInt256 },
-    SetEndTimestamp { end_timestamp: u32 },
+    EventDeployed {
+        address: UInt256,
+        /// Logical time of the configuration transaction
+        transaction_lt: u64,
+    },
+    SetEndTimestamp {
+        end_timestamp: u32,
+    },
 }
 
 impl ReadFromTransaction for TonEventConfigurationEvent {
@@ -1536,6 +1812,7 @@ impl ReadFromTransaction for TonEventConfigurationEvent {
         match nekoton_abi::read_function_id(&in_msg_body).ok()? {
             id if id == deploy_event.input_id => Some(Self::EventDeployed {
                 address: ctx.find_new_event_contract_address()?,
+                transaction_lt: ctx.transaction.logical_time(),
             }),
             id if id == set_end_timestamp.input_id => {
                 let end_timestamp = set_end_timestamp
@@ -1552,8 +1829,14 @@ impl ReadFromTransaction for TonEventConfigurationEvent {
 
 #[derive(Debug, Clone)]
 enum EthEventConfigurationEvent {
-    EventDeployed { address: UInt256 },
-    SetEndBlockNumber { end_block_number: u32 },
+    EventDeployed {
+        address: UInt256,
+        /// Logical time of the configuration transaction
+        transaction_lt: u64,
+    },
+    SetEndBlockNumber {
+        end_block_number: u32,
+    },
 }
 
 impl ReadFromTransaction for EthEventConfigurationEvent {
@@ -1566,6 +1849,7 @@ impl ReadFromTransaction for EthEventConfigurationEvent {
         match nekoton_abi::read_function_id(&in_msg_body).ok()? {
             id if id == deploy_event.input_id => Some(Self::EventDeployed {
                 address: ctx.find_new_event_contract_address()?,
+                transaction_lt: ctx.transaction.logical_time(),
             }),
             id if id == set_end_block_number.input_id => {
                 let end_block_number = set_end_block_number
@@ -1694,6 +1978,10 @@ fn read_external_in_msg(body: &ton_types::SliceData) -> Option<(UInt256, ton_typ
 
 const MIN_EVENT_BALANCE: u128 = 100_000_000; // 0.1 TON
 
+/// Number of recent event states kept while the event is preprocessed,
+/// so that the state right after the deployment is not replaced by later ones
+const EVENT_STATE_HISTORY_DEPTH: usize = 4;
+
 type ConnectorState = Arc<AccountObserver<ConnectorEvent>>;
 
 type DefaultHeaders = (PubkeyHeader, TimeHeader, ExpireHeader);
@@ -1717,4 +2005,6 @@ enum BridgeError {
     InvalidEventConfiguration,
     #[error("Event configuration already exists")]
     EventConfigurationAlreadyExists,
+    #[error("Event contract not found")]
+    EventNotFound,
 }
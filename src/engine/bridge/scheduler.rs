@@ -0,0 +1,298 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+/// Limits the number of simultaneously running event updates.
+/// Waiting tasks are started in order of their priority
+pub struct EventsScheduler {
+    max_concurrent_tasks: usize,
+    state: Mutex<SchedulerState>,
+    seqno: AtomicU64,
+}
+
+impl EventsScheduler {
+    pub fn new(max_concurrent_tasks: usize) -> Arc<Self> {
+        Arc::new(Self {
+            max_concurrent_tasks: std::cmp::max(max_concurrent_tasks, 1),
+            state: Default::default(),
+            seqno: Default::default(),
+        })
+    }
+
+    /// Waits until the task with the specified priority can be started
+    pub async fn acquire(self: &Arc<Self>, priority: EventPriority) -> SchedulerPermit {
+        let mut task = {
+            let mut state = self.state.lock();
+            if state.running < self.max_concurrent_tasks {
+                state.running += 1;
+                return SchedulerPermit(self.clone());
+            }
+
+            let (tx, rx) = oneshot::channel();
+            state.queue.push(QueuedTask {
+                priority,
+                seqno: self.seqno.fetch_add(1, AtomicOrdering::AcqRel),
+                tx,
+            });
+            QueuedTaskGuard {
+                scheduler: self.clone(),
+                rx,
+            }
+        };
+
+        // NOTE: sender is never dropped without sending while the scheduler is alive.
+        // Guard does nothing on drop after the value was received
+        (&mut task.rx).await.ok();
+        SchedulerPermit(self.clone())
+    }
+
+    pub fn metrics(&self) -> EventsSchedulerMetrics {
+        let state = self.state.lock();
+        EventsSchedulerMetrics {
+            max_concurrent_tasks: self.max_concurrent_tasks,
+            running_tasks: state.running,
+            queued_tasks: state.queue.len(),
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock();
+
+        // Pass the slot to the first alive waiter
+        while let Some(task) = state.queue.pop() {
+            if task.tx.send(()).is_ok() {
+                return;
+            }
+        }
+
+        state.running -= 1;
+    }
+}
+
+/// Task slot. Released on drop
+pub struct SchedulerPermit(Arc<EventsScheduler>);
+
+impl Drop for SchedulerPermit {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// Receiving side of the queued task. Returns the slot back if
+/// the task was cancelled after the slot was passed to it
+struct QueuedTaskGuard {
+    scheduler: Arc<EventsScheduler>,
+    rx: oneshot::Receiver<()>,
+}
+
+impl Drop for QueuedTaskGuard {
+    fn drop(&mut self) {
+        // NOTE: close the receiver first so that the slot can't be passed after the check
+        self.rx.close();
+        if self.rx.try_recv().is_ok() {
+            self.scheduler.release();
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct EventsSchedulerMetrics {
+    pub max_concurrent_tasks: usize,
+    pub running_tasks: usize,
+    pub queued_tasks: usize,
+}
+
+/// Event update priority. Events which are closer to the quorum are processed first,
+/// older events are processed first among them
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct EventPriority {
+    /// Number of confirmations required to reach the quorum
+    pub confirmations_left: u32,
+    /// Logical time of the event deployment
+    pub deployed_lt: u64,
+}
+
+impl EventPriority {
+    /// Priority for events without info about votes
+    pub const LOWEST: Self = Self {
+        confirmations_left: u32::MAX,
+        deployed_lt: u64::MAX,
+    };
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    running: usize,
+    queue: BinaryHeap<QueuedTask>,
+}
+
+struct QueuedTask {
+    priority: EventPriority,
+    seqno: u64,
+    tx: oneshot::Sender<()>,
+}
+
+impl Eq for QueuedTask {}
+
+impl PartialEq for QueuedTask {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl PartialOrd for QueuedTask {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedTask {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // NOTE: reversed because `BinaryHeap` is a max-heap. Tasks with the same
+        // priority are processed in order they were scheduled (older first)
+        (other.priority, other.seqno).cmp(&(self.priority, self.seqno))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn priority(confirmations_left: u32) -> EventPriority {
+        EventPriority {
+            confirmations_left,
+            deployed_lt: 0,
+        }
+    }
+
+    #[test]
+    fn older_events_go_first() {
+        let old = EventPriority {
+            confirmations_left: 1,
+            deployed_lt: 100,
+        };
+        let new = EventPriority {
+            confirmations_left: 1,
+            deployed_lt: 200,
+        };
+        let closer_to_quorum = EventPriority {
+            confirmations_left: 0,
+            deployed_lt: 300,
+        };
+
+        let mut priorities = vec![EventPriority::LOWEST, new, closer_to_quorum, old];
+        priorities.sort();
+        assert_eq!(
+            priorities,
+            vec![closer_to_quorum, old, new, EventPriority::LOWEST]
+        );
+    }
+
+    #[tokio::test]
+    async fn older_tasks_are_started_first() {
+        let scheduler = EventsScheduler::new(1);
+        let permit = scheduler.acquire(priority(0)).await;
+
+        let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+        for (id, deployed_lt) in [(0, 300), (1, 100), (2, 200)] {
+            let scheduler = scheduler.clone();
+            let order_tx = order_tx.clone();
+            tokio::spawn(async move {
+                let _permit = scheduler
+                    .acquire(EventPriority {
+                        confirmations_left: 1,
+                        deployed_lt,
+                    })
+                    .await;
+                order_tx.send(id).unwrap();
+            });
+        }
+        drop(order_tx);
+
+        while scheduler.metrics().queued_tasks < 3 {
+            tokio::task::yield_now().await;
+        }
+        drop(permit);
+
+        let mut order = Vec::new();
+        while let Some(id) = order_rx.recv().await {
+            order.push(id);
+        }
+        assert_eq!(order, vec![1, 2, 0]);
+    }
+
+    #[tokio::test]
+    async fn tasks_are_started_by_priority() {
+        let scheduler = EventsScheduler::new(1);
+        let permit = scheduler.acquire(priority(0)).await;
+
+        let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+        for (id, confirmations_left) in [(0, 5), (1, 1), (2, 5), (3, 0)] {
+            let scheduler = scheduler.clone();
+            let order_tx = order_tx.clone();
+            tokio::spawn(async move {
+                let _permit = scheduler.acquire(priority(confirmations_left)).await;
+                order_tx.send(id).unwrap();
+            });
+        }
+        drop(order_tx);
+
+        while scheduler.metrics().queued_tasks < 4 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(scheduler.metrics().running_tasks, 1);
+
+        drop(permit);
+
+        let mut order = Vec::new();
+        while let Some(id) = order_rx.recv().await {
+            order.push(id);
+        }
+        assert_eq!(order, vec![3, 1, 0, 2]);
+
+        let metrics = scheduler.metrics();
+        assert_eq!(metrics.running_tasks, 0);
+        assert_eq!(metrics.queued_tasks, 0);
+    }
+
+    #[tokio::test]
+    async fn cancelled_tasks_return_slots() {
+        let scheduler = EventsScheduler::new(1);
+        let permit = scheduler.acquire(priority(0)).await;
+
+        // Cancel the task which is still in the queue
+        let queued = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move {
+                let _permit = scheduler.acquire(priority(0)).await;
+                futures::future::pending::<()>().await;
+            }
+        });
+        while scheduler.metrics().queued_tasks < 1 {
+            tokio::task::yield_now().await;
+        }
+        queued.abort();
+        queued.await.ok();
+
+        // Cancel the task right after the slot was passed to it
+        let mut acquire = Box::pin(scheduler.acquire(priority(0)));
+        assert!(futures::poll!(&mut acquire).is_pending());
+        drop(permit);
+        drop(acquire);
+
+        let metrics = scheduler.metrics();
+        assert_eq!(metrics.running_tasks, 0);
+        assert_eq!(metrics.queued_tasks, 0);
+
+        let _permit = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            scheduler.acquire(priority(0)),
+        )
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,465 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use ton_types::UInt256;
+use web3::api::Namespace;
+use web3::types::{H256, U256};
+
+use super::{contracts, EthApi};
+use crate::config::*;
+use crate::engine::keystore::*;
+use crate::utils::*;
+
+/// Sends and tracks ETH address verification transactions
+pub struct AddressVerifier {
+    chain_id: u32,
+    api: EthApi,
+    clock: Arc<dyn Clock>,
+}
+
+impl AddressVerifier {
+    pub(super) fn new(chain_id: u32, api: EthApi, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            chain_id,
+            api,
+            clock,
+        }
+    }
+
+    /// Resumes the previous verification or submits a new transaction.
+    /// Returns when the transaction succeeded. Dropped transactions are re-submitted.
+    ///
+    /// NOTE: fails if the transaction reverts, so that UserData is re-checked
+    /// before the next transaction (e.g. the address could be already confirmed)
+    #[allow(clippy::too_many_arguments)]
+    pub async fn verify_relay_staker_address(
+        &self,
+        settings: &AddressVerificationConfig,
+        state_path: &Path,
+        tracker: &AddressVerificationTracker,
+        eth_signer: EthSignerHandle,
+        relay_address: &ethabi::Address,
+        staker_address: UInt256,
+        verifier_address: &ethabi::Address,
+    ) -> Result<()> {
+        // Restore previous state
+        let mut state = match AddressVerificationState::try_load(state_path)? {
+            // Ignore state for different address
+            Some(state) if state.address != relay_address.0 => {
+                log::warn!("Address verification state created for the different relay address. It will be ignored");
+                AddressVerificationState::clear(state_path);
+                None
+            }
+            // Ignore state for different network
+            Some(state) if state.chain_id != 0 && state.chain_id != self.chain_id => {
+                log::warn!(
+                    "Address verification state created for the different chain id {}. It will be ignored",
+                    state.chain_id
+                );
+                AddressVerificationState::clear(state_path);
+                None
+            }
+            Some(mut state) => {
+                state.chain_id = self.chain_id;
+                Some(state)
+            }
+            None => None,
+        };
+
+        tracker.start(self.chain_id, relay_address);
+        if let Some(state) = &state {
+            tracker.update(state);
+        }
+
+        let mut waited = false;
+
+        loop {
+            let submitted = match state {
+                Some(state) if state.status.is_pending() => state,
+                Some(state) if state.status.is_succeeded() => return Ok(()),
+                // NOTE: reverted state is only re-submitted when it was loaded,
+                // i.e. after the caller re-checked UserData
+                Some(state) if state.status == AddressVerificationStatus::Reverted && waited => {
+                    return Err(AddressVerificationError::Reverted {
+                        transaction_hash: hex::encode(state.transaction_hash),
+                    }
+                    .into());
+                }
+                state => {
+                    // Re-submit reverted or dropped transaction
+                    let attempts = match state {
+                        Some(state) => {
+                            log::warn!(
+                                "ETH address verification transaction {} {:?}. It will be re-submitted",
+                                hex::encode(state.transaction_hash),
+                                state.status
+                            );
+                            state.attempts
+                        }
+                        None => 0,
+                    };
+
+                    self.submit(
+                        settings,
+                        state_path,
+                        tracker,
+                        &eth_signer,
+                        relay_address,
+                        staker_address,
+                        verifier_address,
+                        attempts,
+                    )
+                    .await?
+                }
+            };
+
+            state = Some(
+                self.wait_transaction(submitted, state_path, tracker)
+                    .await?,
+            );
+            waited = true;
+        }
+    }
+
+    /// Signs and sends a new verification transaction
+    #[allow(clippy::too_many_arguments)]
+    async fn submit(
+        &self,
+        settings: &AddressVerificationConfig,
+        state_path: &Path,
+        tracker: &AddressVerificationTracker,
+        eth_signer: &EthSignerHandle,
+        relay_address: &ethabi::Address,
+        staker_address: UInt256,
+        verifier_address: &ethabi::Address,
+        attempts: u32,
+    ) -> Result<AddressVerificationState> {
+        const GWEI: u64 = 1000000000;
+
+        if attempts >= settings.max_attempts {
+            return Err(AddressVerificationError::TooManyAttempts { attempts }.into());
+        }
+
+        // Prepare params
+        let min_balance: U256 = U256::from(settings.min_balance_gwei * GWEI);
+        let gas_price: U256 = U256::from(settings.gas_price_gwei * GWEI);
+
+        let verifier_contract = contracts::staking_contract(self.api.clone(), *verifier_address)?;
+        let workchain_id = ethabi::Token::Int(U256::from(0));
+        let address_body = ethabi::Token::Uint(U256::from_big_endian(staker_address.as_slice()));
+
+        // Wait minimal balance
+        tracker.set_status(AddressVerificationStatus::WaitingForBalance);
+        loop {
+            let balance = retry(
+                || self.get_balance(*relay_address),
+                crate::utils::generate_default_timeout_config(Duration::from_secs(60)),
+                "Failed getting balance",
+            )
+            .await?;
+
+            if balance < min_balance {
+                log::info!("Insufficient balance ({}/{})", balance, min_balance);
+                self.clock
+                    .sleep(Duration::from_secs(POLL_INTERVAL_SEC))
+                    .await;
+            } else {
+                break;
+            }
+        }
+
+        // Prepare transaction
+        let fn_data = verifier_contract
+            .abi()
+            .function("verify_relay_staker_address")
+            .and_then(|function| function.encode_input(&[workchain_id, address_body]))
+            .map_err(|err| web3::error::Error::Decoder(format!("{:?}", err)))
+            .context("Failed to prepare address verification transaction")?;
+
+        let accounts = web3::api::Accounts::new(self.api.transport().clone());
+        let tx = web3::types::TransactionParameters {
+            to: Some(*verifier_address),
+            gas_price: Some(gas_price),
+            data: web3::types::Bytes(fn_data),
+            ..Default::default()
+        };
+
+        let signed = accounts
+            .sign_transaction(tx, eth_signer.secret_key())
+            .await
+            .context("Failed to sign address verification transaction")?;
+
+        let state = AddressVerificationState {
+            chain_id: self.chain_id,
+            transaction_hash: signed.transaction_hash.0,
+            address: relay_address.0,
+            status: AddressVerificationStatus::Submitted,
+            block_number: None,
+            attempts: attempts + 1,
+        };
+        state
+            .save(state_path)
+            .context("Failed to save address verification state")?;
+
+        self.api
+            .send_raw_transaction(signed.raw_transaction)
+            .await
+            .context("Failed to send raw ETH transaction")?;
+
+        log::info!(
+            "Sent ETH address verification transaction {} (attempt {})",
+            hex::encode(state.transaction_hash),
+            state.attempts
+        );
+        tracker.update(&state);
+
+        Ok(state)
+    }
+
+    async fn get_balance(&self, address: ethabi::Address) -> Result<U256> {
+        Ok(self.api.balance(address, None).await?)
+    }
+
+    /// Polls transaction until it is executed or dropped
+    async fn wait_transaction(
+        &self,
+        mut state: AddressVerificationState,
+        state_path: &Path,
+        tracker: &AddressVerificationTracker,
+    ) -> Result<AddressVerificationState> {
+        let transaction_hash = H256::from(state.transaction_hash);
+        let transaction_id = hex::encode(state.transaction_hash);
+
+        let mut transitions = TransactionTransitions::default();
+
+        loop {
+            let receipt = self
+                .api
+                .transaction_receipt(transaction_hash)
+                .await
+                .context("Failed to get ETH address verification transaction receipt")?;
+
+            let lookup = match receipt {
+                // Check receipt status of the executed transaction
+                Some(receipt) if receipt.block_number.is_some() => {
+                    state.block_number = receipt.block_number.map(|number| number.as_u64());
+                    TransactionLookup::Executed {
+                        reverted: matches!(receipt.status, Some(status) if status.is_zero()),
+                    }
+                }
+                // Check whether the transaction is still known
+                _ => match self
+                    .api
+                    .transaction(web3::types::TransactionId::Hash(transaction_hash))
+                    .await
+                    .context("Failed to find ETH address verification transaction")?
+                {
+                    Some(transaction) if transaction.block_hash.is_some() => {
+                        TransactionLookup::Mined
+                    }
+                    Some(_) => TransactionLookup::Pending,
+                    None => TransactionLookup::NotFound,
+                },
+            };
+            let status = transitions.next_status(state.status, lookup);
+
+            if status != state.status {
+                log::info!(
+                    "ETH address verification transaction {} status: {:?}",
+                    transaction_id,
+                    status
+                );
+                state.status = status;
+                state
+                    .save(state_path)
+                    .context("Failed to save address verification state")?;
+                tracker.update(&state);
+            }
+
+            if !status.is_pending() {
+                return Ok(state);
+            }
+            self.clock
+                .sleep(Duration::from_secs(POLL_INTERVAL_SEC))
+                .await;
+        }
+    }
+}
+
+/// ETH address verification progress for metrics and admin API
+#[derive(Default)]
+pub struct AddressVerificationTracker(parking_lot::Mutex<AddressVerificationProgress>);
+
+impl AddressVerificationTracker {
+    pub fn progress(&self) -> AddressVerificationProgress {
+        self.0.lock().clone()
+    }
+
+    pub fn set_status(&self, status: AddressVerificationStatus) {
+        self.0.lock().status = status;
+    }
+
+    fn start(&self, chain_id: u32, relay_address: &ethabi::Address) {
+        *self.0.lock() = AddressVerificationProgress {
+            chain_id: Some(chain_id),
+            address: Some(format!("0x{}", hex::encode(relay_address.0))),
+            ..Default::default()
+        };
+    }
+
+    fn update(&self, state: &AddressVerificationState) {
+        let mut progress = self.0.lock();
+        progress.status = state.status;
+        progress.transaction_hash = Some(format!("0x{}", hex::encode(state.transaction_hash)));
+        progress.block_number = state.block_number;
+        progress.attempts = state.attempts;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AddressVerificationProgress {
+    pub status: AddressVerificationStatus,
+    pub chain_id: Option<u32>,
+    pub address: Option<String>,
+    pub transaction_hash: Option<String>,
+    pub block_number: Option<u64>,
+    /// Number of submitted transactions
+    pub attempts: u32,
+}
+
+/// Verification transaction lookup result
+#[derive(Debug, Copy, Clone)]
+enum TransactionLookup {
+    Executed { reverted: bool },
+    Mined,
+    Pending,
+    NotFound,
+}
+
+/// Verification transaction status transitions
+#[derive(Default)]
+struct TransactionTransitions {
+    /// Consecutive lookups which didn't find the transaction
+    misses: u32,
+}
+
+impl TransactionTransitions {
+    fn next_status(
+        &mut self,
+        status: AddressVerificationStatus,
+        lookup: TransactionLookup,
+    ) -> AddressVerificationStatus {
+        if !matches!(lookup, TransactionLookup::NotFound) {
+            self.misses = 0;
+        }
+
+        match lookup {
+            TransactionLookup::Executed { reverted: true } => AddressVerificationStatus::Reverted,
+            TransactionLookup::Executed { reverted: false } => AddressVerificationStatus::Succeeded,
+            TransactionLookup::Mined => AddressVerificationStatus::Mined,
+            TransactionLookup::Pending => AddressVerificationStatus::Submitted,
+            // NOTE: the transaction can be temporarily unknown to the node
+            // (e.g. right after the submission or during reorgs)
+            TransactionLookup::NotFound => {
+                self.misses += 1;
+                if self.misses >= DROPPED_AFTER_MISSES {
+                    AddressVerificationStatus::Dropped
+                } else {
+                    status
+                }
+            }
+        }
+    }
+}
+
+const POLL_INTERVAL_SEC: u64 = 10;
+
+/// Number of consecutive lookups after which the transaction is considered dropped
+const DROPPED_AFTER_MISSES: u32 = 6;
+
+#[derive(thiserror::Error, Debug)]
+enum AddressVerificationError {
+    #[error("Too many address verification attempts: {attempts}")]
+    TooManyAttempts { attempts: u32 },
+    #[error("Address verification transaction {transaction_hash} reverted")]
+    Reverted { transaction_hash: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(
+        transitions: &mut TransactionTransitions,
+        mut status: AddressVerificationStatus,
+        lookups: &[TransactionLookup],
+    ) -> Vec<AddressVerificationStatus> {
+        lookups
+            .iter()
+            .map(|lookup| {
+                status = transitions.next_status(status, *lookup);
+                status
+            })
+            .collect()
+    }
+
+    #[test]
+    fn transaction_is_executed() {
+        use AddressVerificationStatus::*;
+
+        let mut transitions = TransactionTransitions::default();
+        assert_eq!(
+            apply(
+                &mut transitions,
+                Submitted,
+                &[
+                    TransactionLookup::Pending,
+                    TransactionLookup::Mined,
+                    TransactionLookup::Executed { reverted: false },
+                ]
+            ),
+            [Submitted, Mined, Succeeded]
+        );
+
+        let mut transitions = TransactionTransitions::default();
+        assert_eq!(
+            apply(
+                &mut transitions,
+                Submitted,
+                &[TransactionLookup::Executed { reverted: true }]
+            ),
+            [Reverted]
+        );
+    }
+
+    #[test]
+    fn transaction_is_dropped_after_consecutive_misses() {
+        use AddressVerificationStatus::*;
+
+        let mut transitions = TransactionTransitions::default();
+        let mut lookups = vec![TransactionLookup::NotFound; DROPPED_AFTER_MISSES as usize - 1];
+
+        // Status is kept while the transaction is temporarily unknown
+        let statuses = apply(&mut transitions, Mined, &lookups);
+        assert!(statuses.iter().all(|status| *status == Mined));
+
+        // Misses counter is reset when the transaction is found
+        lookups.push(TransactionLookup::Pending);
+        lookups.push(TransactionLookup::NotFound);
+        let mut transitions = TransactionTransitions::default();
+        assert_eq!(
+            apply(&mut transitions, Submitted, &lookups).last(),
+            Some(&Submitted)
+        );
+
+        let mut transitions = TransactionTransitions::default();
+        let lookups = [TransactionLookup::NotFound; DROPPED_AFTER_MISSES as usize];
+        assert_eq!(
+            apply(&mut transitions, Submitted, &lookups).last(),
+            Some(&Dropped)
+        );
+    }
+}
@@ -14,15 +14,17 @@ use tokio::sync::{oneshot, Notify, Semaphore};
 use tokio::time::timeout;
 use ton_types::UInt256;
 use web3::api::Namespace;
-use web3::types::{BlockNumber, FilterBuilder, H256, U256, U64};
+use web3::types::{BlockNumber, FilterBuilder, H256, U64};
 use web3::{transports::Http, Transport};
 
 use self::models::*;
 use crate::config::*;
-use crate::engine::keystore::*;
 use crate::engine::ton_contracts::*;
 use crate::utils::*;
 
+pub use self::address_verification::*;
+
+mod address_verification;
 mod contracts;
 mod models;
 
@@ -64,6 +66,29 @@ impl EthSubscriberRegistry {
         self.subscribers.get(&chain_id).map(|x| x.clone())
     }
 
+    /// Creates ETH address verifier for the specified network.
+    /// Uses the dedicated endpoint if it is specified, or the network subscriber otherwise
+    pub fn get_address_verifier(
+        &self,
+        chain_id: u32,
+        settings: &AddressVerificationConfig,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Option<AddressVerifier>> {
+        if let Some(endpoint) = &settings.endpoint {
+            let transport = Http::new(endpoint.as_str())
+                .context("Failed to create address verification transport")?;
+            return Ok(Some(AddressVerifier::new(
+                chain_id,
+                web3::api::Eth::new(transport),
+                clock,
+            )));
+        }
+
+        Ok(self
+            .get_subscriber(chain_id)
+            .map(|subscriber| AddressVerifier::new(chain_id, subscriber.api.clone(), clock)))
+    }
+
     pub fn subscribers(&self) -> &DashMap<u32, Arc<EthSubscriber>> {
         &self.subscribers
     }
@@ -163,134 +188,6 @@ impl EthSubscriber {
         }
     }
 
-    pub async fn verify_relay_staker_address(
-        &self,
-        settings: &AddressVerificationConfig,
-        eth_signer: EthSignerHandle,
-        relay_address: &ethabi::Address,
-        staker_address: UInt256,
-        verifier_address: &ethabi::Address,
-    ) -> Result<()> {
-        const GWEI: u64 = 1000000000;
-
-        let clear_state = || {
-            if let Err(e) = std::fs::remove_file(&settings.state_path) {
-                log::info!("Failed to reset address verification state: {:?}", e);
-            }
-        };
-
-        // Restore previous state
-        match AddressVerificationState::try_load(&settings.state_path)? {
-            // Ignore state for different address
-            Some(state) if state.address != relay_address.0 => {
-                log::warn!("Address verification state created for the different relay address. It will be ignored");
-                clear_state();
-            }
-            // Wait until transaction is found
-            Some(state) => {
-                let transaction_id = hex::encode(state.transaction_hash);
-
-                loop {
-                    // Find transaction
-                    match self
-                        .api
-                        .transaction(web3::types::TransactionId::Hash(
-                            state.transaction_hash.into(),
-                        ))
-                        .await
-                        .context("Failed to find ETH address verification transaction")?
-                    {
-                        // Check if found transaction was included in block
-                        Some(transaction) => match transaction.block_hash {
-                            // If it was included, consider that the address is confirmed
-                            Some(block) => {
-                                log::info!(
-                                    "ETH transaction {} found in block {}",
-                                    transaction_id,
-                                    hex::encode(block.as_bytes())
-                                );
-                                clear_state();
-                                return Ok(());
-                            }
-                            // If it wasn't included, poll
-                            None => {
-                                log::info!("ETH transaction {} is still pending", transaction_id);
-                                tokio::time::sleep(Duration::from_secs(10)).await;
-                            }
-                        },
-                        // Ignore state for non-existing transaction
-                        None => {
-                            log::warn!("Address verification state contains non-existing transaction. It will be ignored");
-                            clear_state();
-                            break; // continue verification
-                        }
-                    };
-                }
-            }
-            None => { /* continue verification */ }
-        };
-
-        // Prepare params
-        let min_balance: U256 = U256::from(settings.min_balance_gwei * GWEI);
-        let gas_price: U256 = U256::from(settings.gas_price_gwei * GWEI);
-
-        let verifier_contract = contracts::staking_contract(self.api.clone(), *verifier_address)?;
-        let workchain_id = ethabi::Token::Int(U256::from(0));
-        let address_body = ethabi::Token::Uint(U256::from_big_endian(staker_address.as_slice()));
-
-        // Wait minimal balance
-        loop {
-            let balance = retry(
-                || self.get_balance(*relay_address),
-                crate::utils::generate_default_timeout_config(Duration::from_secs(60)),
-                "Failed getting balance",
-            )
-            .await?;
-
-            if balance < min_balance {
-                log::info!("Insufficient balance ({}/{})", balance, min_balance);
-                tokio::time::sleep(Duration::from_secs(10)).await;
-            } else {
-                break;
-            }
-        }
-
-        // Prepare transaction
-        let fn_data = verifier_contract
-            .abi()
-            .function("verify_relay_staker_address")
-            .and_then(|function| function.encode_input(&[workchain_id, address_body]))
-            .map_err(|err| web3::error::Error::Decoder(format!("{:?}", err)))
-            .context("Failed to prepare address verification transaction")?;
-
-        let accounts = web3::api::Accounts::new(self.api.transport().clone());
-        let tx = web3::types::TransactionParameters {
-            to: Some(*verifier_address),
-            gas_price: Some(gas_price),
-            data: web3::types::Bytes(fn_data),
-            ..Default::default()
-        };
-
-        let signed = accounts
-            .sign_transaction(tx, eth_signer.secret_key())
-            .await
-            .context("Failed to sign address verification transaction")?;
-
-        AddressVerificationState {
-            transaction_hash: signed.transaction_hash.0,
-            address: relay_address.0,
-        }
-        .save(&settings.state_path)
-        .context("Failed to save address verification state")?;
-
-        self.api
-            .send_raw_transaction(signed.raw_transaction)
-            .await
-            .context("Failed to send raw ETH transaction")?;
-
-        Ok(())
-    }
-
     pub fn subscribe(
         &self,
         address: ethabi::Address,
@@ -356,6 +253,47 @@ impl EthSubscriber {
         Ok(status)
     }
 
+    /// Checks event against the current chain state without waiting for the confirmations.
+    ///
+    /// NOTE: doesn't require the subscriber to be started
+    pub async fn verify_now(
+        &self,
+        vote_data: EthEventVoteData,
+        event_emitter: [u8; 20],
+        event_abi: Arc<EthEventAbi>,
+        blocks_to_confirm: u16,
+    ) -> Result<VerificationStatus> {
+        let current_block = self.get_current_block_number().await?;
+
+        let target_block = vote_data.event_block_number as u64 + blocks_to_confirm as u64;
+        if target_block > current_block {
+            return Err(EthSubscriberError::NotEnoughConfirmations {
+                current_block,
+                target_block,
+            }
+            .into());
+        }
+
+        let event_id = (
+            H256::from(vote_data.event_transaction.as_slice()),
+            vote_data.event_index,
+        );
+
+        let confirmation = PendingConfirmation {
+            vote_data,
+            status_tx: None,
+            event_emitter,
+            event_abi,
+            target_block,
+            status: PendingConfirmationStatus::InProcess,
+        };
+
+        Ok(match self.find_event(&event_id).await? {
+            Some(ParsedEthEvent::Received(event)) => confirmation.check(event),
+            _ => VerificationStatus::NotExists,
+        })
+    }
+
     fn start(self: &Arc<Self>) {
         let subscriber = Arc::downgrade(self);
 
@@ -615,10 +553,6 @@ impl EthSubscriber {
         }))
     }
 
-    async fn get_balance(&self, address: ethabi::Address) -> Result<U256> {
-        Ok(self.api.balance(address, None).await?)
-    }
-
     async fn get_current_block_number(&self) -> Result<u64> {
         let result = timeout(
             Duration::from_secs(self.config.get_timeout_sec),
@@ -803,4 +737,9 @@ fn is_incomplete_message(error: &anyhow::Error) -> bool {
 enum EthSubscriberError {
     #[error("Unknown chain id")]
     UnknownChainId,
+    #[error("Not enough confirmations ({current_block}/{target_block})")]
+    NotEnoughConfirmations {
+        current_block: u64,
+        target_block: u64,
+    },
 }
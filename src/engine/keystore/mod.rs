@@ -1,10 +1,11 @@
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use nekoton_abi::*;
 use nekoton_utils::TrustMe;
+use parking_lot::{RwLock, RwLockUpgradableReadGuard};
 use pkey_mprotect::*;
 use secstr::SecUtf8;
 use ton_types::UInt256;
@@ -12,6 +13,150 @@ use ton_types::UInt256;
 use crate::config::{FromPhraseAndPath, StoredKeysData, UnencryptedEthData, UnencryptedTonData};
 use crate::utils::*;
 
+/// Current relay keys with the optional next keys for the rotation
+pub struct RotatingKeyStore {
+    keys_path: PathBuf,
+    next_keys_path: Option<PathBuf>,
+    key_pairs: RwLock<KeyPairs>,
+}
+
+struct KeyPairs {
+    current: Arc<KeyStore>,
+    next: Option<Arc<KeyStore>>,
+}
+
+impl RotatingKeyStore {
+    /// Loads current keys and the next keys if their file exists
+    pub fn new(
+        keys_path: PathBuf,
+        next_keys_path: Option<PathBuf>,
+        password: SecUtf8,
+        protection_keys: Arc<ProtectionKeys>,
+    ) -> Result<Self> {
+        let current = KeyStore::new(&keys_path, password.clone(), protection_keys.clone())?;
+
+        let next = match &next_keys_path {
+            Some(path) if path.exists() => {
+                let stored_data = StoredKeysData::load(path)?;
+                let next = KeyStore::from_stored_data(stored_data, password, protection_keys)
+                    .context("Failed to load next keys")?;
+
+                if next.matches(
+                    current.ton.public_key(),
+                    current.eth.address().as_fixed_bytes(),
+                ) {
+                    log::warn!("Next keys are the same as the current keys. They will be ignored");
+                    None
+                } else {
+                    log::warn!("Next TON public key: 0x{:x}", next.ton.public_key());
+                    log::warn!(
+                        "Next ETH address: {}",
+                        EthAddressWrapper(next.eth.address())
+                    );
+                    Some(next)
+                }
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            keys_path,
+            next_keys_path,
+            key_pairs: RwLock::new(KeyPairs { current, next }),
+        })
+    }
+
+    /// Keys used for signing
+    pub fn current(&self) -> Arc<KeyStore> {
+        self.key_pairs.read().current.clone()
+    }
+
+    /// Keys which will be used after the rotation
+    pub fn next(&self) -> Option<Arc<KeyStore>> {
+        self.key_pairs.read().next.clone()
+    }
+
+    /// Returns the next keys if they match the specified keys
+    pub fn find_next(&self, ton_pubkey: &UInt256, eth_address: &[u8; 20]) -> Option<Arc<KeyStore>> {
+        self.next()
+            .filter(|next| next.matches(ton_pubkey, eth_address))
+    }
+
+    /// Whether the TON public key belongs to the current or the next keys
+    pub fn is_known_ton_pubkey(&self, ton_pubkey: &UInt256) -> bool {
+        let key_pairs = self.key_pairs.read();
+        std::iter::once(&key_pairs.current)
+            .chain(&key_pairs.next)
+            .any(|keys| keys.ton.public_key() == ton_pubkey)
+    }
+
+    /// Whether the ETH address belongs to the current or the next keys
+    pub fn is_known_eth_address(&self, eth_address: &[u8; 20]) -> bool {
+        let key_pairs = self.key_pairs.read();
+        std::iter::once(&key_pairs.current)
+            .chain(&key_pairs.next)
+            .any(|keys| keys.eth.address().as_fixed_bytes() == eth_address)
+    }
+
+    /// Replaces the keys file with the next keys and then switches to them.
+    /// The previous keys file is kept with `.prev` suffix. Keys are not
+    /// changed if the file can't be replaced
+    pub fn switch_to_next(&self) -> Result<()> {
+        let next_keys_path = self
+            .next_keys_path
+            .as_ref()
+            .ok_or(KeyStoreError::NextKeysNotFound)?;
+
+        // NOTE: upgradable lock doesn't block readers but prevents concurrent switches
+        let key_pairs = self.key_pairs.upgradable_read();
+        let next = key_pairs
+            .next
+            .clone()
+            .ok_or(KeyStoreError::NextKeysNotFound)?;
+
+        let prev_keys_path = with_suffix(&self.keys_path, ".prev");
+        let tmp_keys_path = with_suffix(&self.keys_path, ".tmp");
+
+        let replace_keys_file = || -> Result<()> {
+            std::fs::copy(next_keys_path, &tmp_keys_path).context("Failed to copy next keys")?;
+            std::fs::copy(&self.keys_path, &prev_keys_path)
+                .context("Failed to backup previous keys")?;
+            std::fs::rename(&tmp_keys_path, &self.keys_path)
+                .context("Failed to replace keys file with the next keys")
+        };
+        if let Err(e) = replace_keys_file() {
+            std::fs::remove_file(&tmp_keys_path).ok();
+            return Err(e);
+        }
+
+        {
+            let mut key_pairs = RwLockUpgradableReadGuard::upgrade(key_pairs);
+            key_pairs.next = None;
+            key_pairs.current = next.clone();
+        }
+
+        log::warn!("Switched to the next keys");
+        log::warn!("Using TON public key: 0x{:x}", next.ton.public_key());
+        log::warn!(
+            "Using ETH address: {}",
+            EthAddressWrapper(next.eth.address())
+        );
+
+        // NOTE: next keys file is ignored on startup if it matches the current keys
+        if let Err(e) = std::fs::remove_file(next_keys_path) {
+            log::warn!("Failed to remove next keys file: {:?}", e);
+        }
+
+        Ok(())
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(suffix);
+    path.into()
+}
+
 /// A collection of signers
 pub struct KeyStore {
     pub eth: EthSigner,
@@ -47,6 +192,36 @@ impl KeyStore {
             data
         };
 
+        let keystore = Self::from_stored_data(stored_data, password, protection_keys)?;
+
+        // Print ETH address and TON public key
+        log::warn!("Using TON public key: 0x{:x}", keystore.ton.public_key());
+        log::warn!(
+            "Using ETH address: {}",
+            EthAddressWrapper(keystore.eth.address())
+        );
+
+        Ok(keystore)
+    }
+
+    /// Loads and decrypts existing keystore state. Doesn't generate new keys
+    pub fn load<P>(
+        keys_path: P,
+        password: SecUtf8,
+        protection_keys: Arc<ProtectionKeys>,
+    ) -> Result<Arc<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        let stored_data = StoredKeysData::load(keys_path)?;
+        Self::from_stored_data(stored_data, password, protection_keys)
+    }
+
+    fn from_stored_data(
+        stored_data: StoredKeysData,
+        password: SecUtf8,
+        protection_keys: Arc<ProtectionKeys>,
+    ) -> Result<Arc<Self>> {
         let (eth_secret_key, ton_secret_key) =
             stored_data.decrypt_only_keys(password.unsecure())?;
         let keys = protection_keys
@@ -60,19 +235,15 @@ impl KeyStore {
             })
             .context("Failed to create protected region")?;
 
-        let keystore = Arc::new(Self {
+        Ok(Arc::new(Self {
             eth: EthSigner::new(keys.clone()),
             ton: TonSigner::new(keys),
-        });
-
-        // Print ETH address and TON public key
-        log::warn!("Using TON public key: 0x{:x}", keystore.ton.public_key());
-        log::warn!(
-            "Using ETH address: {}",
-            EthAddressWrapper(keystore.eth.address())
-        );
+        }))
+    }
 
-        Ok(keystore)
+    /// Whether these keys are the specified TON public key and ETH address
+    pub fn matches(&self, ton_pubkey: &UInt256, eth_address: &[u8; 20]) -> bool {
+        self.ton.public_key() == ton_pubkey && self.eth.address().as_fixed_bytes() == eth_address
     }
 }
 
@@ -178,9 +349,14 @@ impl TonSigner {
         &self.public_key
     }
 
-    pub fn sign(&self, unsigned_message: &UnsignedMessage) -> Result<SignedMessage> {
-        let time = chrono::Utc::now().timestamp_millis() as u64;
-        let expire_at = (time / 1000) as u32 + MESSAGE_TTL_SEC;
+    pub fn sign(
+        &self,
+        clock: &dyn Clock,
+        unsigned_message: &UnsignedMessage,
+        ttl: u32,
+    ) -> Result<SignedMessage> {
+        let time = clock.now_ms();
+        let expire_at = (time / 1000) as u32 + ttl;
 
         let headers = default_headers(time, expire_at, &self.public_key);
         let body = unsigned_message.function.encode_input(
@@ -204,6 +380,12 @@ impl TonSigner {
             expire_at,
         })
     }
+
+    /// Signs arbitrary data (e.g. wallet message payload hash)
+    pub fn sign_raw(&self, data: &[u8]) -> [u8; 64] {
+        use ed25519_dalek::Signer;
+        self.keys.lock().ton_keypair.sign(data).to_bytes()
+    }
 }
 
 struct Keys {
@@ -211,11 +393,13 @@ struct Keys {
     ton_keypair: ed25519_dalek::Keypair,
 }
 
+#[derive(Clone)]
 pub struct UnsignedMessage {
     function: &'static ton_abi::Function,
     inputs: Vec<ton_abi::Token>,
     account: UInt256,
     dst: ton_block::MsgAddressInt,
+    sender_agnostic: bool,
 }
 
 impl UnsignedMessage {
@@ -227,9 +411,35 @@ impl UnsignedMessage {
             inputs: Vec::with_capacity(function.inputs.len()),
             account,
             dst,
+            sender_agnostic: false,
         }
     }
 
+    /// Marks the message as accepted from any sender. Otherwise the message is
+    /// authorised by the message pubkey and can't be sent through the wallet
+    pub fn sender_agnostic(mut self) -> Self {
+        self.sender_agnostic = true;
+        self
+    }
+
+    pub fn is_sender_agnostic(&self) -> bool {
+        self.sender_agnostic
+    }
+
+    pub fn function_name(&self) -> &'static str {
+        &self.function.name
+    }
+
+    pub fn dst(&self) -> &ton_block::MsgAddressInt {
+        &self.dst
+    }
+
+    /// Encodes function call as an internal message body
+    pub fn encode_internal_body(&self) -> Result<ton_types::Cell> {
+        let body = self.function.encode_internal_input(&self.inputs)?;
+        Ok(body.into_cell()?)
+    }
+
     pub fn arg<T>(mut self, arg: T) -> Self
     where
         T: BuildTokenValue,
@@ -239,8 +449,8 @@ impl UnsignedMessage {
         self
     }
 
-    pub fn build_without_signature(&self) -> Result<SignedMessage> {
-        let time = chrono::Utc::now().timestamp_millis() as u64;
+    pub fn build_without_signature(&self, clock: &dyn Clock) -> Result<SignedMessage> {
+        let time = clock.now_ms();
         let expire_at = (time / 1000) as u32 + MESSAGE_TTL_SEC;
 
         let headers = default_headers(time, expire_at, &Default::default());
@@ -286,6 +496,12 @@ type HeadersMap = HashMap<String, ton_abi::TokenValue>;
 
 const MESSAGE_TTL_SEC: u32 = 60;
 
+#[derive(thiserror::Error, Debug)]
+enum KeyStoreError {
+    #[error("Next keys not found")]
+    NextKeysNotFound,
+}
+
 #[cfg(test)]
 mod tst {
     use std::io::Write;
@@ -364,6 +580,89 @@ mod tst {
         assert!(KeyStore::new(path, "kek".into(), protection_keys).is_err())
     }
 
+    #[test]
+    fn switch_to_next_keys() {
+        let protection_keys = ProtectionKeys::new(false).unwrap();
+
+        let (dir, path) = create_file();
+        let next_path = dir.path().join("next.json");
+        StoredKeysData::new(
+            "lol",
+            UnencryptedEthData::generate().unwrap(),
+            UnencryptedTonData::generate().unwrap(),
+        )
+        .unwrap()
+        .save(&next_path)
+        .unwrap();
+
+        let store = RotatingKeyStore::new(
+            path.clone(),
+            Some(next_path.clone()),
+            "lol".into(),
+            protection_keys,
+        )
+        .unwrap();
+        let current = store.current();
+        let next = store.next().unwrap();
+        assert!(store.is_known_ton_pubkey(next.ton.public_key()));
+        assert!(store
+            .find_next(
+                current.ton.public_key(),
+                current.eth.address().as_fixed_bytes()
+            )
+            .is_none());
+
+        store.switch_to_next().unwrap();
+        assert_eq!(store.current().ton.public_key(), next.ton.public_key());
+        assert!(store.next().is_none());
+        assert!(!store.is_known_ton_pubkey(current.ton.public_key()));
+
+        assert!(path.exists());
+        assert!(!next_path.exists());
+        assert!(dir.path().join("data.json.prev").exists());
+    }
+
+    #[test]
+    fn keys_are_not_switched_on_file_error() {
+        let protection_keys = ProtectionKeys::new(false).unwrap();
+
+        let (dir, path) = create_file();
+        let next_path = dir.path().join("next.json");
+        StoredKeysData::new(
+            "lol",
+            UnencryptedEthData::generate().unwrap(),
+            UnencryptedTonData::generate().unwrap(),
+        )
+        .unwrap()
+        .save(&next_path)
+        .unwrap();
+
+        let store = RotatingKeyStore::new(
+            path.clone(),
+            Some(next_path.clone()),
+            "lol".into(),
+            protection_keys,
+        )
+        .unwrap();
+        let current = store.current();
+        let next = store.next().unwrap();
+
+        // Make the next keys file unavailable
+        std::fs::remove_file(&next_path).unwrap();
+        assert!(store.switch_to_next().is_err());
+
+        assert_eq!(store.current().ton.public_key(), current.ton.public_key());
+        assert_eq!(
+            store.next().unwrap().ton.public_key(),
+            next.ton.public_key()
+        );
+
+        let stored = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(stored, JSON);
+        assert!(!dir.path().join("data.json.prev").exists());
+        assert!(!dir.path().join("data.json.tmp").exists());
+    }
+
     fn create_file() -> (TempDir, PathBuf) {
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join("data.json");
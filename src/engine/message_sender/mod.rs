@@ -0,0 +1,555 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use tiny_adnl::utils::*;
+use tokio::sync::oneshot;
+use ton_block::Serializable;
+use ton_types::UInt256;
+
+use super::keystore::*;
+use super::ton_chain::*;
+use super::ton_contracts::*;
+use super::ton_subscriber::*;
+use crate::config::*;
+use crate::utils::*;
+
+/// Builds and signs relay messages according to the sender mode
+pub enum MessageSender {
+    /// External messages are sent directly to contracts
+    Direct,
+    /// Internal messages are sent through the wallet contract
+    Wallet(WalletSender),
+}
+
+impl MessageSender {
+    pub fn new(
+        config: &MessageSenderConfig,
+        ton_chain: &Arc<dyn TonChain>,
+        messages_queue: &Arc<PendingMessagesQueue>,
+    ) -> Result<Self> {
+        Ok(match config {
+            MessageSenderConfig::Direct => Self::Direct,
+            MessageSenderConfig::Wallet(config) => {
+                log::warn!(
+                    "Wallet {} is only used for the elections start and end calls. \
+                    Event votes, `becomeRelayNextRound`, rewards and TON account confirmation \
+                    are authorised by the relay key and are still sent directly",
+                    config.address
+                );
+                Self::Wallet(WalletSender::new(config, ton_chain, messages_queue)?)
+            }
+        })
+    }
+
+    /// Prepares a signed external message. The message is sent either to the
+    /// destination contract or to the wallet.
+    ///
+    /// NOTE: messages which are authorised by the message pubkey are always
+    /// sent directly, because the wallet address would be the sender.
+    ///
+    /// NOTE: the returned message must be held until it is delivered or expired
+    pub async fn prepare(
+        &self,
+        keystore: &KeyStore,
+        ton_chain: &dyn TonChain,
+        clock: &dyn Clock,
+        unsigned_message: &UnsignedMessage,
+        ttl: u32,
+    ) -> Result<PreparedMessage> {
+        match self {
+            Self::Wallet(wallet) if unsigned_message.is_sender_agnostic() => {
+                wallet
+                    .prepare(keystore, ton_chain, clock, unsigned_message, ttl)
+                    .await
+            }
+            _ => Ok(PreparedMessage {
+                message: keystore.ton.sign(clock, unsigned_message, ttl)?,
+                outgoing: None,
+                _guard: None,
+            }),
+        }
+    }
+}
+
+pub struct PreparedMessage {
+    pub message: SignedMessage,
+    /// Outgoing wallet message tracker. `None` for direct messages
+    outgoing: Option<OutgoingMessage>,
+    _guard: Option<tokio::sync::OwnedMutexGuard<()>>,
+}
+
+impl PreparedMessage {
+    /// Converts the status of the sent external message into the delivery status.
+    ///
+    /// Wallet messages are only delivered when the destination transaction
+    /// of the outgoing internal message succeeds
+    pub async fn wait_destination(&self, status: MessageStatus) -> MessageStatus {
+        match (&self.outgoing, status) {
+            (Some(outgoing), MessageStatus::Delivered { .. }) => outgoing.wait().await,
+            _ => status,
+        }
+    }
+}
+
+pub struct WalletSender {
+    address: ton_block::MsgAddressInt,
+    account: UInt256,
+    wallet_type: WalletType,
+    attached_amount: u64,
+    subwallet_id: u32,
+    /// Seqno based wallets can't process several messages simultaneously
+    seqno_lock: Arc<tokio::sync::Mutex<()>>,
+    /// Outgoing internal messages of the sent external messages
+    outgoing: Arc<OutgoingMessages>,
+    /// Keeps wallet transactions in the messages queue scope
+    _observer: Arc<WalletObserver>,
+}
+
+impl WalletSender {
+    fn new(
+        config: &WalletSenderConfig,
+        ton_chain: &Arc<dyn TonChain>,
+        messages_queue: &Arc<PendingMessagesQueue>,
+    ) -> Result<Self> {
+        if config.address.workchain_id() != 0 {
+            return Err(MessageSenderError::WalletNotInBasechain.into());
+        }
+        let account = only_account_hash(config.address.clone());
+
+        let outgoing = Arc::new(OutgoingMessages {
+            messages_queue: messages_queue.clone(),
+            entries: Default::default(),
+        });
+
+        let observer = Arc::new(WalletObserver {
+            outgoing: outgoing.clone(),
+        });
+        ton_chain.add_transactions_subscription([account], &observer);
+
+        Ok(Self {
+            address: config.address.clone(),
+            account,
+            wallet_type: config.wallet_type,
+            attached_amount: config.attached_amount,
+            subwallet_id: config.subwallet_id,
+            seqno_lock: Default::default(),
+            outgoing,
+            _observer: observer,
+        })
+    }
+
+    async fn prepare(
+        &self,
+        keystore: &KeyStore,
+        ton_chain: &dyn TonChain,
+        clock: &dyn Clock,
+        unsigned_message: &UnsignedMessage,
+        ttl: u32,
+    ) -> Result<PreparedMessage> {
+        let payload = unsigned_message.encode_internal_body()?;
+
+        match self.wallet_type {
+            WalletType::WalletV3 => {
+                let guard = self.seqno_lock.clone().lock_owned().await;
+
+                let wallet = ton_chain
+                    .get_contract_state(self.account)
+                    .await?
+                    .ok_or(MessageSenderError::WalletNotDeployed)?;
+                let seqno = read_wallet_v3_seqno(&wallet)?;
+
+                let internal_message = ton_block::Message::with_int_header_and_body(
+                    ton_block::InternalMessageHeader::with_addresses_and_bounce(
+                        self.address.clone(),
+                        unsigned_message.dst().clone(),
+                        ton_block::CurrencyCollection::with_grams(self.attached_amount),
+                        true,
+                    ),
+                    payload.into(),
+                );
+
+                let expire_at = clock.now_sec() + ttl;
+
+                let mut body = ton_types::BuilderData::new();
+                body.append_u32(self.subwallet_id)?
+                    .append_u32(expire_at)?
+                    .append_u32(seqno)?
+                    .append_u8(WALLET_SEND_MODE)?;
+                body.checked_append_reference(internal_message.serialize()?)?;
+
+                let signature = keystore
+                    .ton
+                    .sign_raw(body.clone().into_cell()?.repr_hash().as_slice());
+
+                let mut signed_body = ton_types::BuilderData::new();
+                signed_body.append_raw(&signature, 512)?;
+                signed_body.append_builder(&body)?;
+
+                let message = ton_block::Message::with_ext_in_header_and_body(
+                    ton_block::ExternalInboundMessageHeader {
+                        dst: self.address.clone(),
+                        ..Default::default()
+                    },
+                    signed_body.into(),
+                );
+
+                let message = SignedMessage {
+                    account: self.account,
+                    message,
+                    expire_at,
+                };
+                Ok(PreparedMessage {
+                    outgoing: Some(self.outgoing.track(&message)?),
+                    message,
+                    _guard: Some(guard),
+                })
+            }
+            WalletType::EverWallet => {
+                let transfer =
+                    UnsignedMessage::new(wallet_contract::send_transaction(), self.account)
+                        .arg(unsigned_message.dst().clone())
+                        .arg(self.attached_amount as u128)
+                        .arg(true)
+                        .arg(WALLET_SEND_MODE)
+                        .arg(payload);
+
+                let message = keystore.ton.sign(clock, &transfer, ttl)?;
+                Ok(PreparedMessage {
+                    outgoing: Some(self.outgoing.track(&message)?),
+                    message,
+                    _guard: None,
+                })
+            }
+        }
+    }
+}
+
+/// Registers outgoing internal messages of the tracked wallet transactions
+struct WalletObserver {
+    outgoing: Arc<OutgoingMessages>,
+}
+
+impl TransactionsSubscription for WalletObserver {
+    fn tracks_messages(&self) -> bool {
+        true
+    }
+
+    fn handle_transaction(&self, ctx: TxContext<'_>) -> Result<()> {
+        let external_message_hash = match (&ctx.transaction.in_msg, ctx.in_msg_external()) {
+            (Some(in_msg), Some(_)) => in_msg.hash(),
+            _ => return Ok(()),
+        };
+
+        // NOTE: wallet sends at most one internal message per external message
+        let mut outgoing = None;
+        ctx.transaction
+            .out_msgs
+            .iterate(|ton_block::InRefValue(message)| {
+                if let ton_block::CommonMsgInfo::IntMsgInfo(header) = message.header() {
+                    let message_hash = message.serialize()?.repr_hash();
+                    outgoing = Some((only_account_hash(&header.dst), message_hash));
+                    return Ok(false);
+                }
+                Ok(true)
+            })
+            .ok();
+
+        self.outgoing.handle_wallet_transaction(
+            &external_message_hash,
+            outgoing,
+            ctx.block_info.gen_utime().0 + DESTINATION_TIMEOUT_SEC,
+        );
+        Ok(())
+    }
+}
+
+/// Matches sent external messages with the outgoing wallet messages
+struct OutgoingMessages {
+    messages_queue: Arc<PendingMessagesQueue>,
+    entries: Mutex<FxHashMap<UInt256, OutgoingMessageState>>,
+}
+
+impl OutgoingMessages {
+    /// Starts tracking the external message to the wallet
+    fn track(self: &Arc<Self>, message: &SignedMessage) -> Result<OutgoingMessage> {
+        let external_message_hash = message.message.serialize()?.repr_hash();
+        let (tx, rx) = oneshot::channel();
+        self.entries
+            .lock()
+            .insert(external_message_hash, OutgoingMessageState::Sent(tx));
+
+        Ok(OutgoingMessage {
+            outgoing: self.clone(),
+            external_message_hash,
+            rx: Mutex::new(Some(rx)),
+        })
+    }
+
+    /// Registers the outgoing message in the messages queue, so that it is
+    /// found in the destination account transactions
+    fn handle_wallet_transaction(
+        &self,
+        external_message_hash: &UInt256,
+        outgoing: Option<(UInt256, UInt256)>,
+        expire_at: u32,
+    ) {
+        let tx = match self.entries.lock().get_mut(external_message_hash) {
+            Some(state) => match std::mem::replace(state, OutgoingMessageState::Registered) {
+                OutgoingMessageState::Sent(tx) => tx,
+                OutgoingMessageState::Registered => return,
+            },
+            None => return,
+        };
+
+        let status_rx = match outgoing {
+            Some((account, message_hash)) => {
+                match self
+                    .messages_queue
+                    .add_message(account, message_hash, expire_at)
+                {
+                    Ok(status_rx) => Some(status_rx),
+                    Err(e) => {
+                        log::error!(
+                            "Failed to track outgoing wallet message to account {:x}: {:?}",
+                            account,
+                            e
+                        );
+                        None
+                    }
+                }
+            }
+            None => {
+                log::error!("Wallet transaction has no outgoing internal messages");
+                None
+            }
+        };
+
+        tx.send(status_rx).ok();
+    }
+}
+
+enum OutgoingMessageState {
+    /// External message was sent, but the wallet transaction was not found yet
+    Sent(oneshot::Sender<Option<MessageStatusRx>>),
+    /// Outgoing message was registered in the messages queue
+    Registered,
+}
+
+/// Destination delivery status of the single wallet message
+struct OutgoingMessage {
+    outgoing: Arc<OutgoingMessages>,
+    external_message_hash: UInt256,
+    rx: Mutex<Option<oneshot::Receiver<Option<MessageStatusRx>>>>,
+}
+
+impl OutgoingMessage {
+    async fn wait(&self) -> MessageStatus {
+        let rx = match self.rx.lock().take() {
+            Some(rx) => rx,
+            None => return MessageStatus::Expired,
+        };
+
+        // NOTE: wallet transaction is passed to the observer right after its
+        // external message is delivered
+        match rx.await {
+            Ok(Some(status_rx)) => status_rx.await.unwrap_or(MessageStatus::Expired),
+            // Wallet didn't produce an outgoing message (e.g. insufficient balance)
+            Ok(None) => MessageStatus::Failed {
+                exit_code: 0,
+                phase: TransactionPhase::Action,
+            },
+            Err(_) => MessageStatus::Expired,
+        }
+    }
+}
+
+impl Drop for OutgoingMessage {
+    fn drop(&mut self) {
+        self.outgoing
+            .entries
+            .lock()
+            .remove(&self.external_message_hash);
+    }
+}
+
+fn read_wallet_v3_seqno(wallet: &ExistingContract) -> Result<u32> {
+    match &wallet.account.storage.state {
+        ton_block::AccountState::AccountActive { state_init, .. } => {
+            let data = state_init
+                .data
+                .clone()
+                .ok_or(MessageSenderError::InvalidWalletData)?;
+            let seqno = ton_types::SliceData::from(data).get_next_u32()?;
+            Ok(seqno)
+        }
+        _ => Err(MessageSenderError::WalletNotDeployed.into()),
+    }
+}
+
+/// Pay transfer fees separately and ignore errors
+const WALLET_SEND_MODE: u8 = 3;
+
+/// Time after the wallet transaction during which the destination transaction
+/// must appear
+const DESTINATION_TIMEOUT_SEC: u32 = 60;
+
+#[derive(thiserror::Error, Debug)]
+enum MessageSenderError {
+    #[error("Wallet must be in the basechain")]
+    WalletNotInBasechain,
+    #[error("Wallet is not deployed")]
+    WalletNotDeployed,
+    #[error("Invalid wallet data")]
+    InvalidWalletData,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_hash(id: u8) -> UInt256 {
+        let mut hash = [0; 32];
+        hash[0] = id;
+        UInt256::from(hash)
+    }
+
+    fn make_outgoing() -> (Arc<PendingMessagesQueue>, Arc<OutgoingMessages>) {
+        let messages_queue = PendingMessagesQueue::new(10);
+        let outgoing = Arc::new(OutgoingMessages {
+            messages_queue: messages_queue.clone(),
+            entries: Default::default(),
+        });
+        (messages_queue, outgoing)
+    }
+
+    fn make_wallet_message(expire_at: u32) -> (SignedMessage, UInt256) {
+        let message =
+            ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+                dst: ton_block::MsgAddressInt::with_standart(None, 0, make_hash(1).into()).unwrap(),
+                ..Default::default()
+            });
+        let hash = message.serialize().unwrap().repr_hash();
+        (
+            SignedMessage {
+                account: make_hash(1),
+                message,
+                expire_at,
+            },
+            hash,
+        )
+    }
+
+    #[tokio::test]
+    async fn wallet_message_waits_for_destination() {
+        let (messages_queue, outgoing) = make_outgoing();
+        let (message, external_message_hash) = make_wallet_message(10);
+
+        let tracked = outgoing.track(&message).unwrap();
+        outgoing.handle_wallet_transaction(
+            &external_message_hash,
+            Some((make_hash(2), make_hash(3))),
+            100,
+        );
+        assert_eq!(messages_queue.len(), 1);
+
+        messages_queue.deliver_message(make_hash(2), make_hash(3), make_hash(4));
+        assert_eq!(
+            tracked.wait().await,
+            MessageStatus::Delivered {
+                transaction_hash: make_hash(4)
+            }
+        );
+
+        drop(tracked);
+        assert!(outgoing.entries.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn wallet_message_fails_with_destination() {
+        let (messages_queue, outgoing) = make_outgoing();
+        let (message, external_message_hash) = make_wallet_message(10);
+
+        let tracked = outgoing.track(&message).unwrap();
+        outgoing.handle_wallet_transaction(
+            &external_message_hash,
+            Some((make_hash(2), make_hash(3))),
+            100,
+        );
+
+        messages_queue.fail_message(make_hash(2), make_hash(3), 60, TransactionPhase::Compute);
+        assert_eq!(
+            tracked.wait().await,
+            MessageStatus::Failed {
+                exit_code: 60,
+                phase: TransactionPhase::Compute
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn wallet_message_without_outgoing_message_fails() {
+        let (messages_queue, outgoing) = make_outgoing();
+        let (message, external_message_hash) = make_wallet_message(10);
+
+        let tracked = outgoing.track(&message).unwrap();
+        outgoing.handle_wallet_transaction(&external_message_hash, None, 100);
+        assert_eq!(messages_queue.len(), 0);
+
+        assert_eq!(
+            tracked.wait().await,
+            MessageStatus::Failed {
+                exit_code: 0,
+                phase: TransactionPhase::Action
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn destination_expires() {
+        let (messages_queue, outgoing) = make_outgoing();
+        let (message, external_message_hash) = make_wallet_message(10);
+
+        let tracked = outgoing.track(&message).unwrap();
+        outgoing.handle_wallet_transaction(
+            &external_message_hash,
+            Some((make_hash(2), make_hash(3))),
+            100,
+        );
+
+        messages_queue.update(&ton_block::ShardIdent::masterchain(), 101);
+        assert_eq!(tracked.wait().await, MessageStatus::Expired);
+    }
+
+    #[test]
+    fn untracked_wallet_transactions_are_ignored() {
+        let (messages_queue, outgoing) = make_outgoing();
+        let (message, external_message_hash) = make_wallet_message(10);
+
+        // Transaction of the unknown external message (e.g. manual transfer)
+        outgoing.handle_wallet_transaction(
+            &external_message_hash,
+            Some((make_hash(2), make_hash(3))),
+            100,
+        );
+        assert_eq!(messages_queue.len(), 0);
+
+        // Outgoing message is registered only once
+        let _tracked = outgoing.track(&message).unwrap();
+        for _ in 0..2 {
+            outgoing.handle_wallet_transaction(
+                &external_message_hash,
+                Some((make_hash(2), make_hash(3))),
+                100,
+            );
+        }
+        assert_eq!(messages_queue.len(), 1);
+    }
+
+    #[test]
+    fn only_sender_agnostic_messages_use_wallet() {
+        let message = UnsignedMessage::new(staking_contract::end_election(), make_hash(1));
+        assert!(!message.is_sender_agnostic());
+        assert!(message.sender_agnostic().is_sender_agnostic());
+    }
+}
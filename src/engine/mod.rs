@@ -1,35 +1,50 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use parking_lot::Mutex;
 use pkey_mprotect::*;
-use tiny_adnl::utils::*;
-use tokio::sync::mpsc;
-use ton_block::Serializable;
+use tokio::sync::{mpsc, watch};
+use ton_block::{Deserializable, Serializable};
 
 use self::bridge::*;
 use self::eth_subscriber::*;
 use self::keystore::*;
 use self::metrics_exporter::*;
+use self::staker::*;
 use self::staking::*;
+use self::ton_backend::*;
+use self::ton_chain::*;
 use self::ton_contracts::*;
 use self::ton_subscriber::*;
 use crate::config::*;
 use crate::utils::*;
 
+pub use self::bridge::{EventInspection, InspectedVote};
+pub use self::ton_chain::TonChain;
+
+mod admin_api;
 mod bridge;
 mod eth_subscriber;
 mod keystore;
+mod message_sender;
 mod metrics_exporter;
+mod staker;
 mod staking;
+mod ton_backend;
+mod ton_chain;
 mod ton_contracts;
 mod ton_subscriber;
 
 pub struct Engine {
     metrics_exporter: Arc<MetricsExporter>,
+    admin_api_settings: Option<AdminApiConfig>,
     context: Arc<EngineContext>,
     bridge: Mutex<Option<Arc<Bridge>>>,
-    staking: Mutex<Option<Arc<Staking>>>,
+    /// Staking for each hosted staker
+    staking: Mutex<Vec<Arc<Staking>>>,
 }
 
 impl Engine {
@@ -38,24 +53,39 @@ impl Engine {
         global_config: ton_indexer::GlobalConfig,
         protection_keys: Arc<ProtectionKeys>,
         shutdown_requests_tx: ShutdownRequestsTx,
+        clock: Arc<dyn Clock>,
     ) -> Result<Arc<Self>> {
         let metrics_exporter =
             MetricsExporter::with_config(config.metrics_settings.clone()).await?;
+        let admin_api_settings = config.admin_api_settings.clone();
 
-        let context =
-            EngineContext::new(config, global_config, protection_keys, shutdown_requests_tx)
-                .await?;
+        let context = EngineContext::new(
+            config,
+            global_config,
+            protection_keys,
+            shutdown_requests_tx,
+            clock,
+        )
+        .await?;
 
         Ok(Arc::new(Self {
             metrics_exporter,
+            admin_api_settings,
             context,
             bridge: Mutex::new(None),
-            staking: Mutex::new(None),
+            staking: Default::default(),
         }))
     }
 
     pub async fn start(self: &Arc<Self>) -> Result<()> {
         self.start_metrics_exporter();
+        if let Some(config) = &self.admin_api_settings {
+            admin_api::serve(config, Arc::downgrade(self))?;
+        }
+
+        // NOTE: messages which were sent before restart must be registered before
+        // blocks processing starts, so that their transactions are not missed
+        let pending_messages = self.context.restore_pending_messages();
 
         // Sync node and subscribers
         self.context.start().await?;
@@ -65,7 +95,7 @@ impl Engine {
 
         let bridge_contract = match self
             .context
-            .ton_subscriber
+            .ton_chain
             .get_contract_state(bridge_account)
             .await?
         {
@@ -83,18 +113,90 @@ impl Engine {
             .context("Failed to init bridge")?;
         *self.bridge.lock() = Some(bridge);
 
-        // Initialize staking
-        let staking = Staking::new(self.context.clone(), bridge_details.staking)
-            .await
-            .context("Failed to init staking")?;
-        *self.staking.lock() = Some(staking);
+        // Initialize staking for each staker.
+        // NOTE: stakers are independent, so the failed one doesn't stop others
+        let staking_account = bridge_details.staking;
+        let staking = futures::future::join_all(
+            self.context
+                .stakers
+                .iter()
+                .map(|staker| Staking::new(self.context.clone(), staker.clone(), staking_account)),
+        )
+        .await
+        .into_iter()
+        .zip(self.context.stakers.iter())
+        .filter_map(|(result, staker)| match result {
+            Ok(staking) => Some(staking),
+            Err(e) => {
+                log::error!(
+                    "Failed to init staking for staker {}: {:?}",
+                    staker.staker_account_str,
+                    e
+                );
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+        if staking.is_empty() {
+            return Err(EngineError::StakingNotInitialized.into());
+        }
+        *self.staking.lock() = staking;
 
         self.context.eth_subscribers.start();
 
+        // Find events which were missed while relay was offline
+        let blocks_replayed = self.context.replay_missed_blocks().await;
+        self.context.start_blocks_replay_state_saver();
+
+        // Wait messages which were sent before restart
+        self.context
+            .spawn_pending_messages_reconciliation(pending_messages, blocks_replayed);
+
         // Done
         Ok(())
     }
 
+    /// Computes relay vote for the event contract using the last applied state
+    /// from the node DB. The DB is opened read-only, so nothing is sent and the
+    /// node is not started.
+    ///
+    /// NOTE: only the primary staker keys, the node DB and EVM networks are used
+    pub async fn inspect_event(
+        config: AppConfig,
+        protection_keys: Arc<ProtectionKeys>,
+        event_address: ton_block::MsgAddressInt,
+        secondary_db_path: Option<PathBuf>,
+    ) -> Result<EventInspection> {
+        let keystore = KeyStore::load(
+            &config.bridge_settings.keys_path,
+            config.master_password.clone(),
+            protection_keys,
+        )
+        .context("Failed to load keys")?;
+
+        let shard_accounts = match &config.ton_source {
+            TonSourceConfig::Node => {
+                ReadOnlyNodeBackend::new(&config.node_settings, secondary_db_path)?
+                    .load_last_applied_shard_accounts()
+                    .await
+                    .context("Failed to load shard states")?
+            }
+            _ => return Err(EngineError::NodeDbRequired.into()),
+        };
+
+        let eth_subscribers = EthSubscriberRegistry::new(config.bridge_settings.networks)
+            .await
+            .context("Failed to create EVM networks registry")?;
+
+        inspect_event_contract(
+            keystore.ton.public_key(),
+            &eth_subscribers,
+            &shard_accounts,
+            only_account_hash(&event_address),
+        )
+        .await
+    }
+
     pub async fn update_metrics_config(&self, config: Option<MetricsConfig>) -> Result<()> {
         self.metrics_exporter
             .reload(config)
@@ -113,7 +215,10 @@ impl Engine {
                     (Some(engine), Some(handle)) => {
                         let mut buffer = handle.buffers().acquire_buffer().await;
                         buffer.write(LabeledEthSubscriberMetrics(&engine.context));
+                        buffer.write(LabeledAddressVerificationMetrics(&engine.context));
                         buffer.write(LabeledTonSubscriberMetrics(&engine.context));
+                        buffer.write(LabeledEventsQueueMetrics(&engine.context));
+                        buffer.write(LabeledTransactionHandlersMetrics(&engine.context));
 
                         if let Some(bridge) = &*engine.bridge.lock() {
                             buffer.write(LabeledBridgeMetrics {
@@ -122,11 +227,8 @@ impl Engine {
                             });
                         }
 
-                        if let Some(staking) = &*engine.staking.lock() {
-                            buffer.write(LabeledStakingMetrics {
-                                context: &engine.context,
-                                staking,
-                            });
+                        for staking in &*engine.staking.lock() {
+                            buffer.write(LabeledStakingMetrics(staking));
                         }
 
                         drop(buffer);
@@ -144,19 +246,28 @@ impl Engine {
 
 pub struct EngineContext {
     pub shutdown_requests_tx: ShutdownRequestsTx,
-    pub staker_account_str: String,
-    pub staker_account: ton_types::UInt256,
+    /// Hosted stakers. Contains at least one item
+    pub stakers: Vec<Arc<StakerContext>>,
     pub settings: BridgeConfig,
-    pub keystore: Arc<KeyStore>,
     pub messages_queue: Arc<PendingMessagesQueue>,
-    pub ton_subscriber: Arc<TonSubscriber>,
-    pub ton_engine: Arc<ton_indexer::Engine>,
+    pub events_queues: Arc<AccountEventsQueues>,
+    /// Transactions subscriptions statistics
+    pub transaction_handlers: Arc<TransactionHandlers>,
+    pub ton_chain: Arc<dyn TonChain>,
+    /// Wall clock source. Virtual in tests
+    pub clock: Arc<dyn Clock>,
     pub eth_subscribers: Arc<EthSubscriberRegistry>,
+    /// Shared shard states snapshot
+    shard_accounts_cache: ShardAccountsCache,
+    /// Broadcasted external messages. Persisted to survive restarts
+    pending_messages_state: Mutex<PendingMessagesState>,
+    /// Background writer of the pending messages state snapshots
+    pending_messages_tx: watch::Sender<PendingMessagesState>,
 }
 
 impl Drop for EngineContext {
     fn drop(&mut self) {
-        self.ton_engine.shutdown();
+        self.ton_chain.shutdown();
     }
 }
 
@@ -166,65 +277,144 @@ impl EngineContext {
         global_config: ton_indexer::GlobalConfig,
         protection_keys: Arc<ProtectionKeys>,
         shutdown_requests_tx: ShutdownRequestsTx,
+        clock: Arc<dyn Clock>,
     ) -> Result<Arc<Self>> {
-        let staker_account =
-            ton_types::UInt256::from_be_bytes(&config.staker_address.address().get_bytestring(0));
-        let staker_account_str = config.staker_address.to_string();
+        let staker_profiles = config.staker_profiles()?;
         let settings = config.bridge_settings;
 
-        let keystore = KeyStore::new(&settings.keys_path, config.master_password, protection_keys)
-            .context("Failed to create keystore")?;
-
         let messages_queue = PendingMessagesQueue::new(16);
-
-        let ton_subscriber = TonSubscriber::new(messages_queue.clone());
-        let ton_engine = ton_indexer::Engine::new(
-            config
-                .node_settings
-                .build_indexer_config()
-                .await
-                .context("Failed to build node config")?,
+        let pending_messages_state =
+            PendingMessagesState::try_load(&settings.message_delivery.pending_messages_path)
+                .context("Failed to load pending messages")?;
+        let events_queues = AccountEventsQueues::new(settings.events_queue.clone());
+
+        // NOTE: live transactions are handled after the missed blocks are replayed
+        let ton_subscriber = TonSubscriber::new(
+            messages_queue.clone(),
+            &settings.transaction_handlers,
+            settings.blocks_replay.enabled,
+        );
+        let transaction_handlers = ton_subscriber.transaction_handlers().clone();
+        let ton_backend = create_ton_backend(
+            &config.ton_source,
+            config.node_settings,
             global_config,
-            vec![ton_subscriber.clone() as Arc<dyn ton_indexer::Subscriber>],
+            ton_subscriber.clone(),
+            clock.clone(),
         )
-        .await
-        .context("Failed to start TON node")?;
+        .await?;
+        let ton_chain: Arc<dyn TonChain> = LiveTonChain::new(ton_subscriber, ton_backend);
+
+        let mut stakers: Vec<Arc<StakerContext>> = Vec::with_capacity(staker_profiles.len());
+        for profile in staker_profiles {
+            let staker = StakerContext::new(
+                profile,
+                config.master_password.clone(),
+                protection_keys.clone(),
+                &ton_chain,
+                &messages_queue,
+            )?;
+            stakers.push(staker);
+        }
+
+        let shard_accounts_cache =
+            ShardAccountsCache::new(settings.shard_accounts_cache_max_age_sec, clock.clone());
 
         let eth_subscribers = EthSubscriberRegistry::new(settings.networks.clone())
             .await
             .context("Failed to create EVM networks registry")?;
 
+        let pending_messages_tx = start_json_writer(
+            "pending messages",
+            settings.message_delivery.pending_messages_path.clone(),
+            pending_messages_state.clone(),
+        );
+
         Ok(Arc::new(Self {
             shutdown_requests_tx,
-            staker_account_str,
-            staker_account,
+            stakers,
             settings,
-            keystore,
             messages_queue,
-            ton_subscriber,
-            ton_engine,
+            events_queues,
+            transaction_handlers,
+            ton_chain,
+            clock,
             eth_subscribers,
+            shard_accounts_cache,
+            pending_messages_tx,
+            pending_messages_state: Mutex::new(pending_messages_state),
+        }))
+    }
+
+    /// Context over the local chain. Used to drive the engine logic in virtual time
+    #[cfg(test)]
+    async fn new_in_memory(
+        settings: BridgeConfig,
+        stakers: Vec<Arc<StakerContext>>,
+        ton_chain: Arc<InMemoryTonChain>,
+        messages_queue: Arc<PendingMessagesQueue>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Arc<Self>> {
+        // NOTE: shutdown requests are ignored in tests
+        let (shutdown_requests_tx, _) = mpsc::unbounded_channel();
+
+        let events_queues = AccountEventsQueues::new(settings.events_queue.clone());
+        let transaction_handlers = TransactionHandlers::new(&settings.transaction_handlers);
+        let shard_accounts_cache =
+            ShardAccountsCache::new(settings.shard_accounts_cache_max_age_sec, clock.clone());
+        let eth_subscribers = EthSubscriberRegistry::new(settings.networks.clone()).await?;
+        let pending_messages_tx = start_json_writer(
+            "pending messages",
+            settings.message_delivery.pending_messages_path.clone(),
+            Default::default(),
+        );
+
+        Ok(Arc::new(Self {
+            shutdown_requests_tx,
+            stakers,
+            settings,
+            messages_queue,
+            events_queues,
+            transaction_handlers,
+            ton_chain,
+            clock,
+            eth_subscribers,
+            shard_accounts_cache,
+            pending_messages_tx,
+            pending_messages_state: Default::default(),
         }))
     }
 
     async fn start(&self) -> Result<()> {
-        self.ton_engine.start().await?;
-        self.ton_subscriber.start().await?;
-        Ok(())
+        self.ton_chain.start().await
     }
 
-    pub async fn get_all_shard_accounts(&self) -> Result<ShardAccountsMap> {
-        let shard_blocks = self.ton_subscriber.wait_shards(None).await?.block_ids;
+    /// The staker specified by `staker_address`. Used to label shared metrics
+    pub fn primary_staker(&self) -> &Arc<StakerContext> {
+        &self.stakers[0]
+    }
 
-        let mut shard_accounts =
-            FxHashMap::with_capacity_and_hasher(shard_blocks.len(), Default::default());
-        for (shard_ident, block_id) in shard_blocks {
-            let shard = self.ton_engine.wait_state(&block_id, None, false).await?;
-            let accounts = shard.state().read_accounts()?;
-            shard_accounts.insert(shard_ident, accounts);
-        }
+    /// Returns all shard states from the shared snapshot, optionally newer
+    /// than the specified masterchain block
+    pub async fn get_all_shard_accounts(
+        &self,
+        newer_than: Option<u32>,
+    ) -> Result<ShardAccountsMap> {
+        Ok(self
+            .get_shard_accounts_snapshot(newer_than)
+            .await?
+            .shard_accounts
+            .clone())
+    }
 
-        Ok(shard_accounts)
+    /// Returns the shared shard states snapshot, optionally newer than the specified block
+    pub async fn get_shard_accounts_snapshot(
+        &self,
+        newer_than: Option<u32>,
+    ) -> Result<Arc<ShardAccountsSnapshot>> {
+        self.shard_accounts_cache
+            .get(self.ton_chain.as_ref(), newer_than)
+            .await
     }
 
     pub async fn send_ton_message(
@@ -232,68 +422,634 @@ impl EngineContext {
         account: &ton_types::UInt256,
         message: &ton_block::Message,
         expire_at: u32,
+        kind: MessageKind,
     ) -> Result<MessageStatus> {
-        let to = match message.header() {
-            ton_block::CommonMsgInfo::ExtInMsgInfo(header) => {
-                ton_block::AccountIdPrefixFull::prefix(&header.dst)?
+        let to = external_message_dst(message)?;
+
+        let cells = message.write_to_new_cell()?.into();
+        let boc = ton_types::serialize_toc(&cells)?;
+
+        self.broadcast_pending_message(
+            StoredPendingMessage {
+                account: *account,
+                message_hash: cells.repr_hash(),
+                expire_at,
+                last_transaction_lt: self.ton_chain.last_known_transaction_lt(account),
+                kind: kind.to_string(),
+                boc,
+            },
+            &to,
+        )
+        .await
+    }
+
+    /// Registers messages which were sent before restart, so that they are
+    /// found in the live and replayed blocks. Expired messages are skipped
+    fn restore_pending_messages(&self) -> RestoredPendingMessages {
+        let now = self.clock.now_sec();
+
+        let messages = {
+            let mut state = self.pending_messages_state.lock();
+            state.messages.retain(|message| {
+                let alive = message.expire_at > now;
+                if !alive {
+                    log::warn!(
+                        "Pending {} message to account {:x} expired while relay was offline",
+                        message.kind,
+                        message.account
+                    );
+                }
+                alive
+            });
+            state.messages.clone()
+        };
+        self.save_pending_messages_state();
+
+        let observer = Arc::new(PendingMessagesObserver);
+
+        let mut items = Vec::with_capacity(messages.len());
+        for message in messages {
+            match self.messages_queue.add_message(
+                message.account,
+                message.message_hash,
+                message.expire_at,
+            ) {
+                Ok(rx) => items.push((message, rx)),
+                Err(e) => log::error!(
+                    "Failed to restore pending {} message to account {:x}: {:?}",
+                    message.kind,
+                    message.account,
+                    e
+                ),
+            }
+        }
+
+        // NOTE: messages are only found for the subscribed accounts
+        self.ton_chain.add_transactions_subscription(
+            items.iter().map(|(message, _)| message.account),
+            &observer,
+        );
+
+        RestoredPendingMessages { observer, items }
+    }
+
+    /// Waits for messages which were sent before restart in background.
+    /// Messages which were not found in blocks are rebroadcasted
+    fn spawn_pending_messages_reconciliation(
+        self: &Arc<Self>,
+        pending_messages: RestoredPendingMessages,
+        blocks_replayed: bool,
+    ) {
+        let RestoredPendingMessages { observer, items } = pending_messages;
+        if items.is_empty() {
+            return;
+        }
+
+        log::info!("Waiting for {} pending messages", items.len());
+
+        let context = self.clone();
+        tokio::spawn(async move {
+            futures::future::join_all(items.into_iter().map(|(message, rx)| {
+                context.reconcile_pending_message(message, rx, blocks_replayed)
+            }))
+            .await;
+
+            // NOTE: subscription is removed after all messages are resolved
+            drop(observer);
+            log::info!("Finished waiting for pending messages");
+        });
+    }
+
+    async fn reconcile_pending_message(
+        &self,
+        message: StoredPendingMessage,
+        mut rx: MessageStatusRx,
+        blocks_replayed: bool,
+    ) {
+        let (account, message_hash) = (message.account, message.message_hash);
+
+        let result = match futures::FutureExt::now_or_never(&mut rx) {
+            // Message was already found in the replayed or live blocks
+            Some(status) => status.map_err(anyhow::Error::from),
+            None => match self.should_rebroadcast(&message, blocks_replayed).await {
+                Ok(true) => match parse_external_message_dst(&message.boc) {
+                    Ok(to) => match self
+                        .ton_chain
+                        .broadcast_external_message(&to, &message.boc)
+                        .await
+                    {
+                        Ok(()) => rx.await.map_err(anyhow::Error::from),
+                        Err(e) => Err(e),
+                    },
+                    Err(e) => Err(e),
+                },
+                // Only wait until the message is found or expired
+                Ok(false) => rx.await.map_err(anyhow::Error::from),
+                Err(e) => Err(e),
+            },
+        };
+
+        match result {
+            Ok(status) => log::info!(
+                "Pending {} message to account {:x}: {:?}",
+                message.kind,
+                account,
+                status
+            ),
+            Err(e) => log::error!(
+                "Failed to reconcile pending {} message to account {:x}: {:?}",
+                message.kind,
+                account,
+                e
+            ),
+        }
+
+        self.pending_messages_state
+            .lock()
+            .remove(&account, &message_hash);
+        self.save_pending_messages_state();
+    }
+
+    /// Checks that the message could not be delivered while relay was offline
+    async fn should_rebroadcast(
+        &self,
+        message: &StoredPendingMessage,
+        blocks_replayed: bool,
+    ) -> Result<bool> {
+        // NOTE: all transactions since restart were already checked if blocks were replayed
+        if blocks_replayed {
+            return Ok(true);
+        }
+
+        let current_lt = self
+            .ton_chain
+            .get_contract_state(message.account)
+            .await?
+            .map(|contract| contract.last_transaction_id.lt());
+
+        let has_new_transactions = match (message.last_transaction_lt, current_lt) {
+            (Some(saved_lt), Some(current_lt)) => current_lt > saved_lt,
+            // Account was not deployed
+            (_, None) => false,
+            // Can't check state saved by the older versions
+            (None, Some(_)) => true,
+        };
+
+        if has_new_transactions {
+            log::warn!(
+                "Pending {} message to account {:x} might have been delivered while relay was offline. \
+                It will not be rebroadcasted",
+                message.kind,
+                message.account
+            );
+        }
+        Ok(!has_new_transactions)
+    }
+
+    /// Passes transactions from blocks which were applied while relay was offline
+    /// to subscriptions and then enables live dispatch. Must be called after all
+    /// subscriptions are created.
+    ///
+    /// Returns whether all missed blocks were replayed
+    async fn replay_missed_blocks(&self) -> bool {
+        let complete = self.replay_blocks_before_live().await;
+        self.ton_chain.resume_live_dispatch().await;
+        complete
+    }
+
+    async fn replay_blocks_before_live(&self) -> bool {
+        let config = &self.settings.blocks_replay;
+        if !config.enabled {
+            return false;
+        }
+
+        let last_processed_mc_seqno = match BlocksReplayState::try_load(&config.state_path) {
+            Ok(Some(state)) => state.last_processed_mc_seqno,
+            // NOTE: it is unknown which blocks were missed (e.g. the first start after
+            // enabling replay), so pending messages must still be checked
+            Ok(None) => return false,
+            Err(e) => {
+                log::error!("Failed to load blocks replay state: {:?}", e);
+                return false;
             }
-            _ => return Err(EngineError::ExternalTonMessageExpected.into()),
         };
 
-        let cells = message.write_to_new_cell()?.into();
-        let serialized = ton_types::serialize_toc(&cells)?;
+        // NOTE: transactions of the first live block and all later ones are deferred
+        let first_live_mc_seqno = self.ton_chain.first_live_mc_seqno().await;
+        if last_processed_mc_seqno + 1 >= first_live_mc_seqno {
+            return true;
+        }
+        let to = first_live_mc_seqno - 1;
+
+        let mut complete = true;
+        let mut from = last_processed_mc_seqno + 1;
+        if to - from >= config.max_blocks {
+            from = to.saturating_sub(config.max_blocks) + 1;
+            complete = false;
+            log::warn!(
+                "Too many missed blocks. Masterchain blocks {}..{} will be skipped",
+                last_processed_mc_seqno + 1,
+                from
+            );
+        }
+
+        log::info!("Replaying masterchain blocks {}..={}", from, to);
+        match self.ton_chain.replay_blocks(from, to).await {
+            Ok(()) => {
+                log::info!("Finished replaying masterchain blocks");
+                complete
+            }
+            Err(e) => {
+                log::error!("Failed to replay blocks: {:?}", e);
+                false
+            }
+        }
+    }
+
+    fn start_blocks_replay_state_saver(self: &Arc<Self>) {
+        if !self.settings.blocks_replay.enabled {
+            return;
+        }
+
+        let clock = self.clock.clone();
+        let context = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                clock
+                    .sleep(Duration::from_secs(BLOCKS_REPLAY_STATE_SAVE_INTERVAL))
+                    .await;
+
+                let context = match context.upgrade() {
+                    Some(context) => context,
+                    None => return,
+                };
+
+                let last_processed_mc_seqno = context.ton_chain.last_processed_mc_seqno();
+                if last_processed_mc_seqno == 0 {
+                    continue;
+                }
+
+                let state = BlocksReplayState {
+                    last_processed_mc_seqno,
+                };
+                if let Err(e) = state.save(&context.settings.blocks_replay.state_path) {
+                    log::error!("Failed to save blocks replay state: {:?}", e);
+                }
+            }
+        });
+    }
+
+    async fn broadcast_pending_message(
+        &self,
+        message: StoredPendingMessage,
+        to: &ton_block::AccountIdPrefixFull,
+    ) -> Result<MessageStatus> {
+        let (account, message_hash) = (message.account, message.message_hash);
 
         let rx = self
             .messages_queue
-            .add_message(*account, cells.repr_hash(), expire_at)?;
+            .add_message(account, message_hash, message.expire_at)?;
 
-        self.ton_engine
-            .broadcast_external_message(&to, &serialized)
-            .await?;
+        let boc = message.boc.clone();
+        self.pending_messages_state.lock().messages.push(message);
+        self.save_pending_messages_state();
+
+        let result = match self.ton_chain.broadcast_external_message(to, &boc).await {
+            Ok(()) => rx.await.map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
 
-        let status = rx.await?;
-        Ok(status)
+        self.pending_messages_state
+            .lock()
+            .remove(&account, &message_hash);
+        self.save_pending_messages_state();
+
+        result
+    }
+
+    /// Passes the state snapshot to the background writer
+    fn save_pending_messages_state(&self) {
+        let state = self.pending_messages_state.lock().clone();
+        self.pending_messages_tx.send(state).ok();
+    }
+
+    fn delivery_policy(&self, kind: MessageKind) -> MessageDeliveryPolicy {
+        let config = &self.settings.message_delivery;
+        match kind {
+            MessageKind::EventVote => config.event_votes,
+            MessageKind::Elections => config.elections,
+            MessageKind::Reward => config.rewards,
+            MessageKind::Verification => config.verification,
+        }
     }
 
     async fn deliver_message<T, F>(
         self: &Arc<Self>,
+        staker: &StakerContext,
         observer: Arc<AccountObserver<T>>,
+        kind: MessageKind,
         unsigned_message: UnsignedMessage,
-        mut condition: F,
+        condition: F,
     ) -> Result<()>
     where
         T: Send + 'static,
         F: FnMut() -> bool + 'static,
     {
+        self.deliver_message_tracked(staker, observer, kind, unsigned_message, condition)
+            .await
+            .map(|_| ())
+    }
+
+    /// Same as `deliver_message`, but returns the hash of the transaction
+    /// in which the message was delivered. `None` if it was not sent due to the condition
+    async fn deliver_message_tracked<T, F>(
+        self: &Arc<Self>,
+        staker: &StakerContext,
+        observer: Arc<AccountObserver<T>>,
+        kind: MessageKind,
+        unsigned_message: UnsignedMessage,
+        condition: F,
+    ) -> Result<Option<ton_types::UInt256>>
+    where
+        T: Send + 'static,
+        F: FnMut() -> bool + 'static,
+    {
+        self.deliver_message_impl(staker, None, observer, kind, unsigned_message, condition)
+            .await
+    }
+
+    /// Same as `deliver_message`, but signs the message with the specified keys
+    /// instead of the current staker keys (e.g. the next keys during the rotation)
+    async fn deliver_message_signed_by<T, F>(
+        self: &Arc<Self>,
+        staker: &StakerContext,
+        keystore: &KeyStore,
+        observer: Arc<AccountObserver<T>>,
+        kind: MessageKind,
+        unsigned_message: UnsignedMessage,
+        condition: F,
+    ) -> Result<()>
+    where
+        T: Send + 'static,
+        F: FnMut() -> bool + 'static,
+    {
+        self.deliver_message_impl(
+            staker,
+            Some(keystore),
+            observer,
+            kind,
+            unsigned_message,
+            condition,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    async fn deliver_message_impl<T, F>(
+        self: &Arc<Self>,
+        staker: &StakerContext,
+        keystore: Option<&KeyStore>,
+        observer: Arc<AccountObserver<T>>,
+        kind: MessageKind,
+        unsigned_message: UnsignedMessage,
+        mut condition: F,
+    ) -> Result<Option<ton_types::UInt256>>
+    where
+        T: Send + 'static,
+        F: FnMut() -> bool + 'static,
+    {
+        let policy = self.delivery_policy(kind);
+        let stats = staker.delivery_stats.entry(kind).or_default().clone();
+
+        let started_at = self.clock.now_sec();
+        let mut attempts = DeliveryAttempts::new(policy);
+        let mut delivered_in = None;
+
         // Check if message should be sent
         while condition() {
+            // Check policy limits
+            let elapsed_sec = self.clock.now_sec().saturating_sub(started_at) as u64;
+            if let Err(e) = attempts.start_next(elapsed_sec) {
+                stats.gave_up.fetch_add(1, Ordering::Release);
+                return Err(e.into());
+            }
+            stats.attempts.fetch_add(1, Ordering::Release);
+
             // Prepare and send the message
             // NOTE: it must be signed every time before sending because it uses current
             // timestamp in headers. It will not work outside this loop
-            let message = self.keystore.ton.sign(&unsigned_message)?;
+            // NOTE: prepared message is held until the end of the attempt
+            // because the wallet sender might require exclusive access
+            let current_keystore;
+            let keystore = match keystore {
+                Some(keystore) => keystore,
+                None => {
+                    current_keystore = staker.keystore.current();
+                    current_keystore.as_ref()
+                }
+            };
+            let prepared = staker
+                .message_sender
+                .prepare(
+                    keystore,
+                    self.ton_chain.as_ref(),
+                    self.clock.as_ref(),
+                    &unsigned_message,
+                    policy.ttl_sec,
+                )
+                .await?;
+            let message = &prepared.message;
 
-            match self
-                .send_ton_message(&message.account, &message.message, message.expire_at)
-                .await?
-            {
+            let status = self
+                .send_ton_message(&message.account, &message.message, message.expire_at, kind)
+                .await?;
+
+            // NOTE: wallet messages are delivered only after the destination transaction
+            match prepared.wait_destination(status).await {
                 MessageStatus::Expired => {
                     // Do nothing on expire and just retry
-                    log::warn!("Message to account {:x} expired", message.account);
+                    log::warn!(
+                        "Message {} to account {:x} expired",
+                        unsigned_message.function_name(),
+                        message.account
+                    );
+                    stats.expired.fetch_add(1, Ordering::Release);
                 }
-                MessageStatus::Delivered => {
-                    log::info!("Successfully sent message to account {:x}", message.account);
+                MessageStatus::Delivered { transaction_hash } => {
+                    log::info!(
+                        "Successfully sent message to account {:x} in transaction {:x}",
+                        message.account,
+                        transaction_hash
+                    );
+                    stats.delivered.fetch_add(1, Ordering::Release);
+                    delivered_in = Some(transaction_hash);
                     break;
                 }
+                MessageStatus::Failed { exit_code, phase } => {
+                    let function = unsigned_message.function_name();
+                    log::error!(
+                        "Message {} to account {:x} failed in {} phase with exit code {}",
+                        function,
+                        message.account,
+                        phase,
+                        exit_code
+                    );
+
+                    {
+                        let mut stats = staker.failed_messages.entry(function).or_default();
+                        stats.count += 1;
+                        stats.last_exit_code = exit_code;
+                    }
+
+                    if let Err(e) = attempts.on_failed(exit_code, phase) {
+                        stats.gave_up.fetch_add(1, Ordering::Release);
+                        return Err(e.into());
+                    }
+                }
+            }
+
+            drop(prepared);
+
+            // Wait before the next attempt
+            let backoff = attempts.next_backoff();
+            if !backoff.is_zero() {
+                self.clock.sleep(backoff).await;
             }
         }
 
         // Make sure that observer is living enough. Messages will not be found
         // if it is deleted too early
         drop(observer);
+        Ok(delivered_in)
+    }
+}
+
+/// Messages which were sent before restart with their delivery status receivers
+struct RestoredPendingMessages {
+    /// Keeps destination accounts subscribed
+    observer: Arc<PendingMessagesObserver>,
+    items: Vec<(StoredPendingMessage, MessageStatusRx)>,
+}
+
+/// Transactions subscription which is only used to find external messages
+struct PendingMessagesObserver;
+
+impl TransactionsSubscription for PendingMessagesObserver {
+    fn handle_transaction(&self, _: TxContext<'_>) -> Result<()> {
         Ok(())
     }
 }
 
+/// Delivery policy limits for the single message
+struct DeliveryAttempts {
+    policy: MessageDeliveryPolicy,
+    attempts: u32,
+    failed_attempts: u32,
+    backoff: Duration,
+}
+
+impl DeliveryAttempts {
+    fn new(policy: MessageDeliveryPolicy) -> Self {
+        Self {
+            policy,
+            attempts: 0,
+            failed_attempts: 0,
+            backoff: Duration::from_secs(policy.min_backoff_sec),
+        }
+    }
+
+    /// Checks limits before the next attempt
+    fn start_next(&mut self, elapsed_sec: u64) -> Result<(), EngineError> {
+        if let Some(max_attempts) = self.policy.max_attempts {
+            if self.attempts >= max_attempts {
+                return Err(EngineError::TooManyAttempts {
+                    attempts: self.attempts,
+                });
+            }
+        }
+        if let Some(deadline) = self.policy.deadline_sec {
+            if elapsed_sec >= deadline {
+                return Err(EngineError::DeliveryDeadlineExceeded);
+            }
+        }
+
+        self.attempts += 1;
+        Ok(())
+    }
+
+    /// Counts attempt which resulted in an aborted transaction
+    fn on_failed(&mut self, exit_code: i32, phase: TransactionPhase) -> Result<(), EngineError> {
+        self.failed_attempts += 1;
+        if self.failed_attempts >= self.policy.max_failed_attempts {
+            Err(EngineError::MessageFailed { exit_code, phase })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the delay before the next attempt
+    fn next_backoff(&mut self) -> Duration {
+        let backoff = self.backoff;
+        self.backoff = std::cmp::min(
+            self.backoff * 2,
+            Duration::from_secs(self.policy.max_backoff_sec),
+        );
+        backoff
+    }
+}
+
+/// Interval in seconds between saving the last processed masterchain block
+const BLOCKS_REPLAY_STATE_SAVE_INTERVAL: u64 = 10;
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FailedMessagesStats {
+    pub count: usize,
+    pub last_exit_code: i32,
+}
+
+fn external_message_dst(message: &ton_block::Message) -> Result<ton_block::AccountIdPrefixFull> {
+    match message.header() {
+        ton_block::CommonMsgInfo::ExtInMsgInfo(header) => {
+            Ok(ton_block::AccountIdPrefixFull::prefix(&header.dst)?)
+        }
+        _ => Err(EngineError::ExternalTonMessageExpected.into()),
+    }
+}
+
+fn parse_external_message_dst(boc: &[u8]) -> Result<ton_block::AccountIdPrefixFull> {
+    let cell = ton_types::deserialize_tree_of_cells(&mut std::io::Cursor::new(boc))?;
+    let message = ton_block::Message::construct_from_cell(cell)?;
+    external_message_dst(&message)
+}
+
+/// External message purpose. Used to select the delivery policy
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum MessageKind {
+    EventVote,
+    Elections,
+    Reward,
+    Verification,
+}
+
+impl std::fmt::Display for MessageKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EventVote => f.write_str("event_vote"),
+            Self::Elections => f.write_str("elections"),
+            Self::Reward => f.write_str("reward"),
+            Self::Verification => f.write_str("verification"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct MessageDeliveryStats {
+    attempts: AtomicUsize,
+    delivered: AtomicUsize,
+    expired: AtomicUsize,
+    gave_up: AtomicUsize,
+}
+
 struct LabeledBridgeMetrics<'a> {
     context: &'a EngineContext,
     bridge: &'a Bridge,
@@ -302,49 +1058,64 @@ struct LabeledBridgeMetrics<'a> {
 impl std::fmt::Display for LabeledBridgeMetrics<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let metrics = self.bridge.metrics();
+        let staker = &self.context.primary_staker().staker_account_str;
 
         f.begin_metric("bridge_pending_eth_event_count")
-            .label(LABEL_STAKER, &self.context.staker_account_str)
+            .label(LABEL_STAKER, staker)
             .value(metrics.pending_eth_event_count)?;
 
         f.begin_metric("bridge_pending_ton_event_count")
-            .label(LABEL_STAKER, &self.context.staker_account_str)
+            .label(LABEL_STAKER, staker)
             .value(metrics.pending_ton_event_count)?;
 
         f.begin_metric("bridge_total_active_eth_event_configurations")
-            .label(LABEL_STAKER, &self.context.staker_account_str)
+            .label(LABEL_STAKER, staker)
             .value(metrics.total_active_eth_event_configurations)?;
 
         f.begin_metric("bridge_total_active_ton_event_configurations")
-            .label(LABEL_STAKER, &self.context.staker_account_str)
+            .label(LABEL_STAKER, staker)
             .value(metrics.total_active_ton_event_configurations)?;
 
+        f.begin_metric("bridge_skipped_eth_event_votes")
+            .label(LABEL_STAKER, staker)
+            .value(metrics.skipped_eth_event_votes)?;
+
+        f.begin_metric("bridge_max_concurrent_event_updates")
+            .label(LABEL_STAKER, staker)
+            .value(metrics.scheduler.max_concurrent_tasks)?;
+
+        f.begin_metric("bridge_running_event_updates")
+            .label(LABEL_STAKER, staker)
+            .value(metrics.scheduler.running_tasks)?;
+
+        f.begin_metric("bridge_queued_event_updates")
+            .label(LABEL_STAKER, staker)
+            .value(metrics.scheduler.queued_tasks)?;
+
         Ok(())
     }
 }
 
-struct LabeledStakingMetrics<'a> {
-    context: &'a EngineContext,
-    staking: &'a Staking,
-}
+struct LabeledStakingMetrics<'a>(&'a Staking);
 
 impl std::fmt::Display for LabeledStakingMetrics<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let metrics = self.staking.metrics();
+        let metrics = self.0.metrics();
+        let staker = &self.0.staker().staker_account_str;
 
         f.begin_metric("staking_user_data_tokens_balance")
-            .label(LABEL_STAKER, &self.context.staker_account_str)
+            .label(LABEL_STAKER, staker)
             .label(LABEL_ROUND_NUM, metrics.current_relay_round)
             .value(metrics.user_data_tokens_balance)?;
 
         f.begin_metric("staking_current_relay_round")
-            .label(LABEL_STAKER, &self.context.staker_account_str)
+            .label(LABEL_STAKER, staker)
             .value(metrics.current_relay_round)?;
 
         let status = match metrics.elections_state {
             ElectionsState::NotStarted { start_time } => {
                 f.begin_metric("staking_elections_start_time")
-                    .label(LABEL_STAKER, &self.context.staker_account_str)
+                    .label(LABEL_STAKER, staker)
                     .label(LABEL_ROUND_NUM, metrics.current_relay_round)
                     .value(start_time)?;
                 0
@@ -354,11 +1125,11 @@ impl std::fmt::Display for LabeledStakingMetrics<'_> {
                 end_time,
             } => {
                 f.begin_metric("staking_elections_start_time")
-                    .label(LABEL_STAKER, &self.context.staker_account_str)
+                    .label(LABEL_STAKER, staker)
                     .label(LABEL_ROUND_NUM, metrics.current_relay_round)
                     .value(start_time)?;
                 f.begin_metric("staking_elections_end_time")
-                    .label(LABEL_STAKER, &self.context.staker_account_str)
+                    .label(LABEL_STAKER, staker)
                     .label(LABEL_ROUND_NUM, metrics.current_relay_round)
                     .value(end_time)?;
                 1
@@ -367,29 +1138,114 @@ impl std::fmt::Display for LabeledStakingMetrics<'_> {
         };
 
         f.begin_metric("staking_elections_status")
-            .label(LABEL_STAKER, &self.context.staker_account_str)
+            .label(LABEL_STAKER, staker)
             .label(LABEL_ROUND_NUM, &metrics.current_relay_round)
             .value(status)?;
 
         f.begin_metric("staking_ignore_elections")
-            .label(LABEL_STAKER, &self.context.staker_account_str)
+            .label(LABEL_STAKER, staker)
             .label(LABEL_ROUND_NUM, &metrics.current_relay_round)
             .value(metrics.ignore_elections as u8)?;
 
+        f.begin_metric("staking_skip_next_election")
+            .label(LABEL_STAKER, staker)
+            .value(metrics.skip_next_election as u8)?;
+
+        f.begin_metric("staking_keys_rotation_in_progress")
+            .label(LABEL_STAKER, staker)
+            .value(metrics.keys_rotation_in_progress as u8)?;
+
+        f.begin_metric("staking_required_deposit")
+            .label(LABEL_STAKER, staker)
+            .label(LABEL_ROUND_NUM, &metrics.current_relay_round)
+            .value(metrics.required_deposit)?;
+
+        if let Some(decision) = metrics.last_election_decision {
+            f.begin_metric("staking_election_participation")
+                .label(LABEL_STAKER, staker)
+                .label(LABEL_ROUND_NUM, decision.round_num)
+                .value(decision.skip_reason.is_none() as u8)?;
+
+            if let Some(reason) = decision.skip_reason {
+                f.begin_metric("staking_election_skip_reason")
+                    .label(LABEL_STAKER, staker)
+                    .label(LABEL_ROUND_NUM, decision.round_num)
+                    .label(LABEL_REASON, reason)
+                    .value(1)?;
+            }
+        }
+
         if let Some(participates_in_round) = metrics.participates_in_round {
             f.begin_metric("staking_participates_in_round")
-                .label(LABEL_STAKER, &self.context.staker_account_str)
+                .label(LABEL_STAKER, staker)
                 .label(LABEL_ROUND_NUM, &metrics.current_relay_round)
                 .value(participates_in_round as u8)?;
         }
 
         if let Some(elected) = metrics.elected {
             f.begin_metric("staking_elected")
-                .label(LABEL_STAKER, &self.context.staker_account_str)
+                .label(LABEL_STAKER, staker)
                 .label(LABEL_ROUND_NUM, metrics.current_relay_round)
                 .value(elected as u8)?;
         }
 
+        for (round_num, reward) in metrics.relay_round_rewards {
+            f.begin_metric("staking_relay_round_reward")
+                .label(LABEL_STAKER, staker)
+                .label(LABEL_ROUND_NUM, round_num)
+                .value(reward.amount)?;
+
+            f.begin_metric("staking_relay_round_reward_claimed")
+                .label(LABEL_STAKER, staker)
+                .label(LABEL_ROUND_NUM, round_num)
+                .value(reward.claimed as u8)?;
+        }
+
+        for (reward_round, balance) in metrics.user_reward_balances.into_iter().enumerate() {
+            f.begin_metric("staking_user_reward_balance")
+                .label(LABEL_STAKER, staker)
+                .label(LABEL_REWARD_ROUND, reward_round)
+                .value(balance)?;
+        }
+
+        if metrics.alerts.relay_lock_until > 0 {
+            f.begin_metric("staking_relay_lock_until")
+                .label(LABEL_STAKER, staker)
+                .value(metrics.alerts.relay_lock_until)?;
+        }
+
+        for (action, call_metrics) in [
+            ("start", metrics.elections_calls.start),
+            ("end", metrics.elections_calls.end),
+        ] {
+            f.begin_metric("staking_elections_call_triggered")
+                .label(LABEL_STAKER, staker)
+                .label(LABEL_ACTION, action)
+                .value(call_metrics.triggered)?;
+
+            f.begin_metric("staking_elections_call_preempted")
+                .label(LABEL_STAKER, staker)
+                .label(LABEL_ACTION, action)
+                .value(call_metrics.preempted)?;
+
+            f.begin_metric("staking_elections_call_failed")
+                .label(LABEL_STAKER, staker)
+                .label(LABEL_ACTION, action)
+                .value(call_metrics.failed)?;
+        }
+
+        for (alert, alert_metrics) in metrics.alerts.alerts {
+            f.begin_metric("staking_alert_active")
+                .label(LABEL_STAKER, staker)
+                .label(LABEL_ALERT, alert)
+                .value(alert_metrics.active as u8)?;
+
+            f.begin_metric("staking_alert_count")
+                .label(LABEL_STAKER, staker)
+                .label(LABEL_ALERT, alert)
+                .value(alert_metrics.count)?;
+        }
+
         Ok(())
     }
 }
@@ -398,51 +1254,164 @@ struct LabeledTonSubscriberMetrics<'a>(&'a EngineContext);
 
 impl std::fmt::Display for LabeledTonSubscriberMetrics<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        use std::sync::atomic::Ordering;
-
-        let metrics = self.0.ton_subscriber.metrics();
-        let indexer_metrics = self.0.ton_engine.metrics();
+        let TonChainMetrics {
+            subscriber: metrics,
+            backend: backend_metrics,
+        } = self.0.ton_chain.metrics();
 
         f.begin_metric("ton_subscriber_ready")
-            .label(LABEL_STAKER, &self.0.staker_account_str)
+            .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
             .value(metrics.ready as u8)?;
 
         if metrics.current_utime > 0 {
-            let mc_time_diff = indexer_metrics.mc_time_diff.load(Ordering::Acquire);
-            let shard_client_time_diff = indexer_metrics
-                .shard_client_time_diff
-                .load(Ordering::Acquire);
-
-            let last_mc_block_seqno = indexer_metrics.last_mc_block_seqno.load(Ordering::Acquire);
-            let last_shard_client_mc_block_seqno = indexer_metrics
-                .last_shard_client_mc_block_seqno
-                .load(Ordering::Acquire);
-
             f.begin_metric("ton_subscriber_current_utime")
-                .label(LABEL_STAKER, &self.0.staker_account_str)
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
                 .value(metrics.current_utime)?;
 
             f.begin_metric("ton_subscriber_time_diff")
-                .label(LABEL_STAKER, &self.0.staker_account_str)
-                .value(mc_time_diff)?;
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .value(backend_metrics.mc_time_diff)?;
 
             f.begin_metric("ton_subscriber_shard_client_time_diff")
-                .label(LABEL_STAKER, &self.0.staker_account_str)
-                .value(shard_client_time_diff)?;
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .value(backend_metrics.shard_client_time_diff)?;
 
             f.begin_metric("ton_subscriber_mc_block_seqno")
-                .label(LABEL_STAKER, &self.0.staker_account_str)
-                .value(last_mc_block_seqno)?;
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .value(backend_metrics.last_mc_block_seqno)?;
 
             f.begin_metric("ton_subscriber_shard_client_mc_block_seqno")
-                .label(LABEL_STAKER, &self.0.staker_account_str)
-                .value(last_shard_client_mc_block_seqno)?;
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .value(backend_metrics.last_shard_client_mc_block_seqno)?;
         }
 
         f.begin_metric("ton_subscriber_pending_message_count")
-            .label(LABEL_STAKER, &self.0.staker_account_str)
+            .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
             .value(metrics.pending_message_count)?;
 
+        f.begin_metric("ton_subscriber_dispatch_queue_len")
+            .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+            .value(metrics.dispatch_queue_len)?;
+
+        for staker in &self.0.stakers {
+            let staker_account = &staker.staker_account_str;
+
+            for item in staker.delivery_stats.iter() {
+                let kind = item.key();
+                let stats = item.value();
+
+                f.begin_metric("ton_subscriber_message_attempts")
+                    .label(LABEL_STAKER, staker_account)
+                    .label(LABEL_MESSAGE_KIND, kind)
+                    .value(stats.attempts.load(Ordering::Acquire))?;
+
+                f.begin_metric("ton_subscriber_message_delivered")
+                    .label(LABEL_STAKER, staker_account)
+                    .label(LABEL_MESSAGE_KIND, kind)
+                    .value(stats.delivered.load(Ordering::Acquire))?;
+
+                f.begin_metric("ton_subscriber_message_expired")
+                    .label(LABEL_STAKER, staker_account)
+                    .label(LABEL_MESSAGE_KIND, kind)
+                    .value(stats.expired.load(Ordering::Acquire))?;
+
+                f.begin_metric("ton_subscriber_message_gave_up")
+                    .label(LABEL_STAKER, staker_account)
+                    .label(LABEL_MESSAGE_KIND, kind)
+                    .value(stats.gave_up.load(Ordering::Acquire))?;
+            }
+
+            for item in staker.failed_messages.iter() {
+                f.begin_metric("ton_subscriber_failed_message_count")
+                    .label(LABEL_STAKER, staker_account)
+                    .label(LABEL_FUNCTION, item.key())
+                    .value(item.count)?;
+
+                f.begin_metric("ton_subscriber_failed_message_last_exit_code")
+                    .label(LABEL_STAKER, staker_account)
+                    .label(LABEL_FUNCTION, item.key())
+                    .value(item.last_exit_code)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct LabeledEventsQueueMetrics<'a>(&'a EngineContext);
+
+impl std::fmt::Display for LabeledEventsQueueMetrics<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (listener, metrics) in self.0.events_queues.metrics() {
+            f.begin_metric("events_queue_capacity")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_LISTENER, listener)
+                .value(metrics.capacity)?;
+
+            f.begin_metric("events_queue_depth")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_LISTENER, listener)
+                .value(metrics.depth)?;
+
+            f.begin_metric("events_queue_processed")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_LISTENER, listener)
+                .value(metrics.processed)?;
+
+            f.begin_metric("events_queue_dropped")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_LISTENER, listener)
+                .value(metrics.dropped)?;
+
+            f.begin_metric("events_queue_overflowed")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_LISTENER, listener)
+                .value(metrics.overflowed)?;
+
+            f.begin_metric("events_queue_wait_time_us")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_LISTENER, listener)
+                .value(metrics.wait_time_us)?;
+
+            f.begin_metric("events_queue_processing_time_us")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_LISTENER, listener)
+                .value(metrics.processing_time_us)?;
+
+            f.begin_metric("events_queue_max_processing_time_us")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_LISTENER, listener)
+                .value(metrics.max_processing_time_us)?;
+        }
+        Ok(())
+    }
+}
+
+struct LabeledTransactionHandlersMetrics<'a>(&'a EngineContext);
+
+impl std::fmt::Display for LabeledTransactionHandlersMetrics<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (subscription, metrics) in self.0.transaction_handlers.metrics() {
+            f.begin_metric("transaction_handler_handled")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_SUBSCRIPTION, subscription)
+                .value(metrics.handled)?;
+
+            f.begin_metric("transaction_handler_slow")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_SUBSCRIPTION, subscription)
+                .value(metrics.slow)?;
+
+            f.begin_metric("transaction_handler_processing_time_us")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_SUBSCRIPTION, subscription)
+                .value(metrics.processing_time_us)?;
+
+            f.begin_metric("transaction_handler_max_processing_time_us")
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
+                .label(LABEL_SUBSCRIPTION, subscription)
+                .value(metrics.max_processing_time_us)?;
+        }
         Ok(())
     }
 }
@@ -456,12 +1425,12 @@ impl std::fmt::Display for LabeledEthSubscriberMetrics<'_> {
             let metrics = subscriber.metrics();
 
             f.begin_metric("eth_subscriber_last_processed_block")
-                .label(LABEL_STAKER, &self.0.staker_account_str)
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
                 .label(LABEL_CHAIN_ID, &chain_id)
                 .value(metrics.last_processed_block)?;
 
             f.begin_metric("eth_subscriber_pending_confirmation_count")
-                .label(LABEL_STAKER, &self.0.staker_account_str)
+                .label(LABEL_STAKER, &self.0.primary_staker().staker_account_str)
                 .label(LABEL_CHAIN_ID, &chain_id)
                 .value(metrics.pending_confirmation_count)?;
         }
@@ -469,9 +1438,46 @@ impl std::fmt::Display for LabeledEthSubscriberMetrics<'_> {
     }
 }
 
+struct LabeledAddressVerificationMetrics<'a>(&'a EngineContext);
+
+impl std::fmt::Display for LabeledAddressVerificationMetrics<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for staker in &self.0.stakers {
+            let progress = staker.address_verification.progress();
+            let chain_id = progress.chain_id.unwrap_or_default();
+
+            f.begin_metric("eth_address_verification_status")
+                .label(LABEL_STAKER, &staker.staker_account_str)
+                .label(LABEL_CHAIN_ID, chain_id)
+                .value(progress.status as u8)?;
+
+            f.begin_metric("eth_address_verification_attempts")
+                .label(LABEL_STAKER, &staker.staker_account_str)
+                .label(LABEL_CHAIN_ID, chain_id)
+                .value(progress.attempts)?;
+
+            if let Some(block_number) = progress.block_number {
+                f.begin_metric("eth_address_verification_block_number")
+                    .label(LABEL_STAKER, &staker.staker_account_str)
+                    .label(LABEL_CHAIN_ID, chain_id)
+                    .value(block_number)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 const LABEL_STAKER: &str = "staker";
 const LABEL_CHAIN_ID: &str = "chain_id";
 const LABEL_ROUND_NUM: &str = "round_num";
+const LABEL_REWARD_ROUND: &str = "reward_round";
+const LABEL_ALERT: &str = "alert";
+const LABEL_REASON: &str = "reason";
+const LABEL_ACTION: &str = "action";
+const LABEL_LISTENER: &str = "listener";
+const LABEL_SUBSCRIPTION: &str = "subscription";
+const LABEL_FUNCTION: &str = "function";
+const LABEL_MESSAGE_KIND: &str = "kind";
 
 pub type ShutdownRequestsRx = mpsc::UnboundedReceiver<()>;
 pub type ShutdownRequestsTx = mpsc::UnboundedSender<()>;
@@ -482,4 +1488,86 @@ enum EngineError {
     ExternalTonMessageExpected,
     #[error("Bridge account not found")]
     BridgeAccountNotFound,
+    #[error("Failed to init staking for all stakers")]
+    StakingNotInitialized,
+    #[error("Embedded node DB is required (`ton_source` must be `node`)")]
+    NodeDbRequired,
+    #[error("Message failed in {phase} phase with exit code {exit_code}")]
+    MessageFailed {
+        exit_code: i32,
+        phase: TransactionPhase,
+    },
+    #[error("Message was not delivered after {attempts} attempts")]
+    TooManyAttempts { attempts: u32 },
+    #[error("Message delivery deadline exceeded")]
+    DeliveryDeadlineExceeded,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_delivery_policy_is_finite() {
+        let policy = MessageDeliveryPolicy::default();
+        assert!(policy.max_attempts.is_some());
+        assert!(policy.deadline_sec.is_some());
+        assert!(policy.min_backoff_sec > 0);
+    }
+
+    #[test]
+    fn expired_messages_are_retried_until_limit() {
+        let policy = MessageDeliveryPolicy {
+            max_attempts: Some(3),
+            min_backoff_sec: 1,
+            max_backoff_sec: 3,
+            ..Default::default()
+        };
+        let mut attempts = DeliveryAttempts::new(policy);
+
+        // Each expired attempt is followed by the increasing backoff
+        let mut backoffs = Vec::new();
+        for _ in 0..3 {
+            attempts.start_next(0).unwrap();
+            backoffs.push(attempts.next_backoff().as_secs());
+        }
+        assert_eq!(backoffs, vec![1, 2, 3]);
+
+        assert!(matches!(
+            attempts.start_next(0),
+            Err(EngineError::TooManyAttempts { attempts: 3 })
+        ));
+    }
+
+    #[test]
+    fn expired_messages_are_retried_until_deadline() {
+        let policy = MessageDeliveryPolicy {
+            max_attempts: None,
+            deadline_sec: Some(60),
+            ..Default::default()
+        };
+        let mut attempts = DeliveryAttempts::new(policy);
+
+        attempts.start_next(0).unwrap();
+        attempts.start_next(59).unwrap();
+        assert!(matches!(
+            attempts.start_next(60),
+            Err(EngineError::DeliveryDeadlineExceeded)
+        ));
+    }
+
+    #[test]
+    fn failed_messages_are_retried_until_limit() {
+        let policy = MessageDeliveryPolicy {
+            max_failed_attempts: 2,
+            ..Default::default()
+        };
+        let mut attempts = DeliveryAttempts::new(policy);
+
+        attempts.on_failed(1, TransactionPhase::Compute).unwrap();
+        assert!(matches!(
+            attempts.on_failed(1, TransactionPhase::Compute),
+            Err(EngineError::MessageFailed { exit_code: 1, .. })
+        ));
+    }
 }
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use pkey_mprotect::*;
+use secstr::SecUtf8;
+use tiny_adnl::utils::*;
+use ton_types::UInt256;
+
+use super::eth_subscriber::*;
+use super::keystore::*;
+use super::message_sender::*;
+use super::ton_chain::*;
+use super::{FailedMessagesStats, MessageDeliveryStats, MessageKind};
+use crate::config::*;
+use crate::utils::*;
+
+/// Staker identity hosted by the relay
+pub struct StakerContext {
+    pub staker_account_str: String,
+    pub staker_account: UInt256,
+    /// Current keys and the next keys for the rotation
+    pub keystore: RotatingKeyStore,
+    /// Path to the file with per-round participation and rewards
+    pub staking_accounting_path: PathBuf,
+    /// Path to the file with ETH address verification transaction state
+    pub address_verification_state_path: PathBuf,
+    /// ETH address verification progress
+    pub address_verification: AddressVerificationTracker,
+    /// Direct or wallet-based messages sender
+    pub(super) message_sender: MessageSender,
+    /// Failed external messages statistics by function name
+    pub(super) failed_messages: FxDashMap<&'static str, FailedMessagesStats>,
+    /// External messages delivery statistics by message kind
+    pub(super) delivery_stats: FxDashMap<MessageKind, Arc<MessageDeliveryStats>>,
+}
+
+impl StakerContext {
+    pub fn new(
+        config: StakerProfileConfig,
+        master_password: SecUtf8,
+        protection_keys: Arc<ProtectionKeys>,
+        ton_chain: &Arc<dyn TonChain>,
+        messages_queue: &Arc<PendingMessagesQueue>,
+    ) -> Result<Arc<Self>> {
+        let staker_account =
+            UInt256::from_be_bytes(&config.staker_address.address().get_bytestring(0));
+        let staker_account_str = config.staker_address.to_string();
+
+        let keystore = RotatingKeyStore::new(
+            config.keys_path,
+            config.next_keys_path,
+            master_password,
+            protection_keys,
+        )
+        .with_context(|| {
+            format!(
+                "Failed to create keystore for staker {}",
+                staker_account_str
+            )
+        })?;
+
+        let message_sender = MessageSender::new(&config.message_sender, ton_chain, messages_queue)
+            .context("Failed to create messages sender")?;
+
+        Ok(Arc::new(Self {
+            staker_account_str,
+            staker_account,
+            keystore,
+            staking_accounting_path: config.staking_accounting_path,
+            address_verification_state_path: config.address_verification_state_path,
+            address_verification: Default::default(),
+            message_sender,
+            failed_messages: Default::default(),
+            delivery_stats: Default::default(),
+        }))
+    }
+}
@@ -0,0 +1,280 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::*;
+use crate::engine::keystore::*;
+use crate::engine::ton_contracts::*;
+
+/// Staker problems which require operator attention
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StakingAlert {
+    Slashed,
+    /// Balance is not enough to participate in elections
+    LowDeposit {
+        token_balance: u128,
+        min_relay_deposit: u128,
+    },
+    /// Relay lock expires soon or has already expired
+    LockExpiring {
+        relay_lock_until: u32,
+    },
+    /// Relay keys in user data differ from the local keys
+    KeysChanged {
+        ton_pubkey: String,
+        eth_address: String,
+    },
+}
+
+impl StakingAlert {
+    pub fn kind(&self) -> StakingAlertKind {
+        match self {
+            Self::Slashed => StakingAlertKind::Slashed,
+            Self::LowDeposit { .. } => StakingAlertKind::LowDeposit,
+            Self::LockExpiring { .. } => StakingAlertKind::LockExpiring,
+            Self::KeysChanged { .. } => StakingAlertKind::KeysChanged,
+        }
+    }
+}
+
+impl std::fmt::Display for StakingAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Slashed => f.write_str("Staker was slashed"),
+            Self::LowDeposit {
+                token_balance,
+                min_relay_deposit,
+            } => write!(
+                f,
+                "Deposit is below the min relay deposit ({}/{})",
+                token_balance, min_relay_deposit
+            ),
+            Self::LockExpiring { relay_lock_until } => {
+                write!(f, "Relay lock expires at {}", relay_lock_until)
+            }
+            Self::KeysChanged {
+                ton_pubkey,
+                eth_address,
+            } => write!(
+                f,
+                "Relay keys were changed to TON public key {} and ETH address {}",
+                ton_pubkey, eth_address
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StakingAlertKind {
+    Slashed,
+    LowDeposit,
+    LockExpiring,
+    KeysChanged,
+}
+
+impl StakingAlertKind {
+    const ALL: [Self; 4] = [
+        Self::Slashed,
+        Self::LowDeposit,
+        Self::LockExpiring,
+        Self::KeysChanged,
+    ];
+}
+
+impl std::fmt::Display for StakingAlertKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Slashed => "slashed",
+            Self::LowDeposit => "low_deposit",
+            Self::LockExpiring => "lock_expiring",
+            Self::KeysChanged => "keys_changed",
+        })
+    }
+}
+
+/// Tracks alert conditions and notifies about them once on each activation
+pub struct StakingAlerts {
+    staker: String,
+    lock_expiry_threshold_sec: u32,
+    webhook: Option<Webhook>,
+    relay_lock_until: AtomicU32,
+    states: [AlertState; 4],
+}
+
+impl StakingAlerts {
+    pub fn new(staker: String, config: &StakingAlertsConfig) -> Result<Self> {
+        let webhook = match &config.webhook_url {
+            Some(url) => Some(Webhook {
+                client: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(config.webhook_timeout_sec))
+                    .build()?,
+                url: url.clone(),
+            }),
+            None => None,
+        };
+
+        Ok(Self {
+            staker,
+            lock_expiry_threshold_sec: config.lock_expiry_threshold_sec,
+            webhook,
+            relay_lock_until: Default::default(),
+            states: Default::default(),
+        })
+    }
+
+    /// Checks user data details and returns newly raised alerts
+    pub fn check(
+        &self,
+        details: &UserDataDetails,
+        min_relay_deposit: u128,
+        keystore: &RotatingKeyStore,
+        now: u32,
+    ) -> Vec<StakingAlert> {
+        self.relay_lock_until
+            .store(details.relay_lock_until, Ordering::Release);
+
+        // NOTE: the next keys are expected during the rotation
+        let ton_pubkey_changed = details.ton_pubkey_confirmed
+            && !keystore.is_known_ton_pubkey(&details.relay_ton_pubkey);
+        let eth_address_changed = details.eth_address_confirmed
+            && !keystore.is_known_eth_address(&details.relay_eth_address);
+        let lock_expiring = details.relay_lock_until > 0
+            && details.relay_lock_until <= now.saturating_add(self.lock_expiry_threshold_sec);
+
+        [
+            (details.slashed, StakingAlert::Slashed),
+            (
+                details.token_balance < min_relay_deposit,
+                StakingAlert::LowDeposit {
+                    token_balance: details.token_balance,
+                    min_relay_deposit,
+                },
+            ),
+            (
+                lock_expiring,
+                StakingAlert::LockExpiring {
+                    relay_lock_until: details.relay_lock_until,
+                },
+            ),
+            (
+                ton_pubkey_changed || eth_address_changed,
+                StakingAlert::KeysChanged {
+                    ton_pubkey: format!("0x{:x}", details.relay_ton_pubkey),
+                    eth_address: format!("0x{}", hex::encode(details.relay_eth_address)),
+                },
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(active, alert)| self.update(active, alert))
+        .collect()
+    }
+
+    /// Raises the alert regardless of the user data state.
+    /// Returns `false` if it was already active
+    pub fn raise(&self, alert: StakingAlert) -> bool {
+        self.update(true, alert).is_some()
+    }
+
+    pub fn metrics(&self) -> StakingAlertsMetrics {
+        StakingAlertsMetrics {
+            relay_lock_until: self.relay_lock_until.load(Ordering::Acquire),
+            alerts: StakingAlertKind::ALL
+                .into_iter()
+                .map(|kind| {
+                    let state = self.state(kind);
+                    (
+                        kind,
+                        StakingAlertMetrics {
+                            active: state.active.load(Ordering::Acquire),
+                            count: state.count.load(Ordering::Acquire),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn update(&self, active: bool, alert: StakingAlert) -> Option<StakingAlert> {
+        let kind = alert.kind();
+        let state = self.state(kind);
+
+        match (state.active.swap(active, Ordering::AcqRel), active) {
+            (false, true) => {
+                state.count.fetch_add(1, Ordering::Release);
+                self.notify(&alert);
+                Some(alert)
+            }
+            (true, false) => {
+                log::info!("Staking alert {} resolved", kind);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn notify(&self, alert: &StakingAlert) {
+        log::warn!("Staking alert {}: {}", alert.kind(), alert);
+
+        let webhook = match &self.webhook {
+            Some(webhook) => webhook.clone(),
+            None => return,
+        };
+
+        let payload = WebhookPayload {
+            staker: self.staker.clone(),
+            message: alert.to_string(),
+            alert: alert.clone(),
+        };
+        tokio::spawn(async move {
+            let result = webhook
+                .client
+                .post(webhook.url)
+                .json(&payload)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                log::error!("Failed to send staking alert to webhook: {:?}", e);
+            }
+        });
+    }
+
+    fn state(&self, kind: StakingAlertKind) -> &AlertState {
+        &self.states[kind as usize]
+    }
+}
+
+pub struct StakingAlertsMetrics {
+    pub relay_lock_until: u32,
+    pub alerts: Vec<(StakingAlertKind, StakingAlertMetrics)>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct StakingAlertMetrics {
+    /// Whether the alert condition is present now
+    pub active: bool,
+    /// How many times the alert was raised
+    pub count: u64,
+}
+
+#[derive(Default)]
+struct AlertState {
+    active: AtomicBool,
+    count: AtomicU64,
+}
+
+#[derive(Clone)]
+struct Webhook {
+    client: reqwest::Client,
+    url: url::Url,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload {
+    staker: String,
+    message: String,
+    alert: StakingAlert,
+}
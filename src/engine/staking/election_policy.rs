@@ -0,0 +1,75 @@
+use serde::Serialize;
+
+use crate::config::*;
+
+/// Why the relay doesn't participate in elections
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ElectionSkipReason {
+    /// `ignore_elections` is set
+    Ignored,
+    /// Round is outside of the configured window
+    OutsideRoundWindow,
+    /// Staker was slashed recently
+    RecentlySlashed,
+    /// One-shot skip was requested via admin API
+    SkippedByAdmin,
+}
+
+impl std::fmt::Display for ElectionSkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Ignored => "ignored",
+            Self::OutsideRoundWindow => "outside_round_window",
+            Self::RecentlySlashed => "recently_slashed",
+            Self::SkippedByAdmin => "skipped_by_admin",
+        })
+    }
+}
+
+/// Participation decision for the elections of the specified round
+#[derive(Debug, Copy, Clone, Serialize)]
+pub struct ElectionDecision {
+    pub round_num: u32,
+    /// `None` if the relay participates in elections
+    pub skip_reason: Option<ElectionSkipReason>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ElectionPolicyState {
+    pub skip_next_election: bool,
+    pub last_decision: Option<ElectionDecision>,
+    pub required_deposit: String,
+}
+
+impl ElectionPolicyConfig {
+    /// Static policy checks for the elections of the specified round
+    pub fn check_round(
+        &self,
+        round_num: u32,
+        last_slashed_round: Option<u32>,
+    ) -> Option<ElectionSkipReason> {
+        let before_window = matches!(self.first_round, Some(first) if round_num < first);
+        let after_window = matches!(self.last_round, Some(last) if round_num > last);
+        if before_window || after_window {
+            return Some(ElectionSkipReason::OutsideRoundWindow);
+        }
+
+        match last_slashed_round {
+            Some(slashed_round)
+                if round_num
+                    <= slashed_round.saturating_add(self.rounds_to_skip_after_slashing) =>
+            {
+                Some(ElectionSkipReason::RecentlySlashed)
+            }
+            _ => None,
+        }
+    }
+
+    /// Min relay deposit with the configured margin
+    pub fn required_deposit(&self, min_relay_deposit: u128) -> u128 {
+        let margin =
+            min_relay_deposit.saturating_mul(self.min_deposit_margin_percent as u128) / 100;
+        min_relay_deposit.saturating_add(margin)
+    }
+}
@@ -0,0 +1,265 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use rand::Rng;
+use tokio::sync::futures::Notified;
+use tokio::sync::Notify;
+use ton_types::UInt256;
+
+use super::ElectionsState;
+use crate::config::*;
+use crate::utils::*;
+
+/// Permissionless elections lifecycle calls
+#[async_trait::async_trait]
+pub trait ElectionsHandler: Send + Sync {
+    /// Returns `false` if elections were started by someone else
+    async fn start_election(self: Arc<Self>) -> Result<bool>;
+
+    /// Returns `false` if elections were ended by someone else
+    async fn end_election(self: Arc<Self>) -> Result<bool>;
+}
+
+pub enum PendingElectionsState<'a> {
+    NotStarted {
+        start_time: u32,
+        started_fut: Notified<'a>,
+    },
+    Started {
+        end_time: u32,
+        ended_fut: Notified<'a>,
+    },
+    Finished {
+        new_round_fut: Notified<'a>,
+    },
+}
+
+impl<'a> PendingElectionsState<'a> {
+    /// Prepares notification futures for the next transition.
+    ///
+    /// NOTE: must be called while the round state is locked so that no events are missed
+    pub fn new(
+        state: ElectionsState,
+        elections_start_notify: &'a Notify,
+        elections_end_notify: &'a Notify,
+        relay_round_started_notify: &'a Notify,
+    ) -> Self {
+        match state {
+            ElectionsState::NotStarted { start_time } => Self::NotStarted {
+                start_time,
+                started_fut: elections_start_notify.notified(),
+            },
+            ElectionsState::Started { end_time, .. } => Self::Started {
+                end_time,
+                ended_fut: elections_end_notify.notified(),
+            },
+            ElectionsState::Finished => Self::Finished {
+                new_round_fut: relay_round_started_notify.notified(),
+            },
+        }
+    }
+}
+
+/// Per-relay delays and statistics of elections calls
+pub struct ElectionsCalls {
+    relay_delay_ms: u64,
+    jitter_ms: u64,
+    start: ElectionsCallStats,
+    end: ElectionsCallStats,
+}
+
+impl ElectionsCalls {
+    pub fn new(staker: &UInt256, config: &ElectionsCallsConfig) -> Self {
+        // NOTE: staker address is a hash, so its prefix is uniformly distributed
+        let mut prefix = [0; 8];
+        prefix.copy_from_slice(&staker.as_slice()[..8]);
+        let relay_delay_ms =
+            u64::from_be_bytes(prefix) % (config.max_relay_delay_sec as u64 * 1000 + 1);
+
+        Self {
+            relay_delay_ms,
+            jitter_ms: config.jitter_ms,
+            start: Default::default(),
+            end: Default::default(),
+        }
+    }
+
+    pub fn metrics(&self) -> ElectionsCallsMetrics {
+        ElectionsCallsMetrics {
+            start: self.start.metrics(),
+            end: self.end.metrics(),
+        }
+    }
+
+    fn next_delay(&self) -> Duration {
+        let jitter_ms = if self.jitter_ms > 0 {
+            rand::thread_rng().gen_range(0..=self.jitter_ms)
+        } else {
+            0
+        };
+        Duration::from_millis(self.relay_delay_ms + jitter_ms)
+    }
+}
+
+#[derive(Default)]
+struct ElectionsCallStats {
+    triggered: AtomicU64,
+    preempted: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl ElectionsCallStats {
+    fn on_result(&self, result: Result<bool>, action: &str) {
+        match result {
+            Ok(true) => {
+                log::info!("Elections {} triggered by this relay", action);
+                self.triggered.fetch_add(1, Ordering::Release);
+            }
+            Ok(false) => self.on_preempted(action),
+            Err(e) => {
+                log::error!("Failed to {} election: {:?}", action, e);
+                self.failed.fetch_add(1, Ordering::Release);
+            }
+        }
+    }
+
+    fn on_preempted(&self, action: &str) {
+        log::info!("Elections {} triggered by another relay", action);
+        self.preempted.fetch_add(1, Ordering::Release);
+    }
+
+    fn metrics(&self) -> ElectionsCallMetrics {
+        ElectionsCallMetrics {
+            triggered: self.triggered.load(Ordering::Acquire),
+            preempted: self.preempted.load(Ordering::Acquire),
+            failed: self.failed.load(Ordering::Acquire),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ElectionsCallsMetrics {
+    pub start: ElectionsCallMetrics,
+    pub end: ElectionsCallMetrics,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ElectionsCallMetrics {
+    /// Calls which were made by this relay
+    pub triggered: u64,
+    /// Calls which were made by another relay first
+    pub preempted: u64,
+    pub failed: u64,
+}
+
+/// Waits for the next elections transition, performing the required call after
+/// the relay delay unless someone else makes it first. Returns when the state must be reloaded
+pub async fn process_elections_state<H>(
+    clock: &dyn Clock,
+    handler: &Arc<H>,
+    calls: &ElectionsCalls,
+    elections_state: PendingElectionsState<'_>,
+    relay_config_updated_fut: Notified<'_>,
+) where
+    H: ElectionsHandler,
+{
+    log::info!("Now: {}", clock.now_sec());
+    tokio::pin!(relay_config_updated_fut);
+
+    match elections_state {
+        PendingElectionsState::NotStarted {
+            start_time,
+            started_fut,
+        } => {
+            tokio::pin!(started_fut);
+
+            // Wait elections start time
+            let delay = calls.next_delay();
+            log::info!(
+                "Starting elections in {} seconds (+{:?} relay delay)",
+                start_time.saturating_sub(clock.now_sec()),
+                delay
+            );
+            tokio::select! {
+                _ = sleep_after(clock, start_time, delay) => {},
+                _ = &mut started_fut => {
+                    calls.start.on_preempted("start");
+                    return;
+                }
+                _ = &mut relay_config_updated_fut => {
+                    log::warn!("Elections loop: cancelling elections start. Timings changed");
+                    return;
+                }
+            }
+
+            // Start elections
+            log::info!("Starting elections");
+            calls
+                .start
+                .on_result(handler.clone().start_election().await, "start");
+
+            // Wait actual elections start
+            log::info!("Waiting elections start");
+            tokio::select! {
+                _ = started_fut => {},
+                _ = relay_config_updated_fut => {
+                    log::warn!("Elections loop: stop waiting elections start. Timings changed");
+                }
+            }
+        }
+        PendingElectionsState::Started {
+            end_time,
+            ended_fut,
+        } => {
+            tokio::pin!(ended_fut);
+
+            // Wait elections end time
+            let delay = calls.next_delay();
+            log::info!(
+                "Ending elections in {} seconds (+{:?} relay delay)",
+                end_time.saturating_sub(clock.now_sec()),
+                delay
+            );
+            tokio::select! {
+                _ = sleep_after(clock, end_time, delay) => {},
+                _ = &mut ended_fut => {
+                    calls.end.on_preempted("end");
+                    return;
+                }
+                _ = &mut relay_config_updated_fut => {
+                    log::warn!("Elections loop: cancelling elections ending. Timings changed");
+                    return;
+                }
+            }
+
+            // End elections
+            log::info!("Ending elections");
+            calls
+                .end
+                .on_result(handler.clone().end_election().await, "end");
+
+            // Wait actual elections end
+            log::info!("Waiting elections end");
+            tokio::select! {
+                _ = ended_fut => {},
+                _ = relay_config_updated_fut => {
+                    log::warn!("Elections loop: stop waiting elections end. Timings changed");
+                }
+            }
+        }
+        PendingElectionsState::Finished { new_round_fut } => {
+            // Wait new round initialization
+            log::info!("Elections loop: waiting new round");
+            new_round_fut.await
+        }
+    }
+}
+
+/// Sleeps until the specified unix timestamp in seconds plus delay
+async fn sleep_after(clock: &dyn Clock, timestamp: u32, delay: Duration) {
+    let deadline_ms = timestamp as u64 * 1000 + delay.as_millis() as u64;
+    let delay_ms = deadline_ms.saturating_sub(clock.now_ms());
+    clock.sleep(Duration::from_millis(delay_ms)).await
+}
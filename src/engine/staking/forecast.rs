@@ -0,0 +1,168 @@
+use serde::Serialize;
+
+use super::election_policy::*;
+use super::ElectionsState;
+use crate::engine::ton_contracts::*;
+
+/// Number of forecasted rounds if it was not specified
+pub const DEFAULT_FORECAST_ROUNDS: usize = 5;
+/// Max number of forecasted rounds
+pub const MAX_FORECAST_ROUNDS: usize = 100;
+
+/// Estimated schedule of the next relay rounds
+#[derive(Debug, Clone, Serialize)]
+pub struct StakingForecast {
+    pub current_round: u32,
+    pub current_round_start_time: u32,
+    pub current_round_end_time: u32,
+    pub min_relay_deposit: String,
+    pub required_deposit: String,
+    pub user_data_balance: String,
+    pub rounds: Vec<RoundForecast>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundForecast {
+    pub round_num: u32,
+    pub start_time: u32,
+    pub end_time: u32,
+    /// Elections for this round. `None` if they have already finished
+    pub election_start_time: Option<u32>,
+    pub election_end_time: Option<u32>,
+    pub eligibility: RoundEligibility,
+}
+
+/// Whether the relay is expected to participate in the round
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundEligibility {
+    pub eligible: bool,
+    pub skip_reason: Option<ElectionSkipReason>,
+    pub enough_deposit: bool,
+    /// Elections result if they have already finished
+    pub elected: Option<bool>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RoundTimes {
+    pub round_num: u32,
+    pub start_time: u32,
+    pub end_time: u32,
+    pub election_times: Option<(u32, u32)>,
+}
+
+/// Estimates times of the next rounds from the current round and the relay config.
+///
+/// Elections for the next round are held during the current one, and the next round
+/// starts after the current one ends, but not earlier than `min_round_gap_time`
+/// after the elections end.
+pub fn forecast_round_times(
+    current_round: u32,
+    current_round_end_time: u32,
+    elections_state: ElectionsState,
+    relay_config: &RelayConfigDetails,
+    count: usize,
+) -> Vec<RoundTimes> {
+    let mut election_times = match elections_state {
+        ElectionsState::NotStarted { start_time } => {
+            Some((start_time, start_time + relay_config.election_time))
+        }
+        ElectionsState::Started {
+            start_time,
+            end_time,
+        } => Some((start_time, end_time)),
+        ElectionsState::Finished => None,
+    };
+
+    let mut prev_end_time = current_round_end_time;
+    (1..=count as u32)
+        .map(|offset| {
+            let start_time = match election_times {
+                Some((_, election_end_time)) => std::cmp::max(
+                    prev_end_time,
+                    election_end_time + relay_config.min_round_gap_time,
+                ),
+                None => prev_end_time,
+            };
+            let end_time = start_time + relay_config.relay_round_time;
+
+            let round = RoundTimes {
+                round_num: current_round + offset,
+                start_time,
+                end_time,
+                election_times,
+            };
+
+            let election_start_time = start_time + relay_config.time_before_election;
+            election_times = Some((
+                election_start_time,
+                election_start_time + relay_config.election_time,
+            ));
+            prev_end_time = end_time;
+
+            round
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relay_config() -> RelayConfigDetails {
+        RelayConfigDetails {
+            relay_lock_time: 0,
+            relay_round_time: 1000,
+            election_time: 100,
+            time_before_election: 200,
+            min_round_gap_time: 50,
+            relays_count: 10,
+            min_relay_count: 1,
+            min_relay_deposit: 100,
+            relay_initial_ton_deposit: 0,
+            relay_reward_per_second: 0,
+            user_reward_per_second: 0,
+        }
+    }
+
+    #[test]
+    fn forecast_follows_relay_config() {
+        let rounds = forecast_round_times(
+            10,
+            2000,
+            ElectionsState::NotStarted { start_time: 1200 },
+            &relay_config(),
+            2,
+        );
+        assert_eq!(
+            rounds,
+            [
+                RoundTimes {
+                    round_num: 11,
+                    start_time: 2000,
+                    end_time: 3000,
+                    election_times: Some((1200, 1300)),
+                },
+                RoundTimes {
+                    round_num: 12,
+                    start_time: 3000,
+                    end_time: 4000,
+                    election_times: Some((2200, 2300)),
+                },
+            ]
+        );
+
+        // Late elections delay the next round
+        let rounds = forecast_round_times(
+            10,
+            2000,
+            ElectionsState::Started {
+                start_time: 1980,
+                end_time: 2080,
+            },
+            &relay_config(),
+            1,
+        );
+        assert_eq!(rounds[0].start_time, 2130);
+        assert_eq!(rounds[0].end_time, 3130);
+    }
+}
@@ -1,24 +1,38 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{Context, Result};
 use nekoton_abi::UnpackAbiPlain;
 use parking_lot::Mutex;
-use tokio::sync::futures::Notified;
-use tokio::sync::mpsc;
-use tokio::sync::Notify;
+use tokio::sync::{watch, Notify};
 use ton_types::UInt256;
 
+use crate::config::*;
 use crate::engine::keystore::*;
+use crate::engine::staker::*;
 use crate::engine::ton_contracts::*;
 use crate::engine::ton_subscriber::*;
-use crate::engine::EngineContext;
+use crate::engine::{EngineContext, MessageKind};
 use crate::utils::*;
 
+pub use self::alerts::*;
+pub use self::election_policy::*;
+pub use self::elections_cycle::*;
+pub use self::forecast::*;
+
+mod alerts;
+mod election_policy;
+mod elections_cycle;
+mod forecast;
+
 /// Rounds part of relays logic
 pub struct Staking {
     /// Shared engine context
     context: Arc<EngineContext>,
+    /// Staker identity
+    staker: Arc<StakerContext>,
 
     /// Current relay round info
     current_relay_round: Mutex<CurrentRelayRound>,
@@ -38,6 +52,25 @@ pub struct Staking {
     participates_in_round: Tristate,
     /// Whether relay was elected during elections in current round.
     elected: Tristate,
+    /// One-shot elections skip requested via admin API
+    skip_next_election: AtomicBool,
+    /// The latest elections participation decision
+    last_election_decision: Mutex<Option<ElectionDecision>>,
+    /// Elections start/end calls delays and statistics
+    elections_calls: ElectionsCalls,
+    /// Whether the next keys are being confirmed
+    keys_rotation_in_progress: AtomicBool,
+
+    /// Relay rewards by relay rounds in which the staker participated
+    relay_round_rewards: Mutex<BTreeMap<u32, RelayRoundReward>>,
+    /// Pending user rewards by staking reward rounds
+    user_reward_balances: Mutex<Vec<u128>>,
+    /// Persistent participation and rewards history
+    accounting: Mutex<StakingAccounting>,
+    /// Accounting snapshots for the background writer
+    accounting_tx: watch::Sender<StakingAccounting>,
+    /// Slashing, deposit and keys alerts
+    alerts: StakingAlerts,
 
     /// Staking contract address
     staking_account: UInt256,
@@ -51,16 +84,24 @@ pub struct Staking {
 }
 
 impl Staking {
-    pub async fn new(ctx: Arc<EngineContext>, staking_account: UInt256) -> Result<Arc<Self>> {
+    pub async fn new(
+        ctx: Arc<EngineContext>,
+        staker: Arc<StakerContext>,
+        staking_account: UInt256,
+    ) -> Result<Arc<Self>> {
         // Prepare staking
-        ctx.ensure_user_data_confirmed(staking_account)
+        let next_keys_confirmed = ctx
+            .ensure_user_data_confirmed_with_retries(&staker, staking_account)
             .await
             .context("Failed to ensure that user data is confirmed")?;
 
         // Prepare initial data
+        let mut prev_mc_seqno = None;
         let (shard_accounts, relay_round_details, relay_round_state, user_data_account) = loop {
-            // Load all shard states
-            let shard_accounts = ctx.get_all_shard_accounts().await?;
+            // Load all shard states. Retries must use the next state
+            let snapshot = ctx.get_shard_accounts_snapshot(prev_mc_seqno).await?;
+            prev_mc_seqno = Some(snapshot.mc_seqno);
+            let shard_accounts = snapshot.shard_accounts.clone();
 
             // Get all info from staking contract
             let staking_contract = shard_accounts
@@ -95,7 +136,7 @@ impl Staking {
             }
 
             let user_data_account = staking_contract
-                .get_user_data_address(&ctx.staker_account)
+                .get_user_data_address(&staker.staker_account)
                 .context("User data account not found")?;
 
             break (
@@ -108,15 +149,15 @@ impl Staking {
 
         let participates_in_round = relay_round_details
             .staker_addrs
-            .contains(&ctx.staker_account);
+            .contains(&staker.staker_account);
 
         let user_data_contract = shard_accounts
             .find_account(&user_data_account)?
             .context("User data account not found")?;
-        let user_data_balance = UserDataContract(&user_data_contract)
+        let user_data_details = UserDataContract(&user_data_contract)
             .get_details()
-            .context("Failed to get user data details")?
-            .token_balance;
+            .context("Failed to get user data details")?;
+        let user_data_balance = user_data_details.token_balance;
 
         let check_elected = |elections_account_address: &UInt256| -> anyhow::Result<bool> {
             let elections_contract = shard_accounts
@@ -126,14 +167,14 @@ impl Staking {
             Ok(elections_contract
                 .staker_addrs()
                 .context("Failed to get staker addresses from next elections contract")?
-                .contains(&ctx.staker_account))
+                .contains(&staker.staker_account))
         };
 
         let (should_vote, elected) = match &relay_round_state.elections_state {
             ElectionsState::NotStarted { .. } => (false, None),
             ElectionsState::Started { .. } => {
                 let elected = check_elected(&relay_round_state.next_elections_account)?;
-                (!ctx.settings.ignore_elections && !elected, Some(elected))
+                (!elected, Some(elected))
             }
             ElectionsState::Finished => {
                 let elected = check_elected(&relay_round_state.next_elections_account)?;
@@ -141,15 +182,32 @@ impl Staking {
             }
         };
 
-        let (staking_events_tx, staking_events_rx) = mpsc::unbounded_channel();
-        let (user_data_events_tx, user_data_events_rx) = mpsc::unbounded_channel();
+        let accounting = StakingAccounting::load(&staker.staking_accounting_path)
+            .context("Failed to load staking accounting")?;
+        let accounting_tx = start_json_writer(
+            "staking accounting",
+            staker.staking_accounting_path.clone(),
+            accounting.clone(),
+        );
+        let alerts = StakingAlerts::new(
+            staker.staker_account_str.clone(),
+            &ctx.settings.staking_alerts,
+        )
+        .context("Failed to create staking alerts")?;
+        let elections_calls =
+            ElectionsCalls::new(&staker.staker_account, &ctx.settings.elections_calls);
+
+        let (staking_events_tx, staking_events_rx) = ctx.events_queues.channel("StakingContract");
+        let (user_data_events_tx, user_data_events_rx) =
+            ctx.events_queues.channel("UserDataContract");
 
         // Create object
         let staking = Arc::new(Self {
             context: ctx,
+            staker,
             current_relay_round: Mutex::new(CurrentRelayRound {
                 user_data_balance,
-                state: relay_round_state,
+                state: relay_round_state.clone(),
             }),
             relay_round_started_notify: Default::default(),
             elections_start_notify: Default::default(),
@@ -158,12 +216,30 @@ impl Staking {
             user_data_balance_changed_notify: Default::default(),
             participates_in_round: Tristate::new(Some(participates_in_round)),
             elected: Tristate::new(elected),
+            skip_next_election: Default::default(),
+            last_election_decision: Default::default(),
+            elections_calls,
+            keys_rotation_in_progress: Default::default(),
+            relay_round_rewards: Default::default(),
+            user_reward_balances: Default::default(),
+            accounting: Mutex::new(accounting),
+            accounting_tx,
+            alerts,
             staking_account,
             staking_observer: AccountObserver::new(&staking_events_tx),
             user_data_account,
             user_data_observer: AccountObserver::new(&user_data_events_tx),
         });
 
+        staking.update_accounting(|accounting| {
+            let round = accounting.round_mut(relay_round_state.number);
+            round.participated = Some(participates_in_round);
+            round.slashed |= user_data_details.slashed;
+            if elected.is_some() {
+                accounting.round_mut(relay_round_state.number + 1).elected = elected;
+            }
+        });
+
         let staking_clone = staking.clone();
         let elections_end_fut = staking_clone.elections_end_notify.notified();
 
@@ -183,13 +259,13 @@ impl Staking {
         // Subscribe observers
         let context = &staking.context;
         context
-            .ton_subscriber
+            .ton_chain
             .add_transactions_subscription([staking_account], &staking.staking_observer);
         context
-            .ton_subscriber
+            .ton_chain
             .add_transactions_subscription([user_data_account], &staking.user_data_observer);
 
-        if should_vote {
+        if should_vote && staking.decide_election_participation(relay_round_state.number + 1) {
             tokio::select! {
                 _ = staking_clone.become_relay_next_round() => {}
                 _ = elections_end_fut => {
@@ -199,12 +275,22 @@ impl Staking {
         }
 
         staking.start_managing_elections();
+        staking.start_checking_alerts(user_data_details);
+
+        // NOTE: the next keys could be confirmed before the restart
+        if next_keys_confirmed {
+            staking.start_keys_rotation();
+        }
 
         staking.collect_all_unclaimed_reward().await?;
 
         Ok(staking)
     }
 
+    pub fn staker(&self) -> &StakerContext {
+        &self.staker
+    }
+
     pub fn metrics(&self) -> StakingMetrics {
         let current_relay_round = self.current_relay_round.lock();
 
@@ -215,17 +301,213 @@ impl Staking {
             ignore_elections: self.context.settings.ignore_elections,
             participates_in_round: self.participates_in_round.load(),
             elected: self.elected.load(),
+            relay_round_rewards: self
+                .relay_round_rewards
+                .lock()
+                .iter()
+                .map(|(round, reward)| (*round, *reward))
+                .collect(),
+            user_reward_balances: self.user_reward_balances.lock().clone(),
+            alerts: self.alerts.metrics(),
+            elections_calls: self.elections_calls.metrics(),
+            skip_next_election: self.skip_next_election.load(Ordering::Acquire),
+            last_election_decision: *self.last_election_decision.lock(),
+            keys_rotation_in_progress: self.keys_rotation_in_progress.load(Ordering::Acquire),
+            required_deposit: self
+                .context
+                .settings
+                .election_policy
+                .required_deposit(current_relay_round.state.relay_config.min_relay_deposit),
+        }
+    }
+
+    pub fn election_policy_state(&self) -> ElectionPolicyState {
+        let min_relay_deposit = self
+            .current_relay_round
+            .lock()
+            .state
+            .relay_config
+            .min_relay_deposit;
+        ElectionPolicyState {
+            skip_next_election: self.skip_next_election.load(Ordering::Acquire),
+            last_decision: *self.last_election_decision.lock(),
+            required_deposit: self
+                .context
+                .settings
+                .election_policy
+                .required_deposit(min_relay_deposit)
+                .to_string(),
+        }
+    }
+
+    /// Requests or cancels the one-shot elections skip
+    pub fn set_skip_next_election(&self, skip: bool) {
+        log::warn!("Skip next election: {}", skip);
+        self.skip_next_election.store(skip, Ordering::Release);
+    }
+
+    /// Checks the elections policy for the specified round and remembers the decision
+    fn decide_election_participation(&self, round_num: u32) -> bool {
+        let skip_reason = self.election_skip_reason(round_num);
+        match skip_reason {
+            Some(reason) => log::warn!("Skipping elections for round {}: {}", round_num, reason),
+            None => log::info!("Participating in elections for round {}", round_num),
         }
+
+        *self.last_election_decision.lock() = Some(ElectionDecision {
+            round_num,
+            skip_reason,
+        });
+        skip_reason.is_none()
     }
 
+    fn election_skip_reason(&self, round_num: u32) -> Option<ElectionSkipReason> {
+        let last_slashed_round = self.accounting.lock().last_slashed_round();
+        if let Some(reason) = self.static_election_skip_reason(round_num, last_slashed_round) {
+            return Some(reason);
+        }
+
+        // NOTE: one-shot skip is consumed only by the elections it actually affects
+        if self.skip_next_election.swap(false, Ordering::AcqRel) {
+            return Some(ElectionSkipReason::SkippedByAdmin);
+        }
+
+        None
+    }
+
+    /// Policy checks which don't depend on the admin API
+    fn static_election_skip_reason(
+        &self,
+        round_num: u32,
+        last_slashed_round: Option<u32>,
+    ) -> Option<ElectionSkipReason> {
+        let settings = &self.context.settings;
+        if settings.ignore_elections {
+            return Some(ElectionSkipReason::Ignored);
+        }
+        settings
+            .election_policy
+            .check_round(round_num, last_slashed_round)
+    }
+
+    /// Estimates the schedule of the next rounds and the relay eligibility for each
+    pub fn forecast(&self, count: usize) -> StakingForecast {
+        let (state, user_data_balance) = {
+            let current_relay_round = self.current_relay_round.lock();
+            (
+                current_relay_round.state.clone(),
+                current_relay_round.user_data_balance,
+            )
+        };
+        let last_slashed_round = self.accounting.lock().last_slashed_round();
+        let last_election_decision = *self.last_election_decision.lock();
+        let skip_next_election = self.skip_next_election.load(Ordering::Acquire);
+        let elected = self.elected.load();
+
+        let min_relay_deposit = state.relay_config.min_relay_deposit;
+        let required_deposit = self
+            .context
+            .settings
+            .election_policy
+            .required_deposit(min_relay_deposit);
+        let enough_deposit = user_data_balance >= required_deposit;
+
+        // Elections which will consume the admin skip flag
+        let next_undecided_round = match state.elections_state {
+            ElectionsState::NotStarted { .. } => state.number + 1,
+            _ => state.number + 2,
+        };
+
+        let rounds = forecast_round_times(
+            state.number,
+            state.end_time,
+            state.elections_state,
+            &state.relay_config,
+            count,
+        )
+        .into_iter()
+        .map(|round| {
+            let decided_skip_reason = match last_election_decision {
+                Some(decision) if decision.round_num == round.round_num => {
+                    Some(decision.skip_reason)
+                }
+                _ => None,
+            };
+            let skip_reason = decided_skip_reason.unwrap_or_else(|| {
+                match self.static_election_skip_reason(round.round_num, last_slashed_round) {
+                    None if skip_next_election && round.round_num == next_undecided_round => {
+                        Some(ElectionSkipReason::SkippedByAdmin)
+                    }
+                    reason => reason,
+                }
+            });
+
+            // Elections result is known only for the next round
+            let elected = match state.elections_state {
+                ElectionsState::Finished if round.round_num == state.number + 1 => elected,
+                _ => None,
+            };
+
+            RoundForecast {
+                round_num: round.round_num,
+                start_time: round.start_time,
+                end_time: round.end_time,
+                election_start_time: round.election_times.map(|(start, _)| start),
+                election_end_time: round.election_times.map(|(_, end)| end),
+                eligibility: RoundEligibility {
+                    eligible: elected.unwrap_or_else(|| skip_reason.is_none() && enough_deposit),
+                    skip_reason,
+                    enough_deposit,
+                    elected,
+                },
+            }
+        })
+        .collect();
+
+        StakingForecast {
+            current_round: state.number,
+            current_round_start_time: state.start_time,
+            current_round_end_time: state.end_time,
+            min_relay_deposit: min_relay_deposit.to_string(),
+            required_deposit: required_deposit.to_string(),
+            user_data_balance: user_data_balance.to_string(),
+            rounds,
+        }
+    }
+
+    /// Checks all relay rounds in which the staker participated and claims
+    /// all outstanding rewards one by one
     async fn collect_all_unclaimed_reward(self: &Arc<Self>) -> Result<()> {
-        let shard_accounts = self.context.get_all_shard_accounts().await?;
+        let shard_accounts = self.context.get_all_shard_accounts(None).await?;
         let staking_contract = shard_accounts
             .find_account(&self.staking_account)?
             .context("Staking contract not found")?;
         let staking_contract = StakingContract(&staking_contract);
 
-        let try_collect_reward = |relay_round: u32| -> Result<()> {
+        // Update pending user rewards
+        let user_data_contract = shard_accounts
+            .find_account(&self.user_data_account)?
+            .context("User data account not found")?;
+        let user_data_details = UserDataContract(&user_data_contract)
+            .get_details()
+            .context("Failed to get user data details")?;
+        let user_reward_balances = user_data_details
+            .reward_rounds
+            .into_iter()
+            .map(|round| round.reward_balance)
+            .collect::<Vec<_>>();
+        log::info!("Pending user rewards: {:?}", user_reward_balances);
+        *self.user_reward_balances.lock() = user_reward_balances;
+
+        let current_round = staking_contract
+            .get_relay_rounds_details()
+            .context("Failed to get relay rounds details")?
+            .current_relay_round;
+
+        let mut rounds = Vec::new();
+
+        let mut unclaimed = Vec::new();
+        for relay_round in 0..=current_round {
             let relay_round_address = staking_contract
                 .get_relay_round_address(relay_round)
                 .context("Failed to compute relay round address")?;
@@ -233,66 +515,133 @@ impl Staking {
             let relay_round_contract = match shard_accounts.find_account(&relay_round_address) {
                 Ok(Some(contract)) => contract,
                 Ok(None) => {
-                    log::warn!("Relay round {} not found", relay_round);
-                    return Ok(());
+                    log::debug!("Relay round {} not found", relay_round);
+                    continue;
                 }
                 Err(e) => {
                     log::warn!("Failed to find relay round {}: {:?}", relay_round, e);
-                    return Ok(());
+                    continue;
                 }
             };
-            let relay_round_contract = RelayRoundContract(&relay_round_contract);
 
-            // Check if staker has unclaimed reward
-            match relay_round_contract.has_unclaimed_reward(self.context.staker_account) {
-                Ok(true) => { /* continue */ }
-                Ok(false) => return Ok(()),
-                Err(e) => {
-                    log::warn!(
-                        "Failed to check unclaimed reward for round {}: {:?}",
-                        relay_round,
-                        e
-                    );
+            match self.get_relay_round_reward(&RelayRoundContract(&relay_round_contract)) {
+                Ok(Some((reward, end_time))) => {
+                    if !reward.claimed {
+                        unclaimed.push((relay_round, end_time));
+                    }
+                    self.relay_round_rewards.lock().insert(relay_round, reward);
+                    rounds.push((relay_round, Some(reward)));
                 }
-            };
-
-            // Get end time and collect reward
-            match relay_round_contract.end_time() {
-                Ok(end_time) => {
-                    let staking = self.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = staking
-                            .get_reward_for_relay_round(relay_round, end_time)
-                            .await
-                        {
-                            log::error!(
-                                "Failed to collect reward for round {}: {:?}",
-                                relay_round,
-                                e
-                            );
-                        }
-                    });
+                Ok(None) => {
+                    // Staker didn't participate in this round
+                    rounds.push((relay_round, None));
                 }
                 Err(e) => {
-                    log::warn!("Failed to check round {} end time: {:?}", relay_round, e);
+                    log::warn!("Failed to check reward for round {}: {:?}", relay_round, e);
                 }
-            };
-            Ok(())
-        };
+            }
+        }
 
-        let current_round = staking_contract
-            .get_relay_rounds_details()
-            .context("Failed to get relay rounds details")?
-            .current_relay_round;
-        let oldest_relay_round = current_round.saturating_sub(2);
+        self.update_accounting(|accounting| {
+            for (relay_round, reward) in rounds {
+                let round = accounting.round_mut(relay_round);
+                round.participated = Some(reward.is_some());
+                if let Some(reward) = reward {
+                    round.reward_amount = Some(reward.amount);
+                    round.reward_claimed = reward.claimed;
+                }
+            }
+            accounting.round_mut(current_round).slashed |= user_data_details.slashed;
+        });
 
-        for relay_round in (oldest_relay_round..=current_round).rev() {
-            try_collect_reward(relay_round)?;
+        if unclaimed.is_empty() {
+            return Ok(());
         }
+        log::info!("Found unclaimed rewards for rounds: {:?}", unclaimed);
+
+        // NOTE: rounds are claimed in ascending order, so only the last
+        // (current) round may wait for its end
+        let staking = self.clone();
+        tokio::spawn(async move {
+            let interval = Duration::from_secs(staking.context.settings.reward_claim_interval_sec);
+            for (relay_round, end_time) in unclaimed {
+                staking
+                    .claim_relay_round_reward(relay_round, end_time)
+                    .await;
+                staking.context.clock.sleep(interval).await;
+            }
+        });
 
         Ok(())
     }
 
+    /// Returns the staker reward and the round end time.
+    /// `None` if the staker didn't participate in this round
+    fn get_relay_round_reward(
+        &self,
+        relay_round_contract: &RelayRoundContract<'_>,
+    ) -> Result<Option<(RelayRoundReward, u32)>> {
+        let details = relay_round_contract
+            .get_details()
+            .context("Failed to get relay round details")?;
+
+        let staked_tokens = match details
+            .staker_addrs
+            .iter()
+            .position(|staker| staker == &self.staker.staker_account)
+            .and_then(|index| details.staked_tokens.get(index))
+        {
+            Some(staked_tokens) => *staked_tokens,
+            None => return Ok(None),
+        };
+
+        let reward = RelayRoundReward {
+            amount: relay_round_contract.relay_reward(staked_tokens)?,
+            claimed: !relay_round_contract.has_unclaimed_reward(self.staker.staker_account)?,
+        };
+        Ok(Some((reward, relay_round_contract.end_time()?)))
+    }
+
+    /// Waits until the round end and claims the reward
+    async fn claim_relay_round_reward(&self, relay_round: u32, end_time: u32) {
+        match self.get_reward_for_relay_round(relay_round, end_time).await {
+            Ok(transaction_hash) => {
+                if let Some(reward) = self.relay_round_rewards.lock().get_mut(&relay_round) {
+                    reward.claimed = true;
+                }
+
+                self.update_accounting(|accounting| {
+                    let round = accounting.round_mut(relay_round);
+                    round.reward_claimed = true;
+                    round.reward_claim = transaction_hash.map(|transaction_hash| RewardClaim {
+                        transaction_hash,
+                        claimed_at: self.context.clock.now_sec(),
+                    });
+                });
+            }
+            Err(e) => {
+                log::error!(
+                    "Failed to collect reward for round {}: {:?}",
+                    relay_round,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Applies changes to the accounting and passes it to the background writer.
+    ///
+    /// NOTE: the snapshot is sent under the lock so that updates are not reordered,
+    /// but the file is written outside of it
+    fn update_accounting<F>(&self, f: F)
+    where
+        F: FnOnce(&mut StakingAccounting),
+    {
+        let mut accounting = self.accounting.lock();
+        f(&mut accounting);
+        self.accounting_tx.send(accounting.clone()).ok();
+    }
+
     async fn process_staking_event(
         self: Arc<Self>,
         (_, (round_state, event)): (UInt256, (RoundState, StakingEvent)),
@@ -317,8 +666,9 @@ impl Staking {
                 // Set `elected` as `false` on each election start
                 self.elected.store_if_empty(false);
 
-                // Do nothing if elections are ignored
-                if self.context.settings.ignore_elections {
+                // Do nothing if elections are skipped by the policy.
+                // NOTE: elections in the current round are held for the next one
+                if !self.decide_election_participation(current_relay_round.state.number + 1) {
                     return Ok(());
                 }
 
@@ -340,6 +690,13 @@ impl Staking {
             }
             StakingEvent::ElectionEnded(_) => {
                 self.elections_end_notify.notify_waiters();
+
+                // Elections in the current round are held for the next one
+                let next_round = current_relay_round.state.number + 1;
+                let elected = self.elected.load();
+                self.update_accounting(|accounting| {
+                    accounting.round_mut(next_round).elected = elected;
+                });
             }
             StakingEvent::RelayRoundInitialized(event) => {
                 self.relay_round_started_notify.notify_waiters();
@@ -347,10 +704,12 @@ impl Staking {
                 // Reset `participates_in_round` flag on each round start
                 self.participates_in_round.store(None);
 
-                // Spawn participation status checker
+                // Spawn participation status checker. The state must contain
+                // the initialized round, so the cached snapshot is not used
                 let staking = self.clone();
+                let mc_seqno = self.context.ton_chain.last_processed_mc_seqno();
                 tokio::spawn(async move {
-                    if let Err(e) = staking.update_participates_in_round_status().await {
+                    if let Err(e) = staking.update_participates_in_round_status(mc_seqno).await {
                         log::error!("Failed to update `participates_in_round` flag: {:?}", e);
                     }
                 });
@@ -358,17 +717,9 @@ impl Staking {
                 // Spawn delayed reward collection
                 let staking = self.clone();
                 tokio::spawn(async move {
-                    // Collect reward
-                    if let Err(e) = staking
-                        .get_reward_for_relay_round(event.round_num, event.round_end_time)
-                        .await
-                    {
-                        log::error!(
-                            "Failed to collect reward for round {}: {:?}",
-                            event.round_num,
-                            e
-                        );
-                    }
+                    staking
+                        .claim_relay_round_reward(event.round_num, event.round_end_time)
+                        .await;
                 });
             }
             StakingEvent::RelayConfigUpdated(_) => {
@@ -384,13 +735,23 @@ impl Staking {
         self: Arc<Self>,
         (_, event): (UInt256, UserDataEvent),
     ) -> Result<()> {
-        let keystore = &self.context.keystore;
+        let keystore = &self.staker.keystore;
 
         match event {
             UserDataEvent::RelayKeysUpdated(event) => {
-                if event.ton_pubkey != keystore.ton.public_key()
-                    || &event.eth_address != keystore.eth.address().as_fixed_bytes()
-                {
+                let keys_changed = !keystore
+                    .current()
+                    .matches(&event.ton_pubkey, &event.eth_address);
+                let next_keys = keystore.find_next(&event.ton_pubkey, &event.eth_address);
+
+                if keys_changed && next_keys.is_some() {
+                    log::warn!("Staker submitted the next keys. Starting keys rotation");
+                    self.start_keys_rotation();
+                } else if keys_changed {
+                    self.alerts.raise(StakingAlert::KeysChanged {
+                        ton_pubkey: format!("0x{:x}", event.ton_pubkey),
+                        eth_address: format!("0x{}", hex::encode(event.eth_address)),
+                    });
                     log::error!(
                         "FATAL ERROR. Staker sent different keys. Current relay setup is not operational now"
                     );
@@ -404,6 +765,11 @@ impl Staking {
                     hex::encode(event.eth_address)
                 );
                 self.elected.store(Some(true));
+
+                let next_round = self.current_relay_round.lock().state.number + 1;
+                self.update_accounting(|accounting| {
+                    accounting.round_mut(next_round).elected = Some(true);
+                });
             }
             UserDataEvent::DepositProcessed(event) => {
                 let mut current_relay_round = self.current_relay_round.lock();
@@ -416,6 +782,159 @@ impl Staking {
         Ok(())
     }
 
+    /// Confirms the next keys in UserData and switches to them.
+    /// Current keys are used for voting until then
+    fn start_keys_rotation(self: &Arc<Self>) {
+        const RETRY_INTERVAL_SEC: u64 = 60;
+
+        if self.keys_rotation_in_progress.swap(true, Ordering::AcqRel) {
+            log::info!("Keys rotation is already in progress");
+            return;
+        }
+
+        let staking = Arc::downgrade(self);
+        let clock = self.context.clock.clone();
+
+        tokio::spawn(async move {
+            loop {
+                // Get staking if it is still alive
+                let staking = match staking.upgrade() {
+                    Some(staking) => staking,
+                    None => return,
+                };
+
+                match staking.rotate_keys().await {
+                    Ok(()) => {
+                        log::warn!("Keys rotation finished");
+                        staking
+                            .keys_rotation_in_progress
+                            .store(false, Ordering::Release);
+                        return;
+                    }
+                    Err(e) => log::error!("Failed to rotate keys: {:?}", e),
+                }
+
+                drop(staking);
+                clock.sleep(Duration::from_secs(RETRY_INTERVAL_SEC)).await;
+            }
+        });
+    }
+
+    /// Confirms the next keys and switches to them when the current relay round
+    /// accepts them.
+    ///
+    /// NOTE: the relay round stores keys of the relays at the moment of elections,
+    /// so votes signed by the next keys are rejected until the round with them starts
+    async fn rotate_keys(&self) -> Result<()> {
+        let next_keys_confirmed = self
+            .context
+            .ensure_user_data_confirmed_with_retries(&self.staker, self.staking_account)
+            .await?;
+        if !next_keys_confirmed {
+            return Ok(());
+        }
+
+        let next_keys = self.staker.keystore.next().context("Next keys not found")?;
+
+        loop {
+            // NOTE: notification future is created before the check so that
+            // the round start is not missed
+            let relay_round_started_fut = self.relay_round_started_notify.notified();
+
+            let newer_than = self.context.ton_chain.last_processed_mc_seqno();
+            let details = self.get_current_relay_round_details(newer_than).await?;
+            if relay_round_accepts_keys(
+                &details,
+                &self.staker.staker_account,
+                next_keys.ton.public_key(),
+                next_keys.eth.address().as_fixed_bytes(),
+            ) {
+                break;
+            }
+
+            log::warn!(
+                "Relay round {} contains the current keys. Waiting for the next round to switch keys",
+                details.round_num
+            );
+            relay_round_started_fut.await;
+        }
+
+        self.staker
+            .keystore
+            .switch_to_next()
+            .context("Failed to switch to the next keys")
+    }
+
+    /// Checks user data on each change and periodically for time-based alerts
+    fn start_checking_alerts(self: &Arc<Self>, initial_details: UserDataDetails) {
+        let mut user_data_states = self
+            .context
+            .ton_chain
+            .subscribe_account_state(self.user_data_account);
+        let check_interval =
+            Duration::from_secs(self.context.settings.staking_alerts.check_interval_sec);
+        let clock = self.context.clock.clone();
+
+        self.check_alerts(&initial_details);
+
+        let staking = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut details = initial_details;
+            loop {
+                tokio::select! {
+                    update = user_data_states.recv() => {
+                        let update = match update {
+                            Ok(update) => update,
+                            Err(e) => {
+                                log::error!("User data states subscription closed: {:?}", e);
+                                return;
+                            }
+                        };
+                        match parse_user_data_details(&update) {
+                            Ok(new_details) => details = new_details,
+                            Err(e) => {
+                                log::error!("Failed to parse user data details: {:?}", e);
+                                continue;
+                            }
+                        }
+                    }
+                    _ = clock.sleep(check_interval) => {}
+                }
+
+                // Get staking if it is still alive
+                let staking = match staking.upgrade() {
+                    Some(staking) => staking,
+                    None => return,
+                };
+                staking.check_alerts(&details);
+            }
+        });
+    }
+
+    fn check_alerts(&self, details: &UserDataDetails) {
+        let (round_num, min_relay_deposit) = {
+            let current_relay_round = self.current_relay_round.lock();
+            (
+                current_relay_round.state.number,
+                current_relay_round.state.relay_config.min_relay_deposit,
+            )
+        };
+        let now = self.context.clock.now_sec();
+
+        let raised = self
+            .alerts
+            .check(details, min_relay_deposit, &self.staker.keystore, now);
+        if raised
+            .iter()
+            .any(|alert| matches!(alert, StakingAlert::Slashed))
+        {
+            self.update_accounting(|accounting| {
+                accounting.round_mut(round_num).slashed = true;
+            });
+        }
+    }
+
     fn start_managing_elections(self: &Arc<Self>) {
         let staking = Arc::downgrade(self);
 
@@ -436,25 +955,12 @@ impl Staking {
                     let elections_state = current_relay_round.state.elections_state;
                     log::info!("Elections management loop. State: {:?}", elections_state);
 
-                    let elections_state = match elections_state {
-                        ElectionsState::NotStarted { start_time } => {
-                            PendingElectionsState::NotStarted {
-                                start_time,
-                                inner_fut: staking.elections_start_notify.notified(),
-                                outer_fut: staking.elections_start_notify.notified(),
-                            }
-                        }
-                        ElectionsState::Started { end_time, .. } => {
-                            PendingElectionsState::Started {
-                                end_time,
-                                inner_fut: staking.elections_end_notify.notified(),
-                                outer_fut: staking.elections_end_notify.notified(),
-                            }
-                        }
-                        ElectionsState::Finished => PendingElectionsState::Finished {
-                            new_round_fut: staking.relay_round_started_notify.notified(),
-                        },
-                    };
+                    let elections_state = PendingElectionsState::new(
+                        elections_state,
+                        &staking.elections_start_notify,
+                        &staking.elections_end_notify,
+                        &staking.relay_round_started_notify,
+                    );
                     let relay_config_updated_fut = staking.relay_config_updated_notify.notified();
 
                     // NOTE: `current_relay_round` lock is dropped here, so it is guaranteed that
@@ -462,86 +968,15 @@ impl Staking {
                     (elections_state, relay_config_updated_fut)
                 };
 
-                let now = chrono::Utc::now().timestamp() as u64;
-                log::info!("Now: {}", now);
-
                 // Process election state
-                match elections_state {
-                    PendingElectionsState::NotStarted {
-                        start_time,
-                        inner_fut,
-                        outer_fut,
-                    } => {
-                        let staking = staking.clone();
-
-                        let action = async move {
-                            let delay = (start_time as u64).saturating_sub(now);
-
-                            // Wait elections start time
-                            log::info!("Starting elections in {} seconds", delay);
-                            tokio::time::sleep(Duration::from_secs(delay)).await;
-
-                            // Start elections
-                            log::info!("Starting elections");
-                            if let Err(e) = staking.start_election().await {
-                                log::error!("Failed to start election: {:?}", e);
-                            }
-
-                            // Wait actual elections start
-                            log::info!("Waiting elections start");
-                            inner_fut.await;
-                        };
-
-                        tokio::select! {
-                            _ = action => continue,
-                            _ = outer_fut => {
-                                log::warn!("Elections loop: cancelling elections start. Already started");
-                            }
-                            _ = relay_config_updated_fut => {
-                                log::warn!("Elections loop: cancelling elections start. Timings changed");
-                            }
-                        }
-                    }
-                    PendingElectionsState::Started {
-                        end_time,
-                        inner_fut,
-                        outer_fut,
-                    } => {
-                        let staking = staking.clone();
-                        let action = async move {
-                            let delay = (end_time as u64).saturating_sub(now);
-
-                            // Wait elections end time
-                            log::info!("Ending elections in {} seconds", delay);
-                            tokio::time::sleep(Duration::from_secs(delay)).await;
-
-                            // End elections
-                            log::info!("Ending elections");
-                            if let Err(e) = staking.end_election().await {
-                                log::error!("Failed to end election: {:?}", e);
-                            }
-
-                            // Wait actual elections end
-                            log::info!("Waiting elections end");
-                            inner_fut.await;
-                        };
-
-                        tokio::select! {
-                            _ = action => continue,
-                            _ = outer_fut => {
-                                log::warn!("Elections loop: cancelling elections ending. Already ended");
-                            }
-                            _ = relay_config_updated_fut => {
-                                log::warn!("Elections loop: cancelling elections ending. Timings changed");
-                            }
-                        }
-                    }
-                    PendingElectionsState::Finished { new_round_fut } => {
-                        // Wait new round initialization
-                        log::info!("Elections loop: waiting new round");
-                        new_round_fut.await
-                    }
-                }
+                process_elections_state(
+                    staking.context.clock.as_ref(),
+                    &staking,
+                    &staking.elections_calls,
+                    elections_state,
+                    relay_config_updated_fut,
+                )
+                .await;
             }
         });
     }
@@ -554,14 +989,18 @@ impl Staking {
                 let current_relay_round = self.current_relay_round.lock();
 
                 let user_data_balance = current_relay_round.user_data_balance;
-                let min_relay_deposit = current_relay_round.state.min_relay_deposit;
+                let required_deposit = self
+                    .context
+                    .settings
+                    .election_policy
+                    .required_deposit(current_relay_round.state.relay_config.min_relay_deposit);
 
                 // Check if user can be elected
-                if user_data_balance >= min_relay_deposit {
+                if user_data_balance >= required_deposit {
                     log::info!(
                         "User has enough balance to be elected ({}/{})",
                         user_data_balance,
-                        min_relay_deposit
+                        required_deposit
                     );
                     break;
                 }
@@ -570,7 +1009,7 @@ impl Staking {
                 log::info!(
                     "User doesn't have enough balance to be elected ({}/{})",
                     user_data_balance,
-                    min_relay_deposit
+                    required_deposit
                 );
 
                 // User data notifications
@@ -594,11 +1033,10 @@ impl Staking {
         // Send message `becomeRelayNextRound`
         self.context
             .deliver_message(
+                &self.staker,
                 self.user_data_observer.clone(),
-                UnsignedMessage::new(
-                    user_data_contract::become_relay_next_round(),
-                    self.user_data_account,
-                ),
+                MessageKind::Elections,
+                become_relay_next_round_message(self.user_data_account),
                 // Condition is always true because this method should
                 // always be called inside `tokio::select`
                 || true,
@@ -606,26 +1044,28 @@ impl Staking {
             .await
     }
 
-    /// Delivers `getRewardForRelayRound` message to user data contract at specified time
-    async fn get_reward_for_relay_round(&self, relay_round: u32, end_time: u32) -> Result<()> {
-        const ROUND_OFFSET: u64 = 10; // seconds
+    /// Delivers `getRewardForRelayRound` message to user data contract at specified time.
+    /// Returns the hash of the transaction with the claim
+    async fn get_reward_for_relay_round(
+        &self,
+        relay_round: u32,
+        end_time: u32,
+    ) -> Result<Option<UInt256>> {
+        const ROUND_OFFSET: u32 = 10; // seconds
 
         // Wait until round end
-        let now = chrono::Utc::now().timestamp() as u64;
-        tokio::time::sleep(Duration::from_secs(
-            (end_time as u64).saturating_sub(now) + ROUND_OFFSET,
-        ))
-        .await;
+        self.context
+            .clock
+            .sleep_until(end_time.saturating_add(ROUND_OFFSET))
+            .await;
 
         // Collect
         self.context
-            .deliver_message(
+            .deliver_message_tracked(
+                &self.staker,
                 self.user_data_observer.clone(),
-                UnsignedMessage::new(
-                    user_data_contract::get_reward_for_relay_round(),
-                    self.user_data_account,
-                )
-                .arg(relay_round),
+                MessageKind::Reward,
+                get_reward_for_relay_round_message(self.user_data_account, relay_round),
                 // Condition is always true because this method is always accepted by the contract.
                 // (It is an exception situation otherwise)
                 || true,
@@ -633,38 +1073,28 @@ impl Staking {
             .await
     }
 
-    /// Delivers `startElectionOnNewRound` message to staking contract
-    async fn start_election(&self) -> Result<()> {
-        self.context
-            .deliver_message(
-                self.staking_observer.clone(),
-                UnsignedMessage::new(
-                    staking_contract::start_election_on_new_round(),
-                    self.staking_account,
-                ),
-                // Condition is always true because this method should
-                // always be called inside `tokio::select`
-                || true,
-            )
-            .await
-    }
+    /// Checks whether this relay is in current relay round using the state
+    /// newer than the specified masterchain block
+    async fn update_participates_in_round_status(&self, newer_than: u32) -> Result<()> {
+        let details = self.get_current_relay_round_details(newer_than).await?;
 
-    /// Delivers `endElection` message to staking contract
-    async fn end_election(&self) -> Result<()> {
-        self.context
-            .deliver_message(
-                self.staking_observer.clone(),
-                UnsignedMessage::new(staking_contract::end_election(), self.staking_account),
-                // Condition is always true because this method should
-                // always be called inside `tokio::select`
-                || true,
-            )
-            .await
+        let participates_in_round = details.staker_addrs.contains(&self.staker.staker_account);
+        self.participates_in_round
+            .store_if_empty(participates_in_round);
+
+        self.update_accounting(|accounting| {
+            accounting.round_mut(details.round_num).participated = Some(participates_in_round);
+        });
+
+        Ok(())
     }
 
-    /// Checks whether this relay is in current relay round
-    async fn update_participates_in_round_status(&self) -> Result<()> {
-        let shard_accounts = self.context.get_all_shard_accounts().await?;
+    /// Loads the current relay round using the state newer than the specified masterchain block
+    async fn get_current_relay_round_details(&self, newer_than: u32) -> Result<RelayRoundDetails> {
+        let shard_accounts = self
+            .context
+            .get_all_shard_accounts(Some(newer_than))
+            .await?;
         let staking_contract = shard_accounts
             .find_account(&self.staking_account)?
             .context("Staking contract not found")?;
@@ -680,17 +1110,57 @@ impl Staking {
         let relay_round_contract = shard_accounts
             .find_account(&relay_round_address)?
             .context("Current relay round contract not found")?;
-        let relay_round_contract = RelayRoundContract(&relay_round_contract);
-
-        self.participates_in_round.store_if_empty(
-            relay_round_contract
-                .get_details()
-                .context("Failed to get relay round details")?
-                .staker_addrs
-                .contains(&self.staking_account),
-        );
 
-        Ok(())
+        RelayRoundContract(&relay_round_contract)
+            .get_details()
+            .context("Failed to get relay round details")
+    }
+}
+
+#[async_trait::async_trait]
+impl ElectionsHandler for Staking {
+    /// Delivers `startElectionOnNewRound` message to staking contract
+    async fn start_election(self: Arc<Self>) -> Result<bool> {
+        let staking = self.clone();
+        let transaction_hash = self
+            .context
+            .deliver_message_tracked(
+                &self.staker,
+                self.staking_observer.clone(),
+                MessageKind::Elections,
+                start_election_message(self.staking_account),
+                // Stop if elections were started by another relay
+                move || {
+                    matches!(
+                        staking.current_relay_round.lock().state.elections_state,
+                        ElectionsState::NotStarted { .. }
+                    )
+                },
+            )
+            .await?;
+        Ok(transaction_hash.is_some())
+    }
+
+    /// Delivers `endElection` message to staking contract
+    async fn end_election(self: Arc<Self>) -> Result<bool> {
+        let staking = self.clone();
+        let transaction_hash = self
+            .context
+            .deliver_message_tracked(
+                &self.staker,
+                self.staking_observer.clone(),
+                MessageKind::Elections,
+                end_election_message(self.staking_account),
+                // Stop if elections were ended by another relay
+                move || {
+                    matches!(
+                        staking.current_relay_round.lock().state.elections_state,
+                        ElectionsState::Started { .. }
+                    )
+                },
+            )
+            .await?;
+        Ok(transaction_hash.is_some())
     }
 }
 
@@ -701,6 +1171,84 @@ pub struct StakingMetrics {
     pub ignore_elections: bool,
     pub participates_in_round: Option<bool>,
     pub elected: Option<bool>,
+    pub relay_round_rewards: Vec<(u32, RelayRoundReward)>,
+    pub user_reward_balances: Vec<u128>,
+    pub alerts: StakingAlertsMetrics,
+    pub elections_calls: ElectionsCallsMetrics,
+    pub skip_next_election: bool,
+    pub last_election_decision: Option<ElectionDecision>,
+    pub keys_rotation_in_progress: bool,
+    /// Min relay deposit with the configured margin
+    pub required_deposit: u128,
+}
+
+// NOTE: only the elections calls can be sent by anyone, so they are the only
+// messages which are sent through the wallet in the wallet mode. Other methods
+// are authorised by the relay public key of the external message
+
+fn start_election_message(staking_account: UInt256) -> UnsignedMessage {
+    UnsignedMessage::new(
+        staking_contract::start_election_on_new_round(),
+        staking_account,
+    )
+    .sender_agnostic()
+}
+
+fn end_election_message(staking_account: UInt256) -> UnsignedMessage {
+    UnsignedMessage::new(staking_contract::end_election(), staking_account).sender_agnostic()
+}
+
+fn become_relay_next_round_message(user_data_account: UInt256) -> UnsignedMessage {
+    UnsignedMessage::new(
+        user_data_contract::become_relay_next_round(),
+        user_data_account,
+    )
+}
+
+fn get_reward_for_relay_round_message(
+    user_data_account: UInt256,
+    relay_round: u32,
+) -> UnsignedMessage {
+    UnsignedMessage::new(
+        user_data_contract::get_reward_for_relay_round(),
+        user_data_account,
+    )
+    .arg(relay_round)
+}
+
+fn confirm_ton_account_message(user_data_account: UInt256) -> UnsignedMessage {
+    UnsignedMessage::new(user_data_contract::confirm_ton_account(), user_data_account)
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RelayRoundReward {
+    /// Relay share of the round reward
+    pub amount: u128,
+    pub claimed: bool,
+}
+
+fn parse_user_data_details(update: &AccountStateUpdate) -> Result<UserDataDetails> {
+    let contract = update.contract()?.context("User data account not found")?;
+    UserDataContract(&contract)
+        .get_details()
+        .context("Failed to get user data details")
+}
+
+/// Whether votes signed by the specified keys are accepted in the relay round.
+/// Stakers which are not listed in the round don't vote, so any keys are accepted
+fn relay_round_accepts_keys(
+    details: &RelayRoundDetails,
+    staker: &UInt256,
+    ton_pubkey: &UInt256,
+    eth_address: &[u8; 20],
+) -> bool {
+    match details.staker_addrs.iter().position(|item| item == staker) {
+        Some(index) => {
+            details.ton_keys.get(index) == Some(ton_pubkey)
+                && details.eth_addrs.get(index) == Some(eth_address)
+        }
+        None => true,
+    }
 }
 
 /// Relay round and user data params
@@ -710,9 +1258,53 @@ struct CurrentRelayRound {
 }
 
 impl EngineContext {
-    /// Ensures that TON pubkey and ETH address are confirmed in UserData
-    async fn ensure_user_data_confirmed(self: &Arc<Self>, staking_account: UInt256) -> Result<()> {
-        let shard_accounts = self.get_all_shard_accounts().await?;
+    /// Same as `ensure_user_data_confirmed`, but re-reads UserData and tries again
+    /// on failures (e.g. reverted ETH address verification transaction).
+    /// Number of tries is limited by `address_verification.max_attempts`
+    async fn ensure_user_data_confirmed_with_retries(
+        self: &Arc<Self>,
+        staker: &StakerContext,
+        staking_account: UInt256,
+    ) -> Result<bool> {
+        const RETRY_INTERVAL_SEC: u64 = 60;
+
+        let max_attempts = std::cmp::max(self.settings.address_verification.max_attempts, 1);
+        let mut attempt = 1;
+        loop {
+            match self
+                .ensure_user_data_confirmed(staker, staking_account)
+                .await
+            {
+                Ok(next_keys_confirmed) => return Ok(next_keys_confirmed),
+                Err(e) if attempt < max_attempts => {
+                    log::error!(
+                        "Failed to ensure that user data is confirmed for staker {} (attempt {}/{}): {:?}",
+                        staker.staker_account_str,
+                        attempt,
+                        max_attempts,
+                        e
+                    );
+                    attempt += 1;
+                    self.clock
+                        .sleep(Duration::from_secs(RETRY_INTERVAL_SEC))
+                        .await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Ensures that TON pubkey and ETH address are confirmed in UserData.
+    /// Returns `true` if UserData contains the next keys of the staker
+    async fn ensure_user_data_confirmed(
+        self: &Arc<Self>,
+        staker: &StakerContext,
+        staking_account: UInt256,
+    ) -> Result<bool> {
+        // NOTE: the cached snapshot can be older than the submitted keys
+        let shard_accounts = self
+            .get_all_shard_accounts(Some(self.ton_chain.last_processed_mc_seqno()))
+            .await?;
         let staking_contract = shard_accounts
             .find_account(&staking_account)?
             .context("Staking contract not found")?;
@@ -727,7 +1319,7 @@ impl EngineContext {
         );
 
         // Initialize user data
-        let user_data_account = staking_contract.get_user_data_address(&self.staker_account)?;
+        let user_data_account = staking_contract.get_user_data_address(&staker.staker_account)?;
         log::info!("User data account: {:x}", user_data_account);
         let user_data_contract = shard_accounts
             .find_account(&user_data_account)?
@@ -735,35 +1327,55 @@ impl EngineContext {
         let user_data_contract = UserDataContract(&user_data_contract);
 
         user_data_contract
-            .ensure_verified(self, user_data_account, bridge_event_configuration)
+            .ensure_verified(self, staker, user_data_account, bridge_event_configuration)
             .await
     }
 }
 
 impl UserDataContract<'_> {
-    /// Ensures that TON pubkey and ETH address are confirmed in UserData
+    /// Ensures that TON pubkey and ETH address are confirmed in UserData.
+    /// Returns `true` if the confirmed keys are the next keys of the staker.
+    ///
+    /// NOTE: the next keys are not switched to here, because the current relay
+    /// round can still contain the current keys
     async fn ensure_verified(
         &self,
         context: &Arc<EngineContext>,
+        staker: &StakerContext,
         user_data_account: UInt256,
         bridge_event_configuration: EthEventConfigurationDetails,
-    ) -> Result<()> {
+    ) -> Result<bool> {
         let ton_pubkey_confirmed_notify = Arc::new(Notify::new());
         let eth_address_confirmed_notify = Arc::new(Notify::new());
 
         let ton_notified = ton_pubkey_confirmed_notify.notified();
         let eth_notified = eth_address_confirmed_notify.notified();
 
-        let (user_data_events_tx, mut user_data_events_rx) =
-            mpsc::unbounded_channel::<(UInt256, UserDataEvent)>();
+        let (user_data_events_tx, mut user_data_events_rx) = context
+            .events_queues
+            .channel::<UserDataEvent>("UserDataVerification");
 
         let details = self
             .get_details()
             .context("Failed to get UserData details")?;
         log::info!("UserData details: {:?}", details);
 
-        let relay_eth_address = *context.keystore.eth.address().as_fixed_bytes();
-        let relay_ton_pubkey = *context.keystore.ton.public_key();
+        // Select keys submitted by the staker. The next keys are confirmed
+        // during the rotation and replace the current keys afterwards
+        let current_keys = staker.keystore.current();
+        let next_keys = staker
+            .keystore
+            .find_next(&details.relay_ton_pubkey, &details.relay_eth_address);
+        let (keys, rotate) = match next_keys {
+            Some(next_keys) => {
+                log::warn!("UserData contains the next keys. Confirming them before the rotation");
+                (next_keys, true)
+            }
+            None => (current_keys, false),
+        };
+
+        let relay_eth_address = *keys.eth.address().as_fixed_bytes();
+        let relay_ton_pubkey = *keys.ton.public_key();
 
         if details.relay_eth_address != relay_eth_address {
             return Err(StakingError::UserDataEthAddressMismatch.into());
@@ -814,19 +1426,19 @@ impl UserDataContract<'_> {
         });
 
         context
-            .ton_subscriber
+            .ton_chain
             .add_transactions_subscription([user_data_account], &user_data_observer);
 
         if details.ton_pubkey_confirmed {
             ton_pubkey_confirmed_notify.notify_waiters();
         } else {
             context
-                .deliver_message(
+                .deliver_message_signed_by(
+                    staker,
+                    &keys,
                     user_data_observer.clone(),
-                    UnsignedMessage::new(
-                        user_data_contract::confirm_ton_account(),
-                        user_data_account,
-                    ),
+                    MessageKind::Verification,
+                    confirm_ton_account_message(user_data_account),
                     // Condition is always true because this method is always accepted by the contract
                     || true,
                 )
@@ -838,16 +1450,23 @@ impl UserDataContract<'_> {
         if details.eth_address_confirmed {
             eth_address_confirmed_notify.notify_waiters();
         } else {
-            let subscriber = context
+            let chain_id = bridge_event_configuration.network_configuration.chain_id;
+            let verifier = context
                 .eth_subscribers
-                .get_subscriber(bridge_event_configuration.network_configuration.chain_id)
+                .get_address_verifier(
+                    chain_id,
+                    &context.settings.address_verification,
+                    context.clock.clone(),
+                )?
                 .ok_or(StakingError::RequiredEthNetworkNotFound)?;
-            subscriber
+            verifier
                 .verify_relay_staker_address(
                     &context.settings.address_verification,
-                    context.keystore.eth.handle(),
-                    context.keystore.eth.address(),
-                    context.staker_account,
+                    &staker.address_verification_state_path,
+                    &staker.address_verification,
+                    keys.eth.handle(),
+                    keys.eth.address(),
+                    staker.staker_account,
                     &bridge_event_configuration
                         .network_configuration
                         .event_emitter
@@ -855,13 +1474,19 @@ impl UserDataContract<'_> {
                 )
                 .await
                 .context("Failed confirming ETH address")?;
-            log::info!("Sent ETH address confirmation")
+            log::info!("ETH address verification transaction succeeded")
         }
 
         log::info!("Waiting confirmation...");
         futures::future::join(ton_notified, eth_notified).await;
 
-        Ok(())
+        // Verification transaction is no longer needed
+        staker
+            .address_verification
+            .set_status(AddressVerificationStatus::Confirmed);
+        AddressVerificationState::clear(&staker.address_verification_state_path);
+
+        Ok(rotate)
     }
 }
 
@@ -922,9 +1547,11 @@ impl<'a> StakingContract<'a> {
 
         Ok(RoundState {
             number: relay_rounds_details.current_relay_round,
+            start_time: relay_rounds_details.current_relay_round_start_time,
+            end_time: relay_rounds_details.current_relay_round_end_time,
             elections_state,
             next_elections_account,
-            min_relay_deposit: relay_config.min_relay_deposit,
+            relay_config,
         })
     }
 }
@@ -932,9 +1559,11 @@ impl<'a> StakingContract<'a> {
 #[derive(Debug, Clone)]
 struct RoundState {
     number: u32,
+    start_time: u32,
+    end_time: u32,
     elections_state: ElectionsState,
     next_elections_account: UInt256,
-    min_relay_deposit: u128,
+    relay_config: RelayConfigDetails,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -944,22 +1573,6 @@ pub enum ElectionsState {
     Finished,
 }
 
-enum PendingElectionsState<'a> {
-    NotStarted {
-        start_time: u32,
-        inner_fut: Notified<'a>,
-        outer_fut: Notified<'a>,
-    },
-    Started {
-        end_time: u32,
-        inner_fut: Notified<'a>,
-        outer_fut: Notified<'a>,
-    },
-    Finished {
-        new_round_fut: Notified<'a>,
-    },
-}
-
 macro_rules! parse_tokens {
     ($res:expr,$fun:expr, $body:expr, $matched:expr) => {
         match $fun
@@ -1070,3 +1683,521 @@ enum StakingError {
     #[error("UserData TON public key mismatch")]
     UserDataTonPublicKeyMismatch,
 }
+
+#[cfg(test)]
+mod tests {
+    use pkey_mprotect::ProtectionKeys;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::engine::ton_chain::*;
+
+    const START_TIME: u32 = 1000;
+    const ELECTIONS_DURATION: u32 = 50;
+
+    /// Staking over the local chain with the ACCEPT-only staking contract
+    struct TestStaking {
+        _dir: TempDir,
+        clock: Arc<VirtualClock>,
+        chain: Arc<InMemoryTonChain>,
+        staking: Arc<Staking>,
+    }
+
+    impl TestStaking {
+        async fn new(relay_delay_ms: u64, elections_start_time: u32) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let clock = Arc::new(VirtualClock::new(START_TIME));
+            let messages_queue = PendingMessagesQueue::new(16);
+            let chain = InMemoryTonChain::new(messages_queue.clone(), START_TIME);
+            let ton_chain = chain.clone() as Arc<dyn TonChain>;
+
+            // ACCEPT
+            let staking_account = deploy_contract(&chain, UInt256::from([1; 32]), &[0xf8, 0x00]);
+
+            let mut settings: BridgeConfig = serde_yaml::from_str(
+                r#"
+                keys_path: keys.json
+                bridge_address: "0:0000000000000000000000000000000000000000000000000000000000000000"
+                networks: []
+                ignore_elections: true
+                elections_calls:
+                  max_relay_delay_sec: 30
+                  jitter_ms: 0
+                "#,
+            )
+            .unwrap();
+            settings.message_delivery.pending_messages_path = dir.path().join("pending.json");
+
+            // NOTE: the relay delay is derived from the staker address prefix
+            let mut staker_address = [0; 32];
+            staker_address[..8].copy_from_slice(&relay_delay_ms.to_be_bytes());
+            let staker = make_staker(
+                &dir,
+                staker_address,
+                Default::default(),
+                &ton_chain,
+                &messages_queue,
+            );
+
+            let context = EngineContext::new_in_memory(
+                settings,
+                vec![staker.clone()],
+                ton_chain,
+                messages_queue,
+                clock.clone(),
+            )
+            .await
+            .unwrap();
+
+            let round_state = round_state(
+                1,
+                ElectionsState::NotStarted {
+                    start_time: elections_start_time,
+                },
+            );
+            let staking = make_staking(context, staker, staking_account, round_state);
+
+            Self {
+                _dir: dir,
+                clock,
+                chain,
+                staking,
+            }
+        }
+
+        /// Applies the staking event emitted by this or another relay
+        async fn apply_event(
+            &self,
+            number: u32,
+            elections_state: ElectionsState,
+            event: StakingEvent,
+        ) {
+            let account = self.staking.staking_account;
+            self.staking
+                .clone()
+                .process_staking_event((account, (round_state(number, elections_state), event)))
+                .await
+                .unwrap();
+        }
+
+        async fn wait_metrics<F>(&self, f: F)
+        where
+            F: Fn(&ElectionsCallsMetrics) -> bool,
+        {
+            tokio::time::timeout(Duration::from_secs(10), async {
+                while !f(&self.staking.elections_calls.metrics()) {
+                    tokio::task::yield_now().await;
+                }
+            })
+            .await
+            .unwrap();
+        }
+
+        /// Gives spawned tasks enough polls to react to the current time
+        async fn settle(&self) {
+            for _ in 0..100 {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        /// Returns names of the functions called since the last check
+        fn take_calls(&self) -> Vec<&'static str> {
+            let functions = [
+                staking_contract::start_election_on_new_round(),
+                staking_contract::end_election(),
+            ];
+
+            self.chain
+                .take_sent_messages()
+                .into_iter()
+                .map(|message| {
+                    let body = message.body().unwrap();
+                    functions
+                        .iter()
+                        .find(|function| function.decode_input(body.clone(), false).is_ok())
+                        .map(|function| function.name.as_str())
+                        .unwrap()
+                })
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn full_elections_cycle_in_virtual_time() {
+        let test = TestStaking::new(0, 1100).await;
+        test.staking.start_managing_elections();
+
+        // NotStarted -> Started
+        test.clock.advance(Duration::from_secs(100));
+        test.wait_metrics(|metrics| metrics.start.triggered == 1)
+            .await;
+        assert_eq!(test.take_calls(), ["startElectionOnNewRound"]);
+
+        test.apply_event(
+            1,
+            ElectionsState::Started {
+                start_time: 1100,
+                end_time: 1100 + ELECTIONS_DURATION,
+            },
+            StakingEvent::ElectionStarted(ElectionStartedEvent {
+                round_num: 1,
+                election_start_time: 1100,
+                election_end_time: 1100 + ELECTIONS_DURATION,
+                election_addr: Default::default(),
+            }),
+        )
+        .await;
+
+        // Started -> Finished
+        test.clock
+            .advance(Duration::from_secs(ELECTIONS_DURATION as u64));
+        test.wait_metrics(|metrics| metrics.end.triggered == 1)
+            .await;
+        assert_eq!(test.take_calls(), ["endElection"]);
+
+        test.apply_event(
+            1,
+            ElectionsState::Finished,
+            StakingEvent::ElectionEnded(ElectionEndedEvent {
+                round_num: 1,
+                relay_requests: 1,
+                min_relays_ok: true,
+            }),
+        )
+        .await;
+
+        // Finished -> new round with the next elections
+        test.apply_event(
+            2,
+            ElectionsState::NotStarted { start_time: 1300 },
+            StakingEvent::RelayRoundInitialized(RelayRoundInitializedEvent {
+                round_num: 2,
+                round_start_time: 1150,
+                // NOTE: reward is claimed after the round end
+                round_end_time: u32::MAX / 2,
+                round_addr: Default::default(),
+                relays_count: 1,
+                duplicate: false,
+            }),
+        )
+        .await;
+
+        // Nothing is sent until the elections start time
+        test.clock.advance(Duration::from_secs(149));
+        test.settle().await;
+        assert!(test.take_calls().is_empty());
+        assert_eq!(test.staking.elections_calls.metrics().start.triggered, 1);
+
+        test.clock.advance(Duration::from_secs(1));
+        test.wait_metrics(|metrics| metrics.start.triggered == 2)
+            .await;
+        assert_eq!(test.take_calls(), ["startElectionOnNewRound"]);
+
+        let metrics = test.staking.elections_calls.metrics();
+        assert_eq!(metrics.start.preempted, 0);
+        assert_eq!(metrics.end.triggered, 1);
+    }
+
+    #[tokio::test]
+    async fn relay_delay_lets_other_relays_call_first() {
+        let test = TestStaking::new(10_000, 1100).await;
+        test.staking.start_managing_elections();
+
+        // Let the loop prepare notifications
+        tokio::task::yield_now().await;
+
+        // Another relay starts elections before the relay delay passes
+        test.clock.advance(Duration::from_secs(105));
+        test.apply_event(
+            1,
+            ElectionsState::Started {
+                start_time: 1105,
+                end_time: 1105 + ELECTIONS_DURATION,
+            },
+            StakingEvent::ElectionStarted(ElectionStartedEvent {
+                round_num: 1,
+                election_start_time: 1105,
+                election_end_time: 1105 + ELECTIONS_DURATION,
+                election_addr: Default::default(),
+            }),
+        )
+        .await;
+        test.wait_metrics(|metrics| metrics.start.preempted == 1)
+            .await;
+        assert!(test.take_calls().is_empty());
+
+        // No one ends elections, so this relay does it after its delay
+        test.clock
+            .advance(Duration::from_secs(ELECTIONS_DURATION as u64 + 9));
+        test.settle().await;
+        assert!(test.take_calls().is_empty());
+        assert_eq!(test.staking.elections_calls.metrics().end.triggered, 0);
+
+        test.clock.advance(Duration::from_secs(1));
+        test.wait_metrics(|metrics| metrics.end.triggered == 1)
+            .await;
+        assert_eq!(test.take_calls(), ["endElection"]);
+
+        let metrics = test.staking.elections_calls.metrics();
+        assert_eq!(metrics.start.triggered, 0);
+    }
+
+    #[tokio::test]
+    async fn only_elections_calls_are_sent_through_wallet() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = VirtualClock::new(START_TIME);
+        let messages_queue = PendingMessagesQueue::new(16);
+        let chain = InMemoryTonChain::new(messages_queue.clone(), START_TIME);
+        let ton_chain = chain as Arc<dyn TonChain>;
+
+        let wallet_account = UInt256::from([3; 32]);
+        let staker = make_staker(
+            &dir,
+            [0; 32],
+            MessageSenderConfig::Wallet(WalletSenderConfig {
+                address: ton_block::MsgAddressInt::with_standart(None, 0, wallet_account.into())
+                    .unwrap(),
+                wallet_type: WalletType::EverWallet,
+                attached_amount: 1_000_000_000,
+                subwallet_id: 0,
+                elections_only: true,
+            }),
+            &ton_chain,
+            &messages_queue,
+        );
+        let keystore = staker.keystore.current();
+
+        let staking_account = UInt256::from([1; 32]);
+        let user_data_account = UInt256::from([2; 32]);
+        let messages = [
+            (start_election_message(staking_account), wallet_account),
+            (end_election_message(staking_account), wallet_account),
+            (
+                become_relay_next_round_message(user_data_account),
+                user_data_account,
+            ),
+            (
+                get_reward_for_relay_round_message(user_data_account, 1),
+                user_data_account,
+            ),
+            (
+                confirm_ton_account_message(user_data_account),
+                user_data_account,
+            ),
+        ];
+
+        for (message, destination) in messages {
+            let prepared = staker
+                .message_sender
+                .prepare(&keystore, ton_chain.as_ref(), &clock, &message, 60)
+                .await
+                .unwrap();
+            assert_eq!(prepared.message.account, destination);
+        }
+    }
+
+    #[test]
+    fn next_keys_are_used_after_relay_round_with_them() {
+        let staker = UInt256::from([1; 32]);
+        let other_staker = UInt256::from([2; 32]);
+        let (current_ton_pubkey, current_eth_address) = (UInt256::from([3; 32]), [3; 20]);
+        let (next_ton_pubkey, next_eth_address) = (UInt256::from([4; 32]), [4; 20]);
+
+        let round = |ton_keys: Vec<UInt256>, eth_addrs: Vec<[u8; 20]>| RelayRoundDetails {
+            root: Default::default(),
+            round_num: 1,
+            staker_addrs: vec![other_staker, staker],
+            staked_tokens: vec![0; ton_keys.len()],
+            ton_keys,
+            eth_addrs,
+            relays_installed: true,
+            code_version: 0,
+        };
+
+        // Votes signed by the next keys are rejected in the round elected with the current keys
+        let details = round(
+            vec![UInt256::from([5; 32]), current_ton_pubkey],
+            vec![[5; 20], current_eth_address],
+        );
+        assert!(!relay_round_accepts_keys(
+            &details,
+            &staker,
+            &next_ton_pubkey,
+            &next_eth_address
+        ));
+
+        // Both keys must be in the round
+        let details = round(
+            vec![UInt256::from([5; 32]), next_ton_pubkey],
+            vec![[5; 20], current_eth_address],
+        );
+        assert!(!relay_round_accepts_keys(
+            &details,
+            &staker,
+            &next_ton_pubkey,
+            &next_eth_address
+        ));
+
+        let details = round(
+            vec![UInt256::from([5; 32]), next_ton_pubkey],
+            vec![[5; 20], next_eth_address],
+        );
+        assert!(relay_round_accepts_keys(
+            &details,
+            &staker,
+            &next_ton_pubkey,
+            &next_eth_address
+        ));
+
+        // Stakers which are not in the round don't vote
+        let mut details = round(vec![current_ton_pubkey], vec![current_eth_address]);
+        details.staker_addrs = vec![other_staker];
+        assert!(relay_round_accepts_keys(
+            &details,
+            &staker,
+            &next_ton_pubkey,
+            &next_eth_address
+        ));
+    }
+
+    fn make_staker(
+        dir: &TempDir,
+        staker_address: [u8; 32],
+        message_sender: MessageSenderConfig,
+        ton_chain: &Arc<dyn TonChain>,
+        messages_queue: &Arc<PendingMessagesQueue>,
+    ) -> Arc<StakerContext> {
+        let keys_path = dir.path().join("keys.json");
+        StoredKeysData::new(
+            "pwd",
+            UnencryptedEthData::generate().unwrap(),
+            UnencryptedTonData::generate().unwrap(),
+        )
+        .unwrap()
+        .save(&keys_path)
+        .unwrap();
+
+        StakerContext::new(
+            StakerProfileConfig {
+                staker_address: ton_block::MsgAddressInt::with_standart(
+                    None,
+                    0,
+                    UInt256::from(staker_address).into(),
+                )
+                .unwrap(),
+                keys_path,
+                next_keys_path: None,
+                staking_accounting_path: dir.path().join("accounting.json"),
+                address_verification_state_path: dir.path().join("verification.json"),
+                message_sender,
+            },
+            "pwd".into(),
+            ProtectionKeys::new(false).unwrap(),
+            ton_chain,
+            messages_queue,
+        )
+        .unwrap()
+    }
+
+    fn make_staking(
+        context: Arc<EngineContext>,
+        staker: Arc<StakerContext>,
+        staking_account: UInt256,
+        round_state: RoundState,
+    ) -> Arc<Staking> {
+        let user_data_account = UInt256::from([2; 32]);
+
+        let accounting_tx = start_json_writer(
+            "staking accounting",
+            staker.staking_accounting_path.clone(),
+            Default::default(),
+        );
+        let alerts = StakingAlerts::new(
+            staker.staker_account_str.clone(),
+            &context.settings.staking_alerts,
+        )
+        .unwrap();
+        let elections_calls =
+            ElectionsCalls::new(&staker.staker_account, &context.settings.elections_calls);
+
+        let (staking_events_tx, _) = context.events_queues.channel("StakingContract");
+        let (user_data_events_tx, _) = context.events_queues.channel("UserDataContract");
+
+        Arc::new(Staking {
+            context,
+            staker,
+            current_relay_round: Mutex::new(CurrentRelayRound {
+                user_data_balance: 0,
+                state: round_state,
+            }),
+            relay_round_started_notify: Default::default(),
+            elections_start_notify: Default::default(),
+            elections_end_notify: Default::default(),
+            relay_config_updated_notify: Default::default(),
+            user_data_balance_changed_notify: Default::default(),
+            participates_in_round: Tristate::new(Some(true)),
+            elected: Tristate::new(None),
+            skip_next_election: Default::default(),
+            last_election_decision: Default::default(),
+            elections_calls,
+            keys_rotation_in_progress: Default::default(),
+            relay_round_rewards: Default::default(),
+            user_reward_balances: Default::default(),
+            accounting: Default::default(),
+            accounting_tx,
+            alerts,
+            staking_account,
+            staking_observer: AccountObserver::new(&staking_events_tx),
+            user_data_account,
+            user_data_observer: AccountObserver::new(&user_data_events_tx),
+        })
+    }
+
+    fn round_state(number: u32, elections_state: ElectionsState) -> RoundState {
+        RoundState {
+            number,
+            start_time: 0,
+            end_time: u32::MAX,
+            elections_state,
+            next_elections_account: Default::default(),
+            relay_config: RelayConfigDetails {
+                relay_lock_time: 0,
+                relay_round_time: 0,
+                election_time: ELECTIONS_DURATION,
+                time_before_election: 0,
+                min_round_gap_time: 0,
+                relays_count: 1,
+                min_relay_count: 1,
+                min_relay_deposit: 0,
+                relay_initial_ton_deposit: 0,
+                relay_reward_per_second: 0,
+                user_reward_per_second: 0,
+            },
+        }
+    }
+
+    /// Deploys the contract with the specified raw code
+    fn deploy_contract(chain: &InMemoryTonChain, account: UInt256, code: &[u8]) -> UInt256 {
+        let address = ton_block::MsgAddressInt::with_standart(None, 0, account.into()).unwrap();
+
+        let code = ton_types::BuilderData::with_raw(code.to_vec(), code.len() * 8)
+            .unwrap()
+            .into_cell()
+            .unwrap();
+        let state_init = ton_block::StateInit {
+            code: Some(code),
+            data: Some(Default::default()),
+            ..Default::default()
+        };
+        let account = ton_block::Account::active_by_init_code_hash(
+            address,
+            ton_block::CurrencyCollection::with_grams(10_000_000_000),
+            100,
+            state_init,
+            false,
+        )
+        .unwrap();
+
+        chain.set_account(&account).unwrap()
+    }
+}
@@ -0,0 +1,395 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use tiny_adnl::utils::*;
+use tokio::sync::{oneshot, Notify};
+use ton_indexer::{EngineStatus, Subscriber};
+
+use super::{TonBackend, TonBackendMetrics};
+use crate::engine::ton_subscriber::*;
+use crate::utils::*;
+
+/// External blocks provider (indexer API, files, etc.)
+#[async_trait::async_trait]
+pub trait ExternalTonSource: Send + Sync {
+    /// Masterchain block seqno to start from. `None` if there are no blocks yet
+    async fn get_start_mc_seqno(&self) -> Result<Option<u32>>;
+
+    /// Shard accounts after the masterchain block which precedes `mc_seqno`.
+    /// Only loaded once on start, all later states are applied from blocks.
+    ///
+    /// NOTE: states must be full, because contracts are searched in them on start
+    async fn get_initial_shard_accounts(&self, mc_seqno: u32)
+        -> Result<Vec<ExternalShardAccounts>>;
+
+    /// Masterchain block with all new shard blocks. `None` if it is not available yet
+    async fn get_block(&self, mc_seqno: u32) -> Result<Option<ExternalBlock>>;
+
+    async fn send_message(&self, data: &[u8]) -> Result<()>;
+}
+
+pub struct ExternalBlock {
+    pub mc_block: ton_block::Block,
+    /// Shard blocks which were created since the previous masterchain block
+    pub shard_blocks: Vec<ExternalShardBlock>,
+}
+
+pub struct ExternalShardBlock {
+    pub id: ton_block::BlockIdExt,
+    pub block: ton_block::Block,
+    /// Account states after this block. External sources only provide
+    /// accounts which were changed in this block
+    pub accounts: ton_block::ShardAccounts,
+}
+
+pub struct ExternalShardAccounts {
+    pub shard: ton_block::ShardIdent,
+    /// Shard block seqno after which accounts are taken
+    pub seqno: u32,
+    pub accounts: ton_block::ShardAccounts,
+}
+
+/// Lightweight backend which doesn't require a full node
+pub struct ExternalBackend {
+    source: Arc<dyn ExternalTonSource>,
+    ton_subscriber: Arc<TonSubscriber>,
+    clock: Arc<dyn Clock>,
+    poll_interval: Duration,
+    /// Latest shard states with their block seqno
+    shard_accounts: Mutex<FxHashMap<ton_block::ShardIdent, (u32, ton_block::ShardAccounts)>>,
+    shard_accounts_changed: Notify,
+    last_mc_block_seqno: AtomicU32,
+    last_mc_utime: AtomicU32,
+    cancelled: AtomicBool,
+}
+
+impl ExternalBackend {
+    pub fn new(
+        source: Arc<dyn ExternalTonSource>,
+        ton_subscriber: Arc<TonSubscriber>,
+        clock: Arc<dyn Clock>,
+        poll_interval_sec: u64,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            source,
+            ton_subscriber,
+            clock,
+            poll_interval: Duration::from_secs(poll_interval_sec),
+            shard_accounts: Default::default(),
+            shard_accounts_changed: Notify::new(),
+            last_mc_block_seqno: Default::default(),
+            last_mc_utime: Default::default(),
+            cancelled: Default::default(),
+        })
+    }
+
+    async fn process_blocks(self: Arc<Self>, mut mc_seqno: u32, caught_up_tx: oneshot::Sender<()>) {
+        let mut caught_up_tx = Some(caught_up_tx);
+
+        while !self.cancelled.load(Ordering::Acquire) {
+            match self.source.get_block(mc_seqno).await {
+                Ok(Some(block)) => match self.process_block(block).await {
+                    Ok(()) => {
+                        mc_seqno += 1;
+                        continue;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to process masterchain block {}: {:?}", mc_seqno, e)
+                    }
+                },
+                Ok(None) => {
+                    if let Some(tx) = caught_up_tx.take() {
+                        log::info!("External blocks source is synced");
+                        tx.send(()).ok();
+                    }
+                }
+                Err(e) => log::error!("Failed to get masterchain block {}: {:?}", mc_seqno, e),
+            }
+
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    /// Processes shard blocks and then the masterchain block.
+    ///
+    /// NOTE: shard blocks which were processed before the masterchain block
+    /// failed are skipped on retry, so their transactions are dispatched once
+    async fn process_block(&self, block: ExternalBlock) -> Result<()> {
+        for shard_block in &block.shard_blocks {
+            let accounts = match merge_shard_accounts(
+                &self.shard_accounts.lock(),
+                &shard_block.id,
+                &shard_block.accounts,
+            )? {
+                Some(accounts) => accounts,
+                None => continue,
+            };
+
+            self.ton_subscriber
+                .handle_shard_block(&shard_block.id, &shard_block.block, &accounts)
+                .await?;
+
+            self.shard_accounts
+                .lock()
+                .insert(shard_block.id.shard_id, (shard_block.id.seq_no, accounts));
+            self.shard_accounts_changed.notify_waiters();
+        }
+
+        let block_info = block.mc_block.info.read_struct()?;
+        let gen_utime = block_info.gen_utime().0;
+        self.ton_subscriber
+            .handle_masterchain_block(gen_utime, &block.mc_block)?;
+
+        // Remove shards which were split or merged
+        let shards = extract_shards(&block.mc_block, &block_info)?.block_ids;
+        if !shards.is_empty() {
+            self.shard_accounts
+                .lock()
+                .retain(|shard, _| shards.contains_key(shard));
+        }
+
+        self.last_mc_block_seqno
+            .store(block_info.seq_no(), Ordering::Release);
+        self.last_mc_utime.store(gen_utime, Ordering::Release);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl TonBackend for ExternalBackend {
+    async fn start(self: Arc<Self>) -> Result<()> {
+        let mc_seqno = loop {
+            match self.source.get_start_mc_seqno().await? {
+                Some(seqno) => break seqno,
+                None => tokio::time::sleep(self.poll_interval).await,
+            }
+        };
+        log::info!("Processing external blocks since {}", mc_seqno);
+
+        {
+            let initial = self.source.get_initial_shard_accounts(mc_seqno).await?;
+            let mut shard_accounts = self.shard_accounts.lock();
+            for item in initial {
+                shard_accounts.insert(item.shard, (item.seqno, item.accounts));
+            }
+        }
+
+        // NOTE: all blocks from the source are processed, so there is nothing to skip
+        self.ton_subscriber
+            .engine_status_changed(EngineStatus::Synced)
+            .await;
+
+        let (caught_up_tx, caught_up_rx) = oneshot::channel();
+        tokio::spawn(self.clone().process_blocks(mc_seqno, caught_up_tx));
+        caught_up_rx.await?;
+
+        Ok(())
+    }
+
+    fn shutdown(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    async fn wait_shard_accounts(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<ton_block::ShardAccounts> {
+        loop {
+            let changed = self.shard_accounts_changed.notified();
+
+            let accounts = self
+                .shard_accounts
+                .lock()
+                .get(&block_id.shard_id)
+                .filter(|(seqno, _)| *seqno >= block_id.seq_no)
+                .map(|(_, accounts)| accounts.clone());
+            if let Some(accounts) = accounts {
+                return Ok(accounts);
+            }
+
+            changed.await;
+        }
+    }
+
+    async fn load_last_applied_shard_accounts(&self) -> Result<ShardAccountsMap> {
+        let shard_accounts = self.shard_accounts.lock();
+        if shard_accounts.is_empty() {
+            return Err(ExternalBackendError::StatesNotLoaded.into());
+        }
+
+        Ok(shard_accounts
+            .iter()
+            .map(|(shard, (_, accounts))| (*shard, accounts.clone()))
+            .collect())
+    }
+
+    async fn broadcast_external_message(
+        &self,
+        _: &ton_block::AccountIdPrefixFull,
+        data: &[u8],
+    ) -> Result<()> {
+        self.source.send_message(data).await
+    }
+
+    async fn load_stored_block(&self, mc_seqno: u32) -> Result<Option<ExternalBlock>> {
+        self.source.get_block(mc_seqno).await
+    }
+
+    fn metrics(&self) -> TonBackendMetrics {
+        let last_mc_block_seqno = self.last_mc_block_seqno.load(Ordering::Acquire);
+        let last_mc_utime = self.last_mc_utime.load(Ordering::Acquire);
+
+        let time_diff = self.clock.now_sec() as i64 - last_mc_utime as i64;
+        TonBackendMetrics {
+            mc_time_diff: time_diff,
+            shard_client_time_diff: time_diff,
+            last_mc_block_seqno,
+            last_shard_client_mc_block_seqno: last_mc_block_seqno,
+        }
+    }
+}
+
+/// Applies account states from the shard block to the latest states of its shard.
+/// Returns `None` if the block was already processed
+fn merge_shard_accounts(
+    states: &FxHashMap<ton_block::ShardIdent, (u32, ton_block::ShardAccounts)>,
+    block_id: &ton_block::BlockIdExt,
+    updates: &ton_block::ShardAccounts,
+) -> Result<Option<ton_block::ShardAccounts>> {
+    let shard = &block_id.shard_id;
+
+    let mut accounts = match states.get(shard) {
+        Some((seqno, _)) if *seqno >= block_id.seq_no => return Ok(None),
+        Some((_, accounts)) => accounts.clone(),
+        // NOTE: shard after split or merge starts from the states of its parents
+        None => {
+            let mut accounts = ton_block::ShardAccounts::default();
+            for (_, (_, parent_accounts)) in states
+                .iter()
+                .filter(|(other, _)| other.intersect_with(shard))
+            {
+                copy_shard_accounts(&mut accounts, parent_accounts)?;
+            }
+            accounts
+        }
+    };
+
+    copy_shard_accounts(&mut accounts, updates)?;
+    Ok(Some(accounts))
+}
+
+fn copy_shard_accounts(
+    target: &mut ton_block::ShardAccounts,
+    source: &ton_block::ShardAccounts,
+) -> Result<()> {
+    source.iterate_with_keys_and_aug(|account: ton_types::UInt256, shard_account, aug| {
+        target.set(&account, &shard_account, &aug)?;
+        Ok(true)
+    })?;
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+enum ExternalBackendError {
+    #[error("Shard states are not loaded yet")]
+    StatesNotLoaded,
+}
+
+#[cfg(test)]
+mod tests {
+    use ton_block::HashmapAugType;
+    use ton_types::UInt256;
+
+    use super::*;
+
+    fn make_accounts(items: &[(u8, u64)]) -> ton_block::ShardAccounts {
+        let mut accounts = ton_block::ShardAccounts::default();
+        for (id, lt) in items {
+            let address =
+                ton_block::MsgAddressInt::with_standart(None, 0, UInt256::from([*id; 32]).into())
+                    .unwrap();
+            accounts
+                .insert(
+                    0,
+                    &ton_block::Account::with_address(address),
+                    Default::default(),
+                    *lt,
+                )
+                .unwrap();
+        }
+        accounts
+    }
+
+    fn account_lt(accounts: &ton_block::ShardAccounts, id: u8) -> Option<u64> {
+        accounts
+            .get(&UInt256::from([id; 32]))
+            .unwrap()
+            .map(|account| account.last_trans_lt())
+    }
+
+    fn make_block_id(shard: ton_block::ShardIdent, seqno: u32) -> ton_block::BlockIdExt {
+        ton_block::BlockIdExt::with_params(shard, seqno, Default::default(), Default::default())
+    }
+
+    #[test]
+    fn changed_accounts_are_applied_to_latest_states() {
+        let shard = ton_block::ShardIdent::full(0);
+
+        let mut states = FxHashMap::default();
+        states.insert(shard, (10, make_accounts(&[(1, 100), (2, 100)])));
+
+        let accounts = merge_shard_accounts(
+            &states,
+            &make_block_id(shard, 11),
+            &make_accounts(&[(2, 200), (3, 200)]),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(account_lt(&accounts, 1), Some(100));
+        assert_eq!(account_lt(&accounts, 2), Some(200));
+        assert_eq!(account_lt(&accounts, 3), Some(200));
+    }
+
+    #[test]
+    fn processed_shard_blocks_are_skipped() {
+        let shard = ton_block::ShardIdent::full(0);
+
+        let mut states = FxHashMap::default();
+        states.insert(shard, (10, make_accounts(&[(1, 100)])));
+
+        // Retry of the masterchain block must not dispatch shard blocks again
+        for seqno in [9, 10] {
+            assert!(merge_shard_accounts(
+                &states,
+                &make_block_id(shard, seqno),
+                &make_accounts(&[(1, 200)]),
+            )
+            .unwrap()
+            .is_none());
+        }
+    }
+
+    #[test]
+    fn split_shard_starts_from_parent_states() {
+        let shard = ton_block::ShardIdent::full(0);
+        let (left, _) = shard.split().unwrap();
+
+        let mut states = FxHashMap::default();
+        states.insert(shard, (10, make_accounts(&[(1, 100)])));
+
+        let accounts = merge_shard_accounts(
+            &states,
+            &make_block_id(left, 11),
+            &make_accounts(&[(2, 200)]),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(account_lt(&accounts, 1), Some(100));
+        assert_eq!(account_lt(&accounts, 2), Some(200));
+    }
+}
@@ -0,0 +1,253 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use ton_block::Deserializable;
+
+use super::external_backend::*;
+
+/// Reads blocks from the directory with `{mc_seqno}.json` files and initial
+/// shard states from the `{mc_seqno}.states.json` file.
+/// Sent messages are written to the `messages` subdirectory
+pub struct FileTonSource {
+    path: PathBuf,
+}
+
+impl FileTonSource {
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ExternalTonSource for FileTonSource {
+    async fn get_start_mc_seqno(&self) -> Result<Option<u32>> {
+        let mut entries = tokio::fs::read_dir(&self.path).await?;
+
+        let mut result = None;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+
+            let seqno = match path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u32>().ok())
+            {
+                Some(seqno) => seqno,
+                None => continue,
+            };
+
+            result = Some(match result {
+                Some(min) => std::cmp::min(min, seqno),
+                None => seqno,
+            });
+        }
+
+        Ok(result)
+    }
+
+    async fn get_initial_shard_accounts(
+        &self,
+        mc_seqno: u32,
+    ) -> Result<Vec<ExternalShardAccounts>> {
+        let path = self.path.join(format!("{}.states.json", mc_seqno));
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let data = tokio::fs::read(&path).await?;
+        let states: Vec<String> = serde_json::from_slice(&data)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        states
+            .iter()
+            .map(|state| {
+                let state = ton_block::ShardStateUnsplit::construct_from_base64(state)?;
+                Ok(ExternalShardAccounts {
+                    shard: state.shard().clone(),
+                    seqno: state.seq_no(),
+                    accounts: state.read_accounts()?,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_block(&self, mc_seqno: u32) -> Result<Option<ExternalBlock>> {
+        let path = self.path.join(format!("{}.json", mc_seqno));
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let data = tokio::fs::read(&path).await?;
+        let block: StoredBlock = serde_json::from_slice(&data)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        let mut shard_blocks = Vec::with_capacity(block.shard_blocks.len());
+        for shard_block in block.shard_blocks {
+            let (id, block) = parse_shard_block(&shard_block.block)?;
+            shard_blocks.push(ExternalShardBlock {
+                id,
+                block,
+                accounts: parse_shard_accounts(&shard_block.accounts)?,
+            });
+        }
+
+        Ok(Some(ExternalBlock {
+            mc_block: ton_block::Block::construct_from_base64(&block.mc_block)?,
+            shard_blocks,
+        }))
+    }
+
+    async fn send_message(&self, data: &[u8]) -> Result<()> {
+        let cell = ton_types::deserialize_tree_of_cells(&mut std::io::Cursor::new(data))?;
+
+        let messages_path = self.path.join("messages");
+        tokio::fs::create_dir_all(&messages_path).await?;
+        tokio::fs::write(
+            messages_path.join(format!("{:x}.boc", cell.repr_hash())),
+            data,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Masterchain block with new shard blocks in base64 encoded BOCs
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoredBlock {
+    mc_block: String,
+    #[serde(default)]
+    shard_blocks: Vec<StoredShardBlock>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoredShardBlock {
+    block: String,
+    /// Base64 encoded `ShardAccount` BOCs of the changed accounts
+    #[serde(default)]
+    accounts: Vec<String>,
+}
+
+/// Parses the base64 encoded block BOC and computes its id
+fn parse_shard_block(data: &str) -> Result<(ton_block::BlockIdExt, ton_block::Block)> {
+    let bytes = base64::decode(data)?;
+    let cell = ton_types::deserialize_tree_of_cells(&mut std::io::Cursor::new(&bytes))?;
+    let root_hash = cell.repr_hash();
+
+    let block = ton_block::Block::construct_from_cell(cell)?;
+    let block_info = block.info.read_struct()?;
+    let id = ton_block::BlockIdExt::with_params(
+        *block_info.shard(),
+        block_info.seq_no(),
+        root_hash,
+        ton_types::UInt256::calc_file_hash(&bytes),
+    );
+
+    Ok((id, block))
+}
+
+fn parse_shard_accounts(accounts: &[String]) -> Result<ton_block::ShardAccounts> {
+    let mut result = ton_block::ShardAccounts::default();
+    for data in accounts {
+        let shard_account = ton_block::ShardAccount::construct_from_base64(data)?;
+        let account = shard_account.read_account()?;
+        result.insert(
+            0,
+            &account,
+            *shard_account.last_trans_hash(),
+            shard_account.last_trans_lt(),
+        )?;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use ton_block::{HashmapAugType, Serializable};
+    use ton_types::UInt256;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn start_seqno_is_the_lowest_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = FileTonSource::new(dir.path());
+
+        assert_eq!(source.get_start_mc_seqno().await.unwrap(), None);
+
+        for name in [
+            "12.json",
+            "10.json",
+            "5.states.json",
+            "3.txt",
+            "latest.json",
+        ] {
+            std::fs::write(dir.path().join(name), "{}").unwrap();
+        }
+        assert_eq!(source.get_start_mc_seqno().await.unwrap(), Some(10));
+
+        // Missing files are not available yet
+        assert!(source.get_block(11).await.unwrap().is_none());
+        assert!(source
+            .get_initial_shard_accounts(11)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn sent_messages_are_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = FileTonSource::new(dir.path());
+
+        let message =
+            ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+                dst: ton_block::MsgAddressInt::with_standart(
+                    None,
+                    0,
+                    UInt256::from([1; 32]).into(),
+                )
+                .unwrap(),
+                ..Default::default()
+            });
+        let cell = message.serialize().unwrap();
+        let data = message.write_to_bytes().unwrap();
+
+        source.send_message(&data).await.unwrap();
+
+        let path = dir
+            .path()
+            .join("messages")
+            .join(format!("{:x}.boc", cell.repr_hash()));
+        assert_eq!(std::fs::read(path).unwrap(), data);
+    }
+
+    #[test]
+    fn changed_accounts_are_parsed() {
+        let address =
+            ton_block::MsgAddressInt::with_standart(None, 0, UInt256::from([1; 32]).into())
+                .unwrap();
+        let shard_account = ton_block::ShardAccount::with_params(
+            &ton_block::Account::with_address(address),
+            Default::default(),
+            100,
+        )
+        .unwrap();
+        let data = base64::encode(shard_account.write_to_bytes().unwrap());
+
+        let accounts = parse_shard_accounts(&[data]).unwrap();
+        let parsed = accounts.get(&UInt256::from([1; 32])).unwrap().unwrap();
+        assert_eq!(parsed.last_trans_lt(), 100);
+    }
+}
@@ -0,0 +1,375 @@
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use tiny_adnl::utils::*;
+
+pub use self::external_backend::*;
+pub use self::file_source::*;
+use super::ton_subscriber::*;
+use crate::config::*;
+use crate::utils::*;
+
+mod external_backend;
+mod file_source;
+
+/// Source of TON blocks and shard states.
+/// All processed blocks are passed to the `TonSubscriber`
+#[async_trait::async_trait]
+pub trait TonBackend: Send + Sync {
+    /// Starts blocks processing. Returns when the subscriber has fresh states
+    async fn start(self: Arc<Self>) -> Result<()>;
+
+    fn shutdown(&self);
+
+    /// Waits until the state for the specified shard block is available
+    async fn wait_shard_accounts(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<ton_block::ShardAccounts>;
+
+    /// Loads shard accounts for the last applied masterchain block
+    async fn load_last_applied_shard_accounts(&self) -> Result<ShardAccountsMap>;
+
+    async fn broadcast_external_message(
+        &self,
+        to: &ton_block::AccountIdPrefixFull,
+        data: &[u8],
+    ) -> Result<()>;
+
+    /// Loads the already applied masterchain block with all shard blocks created
+    /// since the previous one. Returns `None` if the block is not available
+    async fn load_stored_block(&self, mc_seqno: u32) -> Result<Option<ExternalBlock>>;
+
+    fn metrics(&self) -> TonBackendMetrics;
+}
+
+pub async fn create_ton_backend(
+    config: &TonSourceConfig,
+    node_settings: NodeConfig,
+    global_config: ton_indexer::GlobalConfig,
+    ton_subscriber: Arc<TonSubscriber>,
+    clock: Arc<dyn Clock>,
+) -> Result<Arc<dyn TonBackend>> {
+    let backend: Arc<dyn TonBackend> = match config {
+        TonSourceConfig::Node => {
+            NodeBackend::new(node_settings, global_config, ton_subscriber, clock).await?
+        }
+        TonSourceConfig::File(config) => ExternalBackend::new(
+            Arc::new(FileTonSource::new(&config.path)),
+            ton_subscriber,
+            clock,
+            config.poll_interval_sec,
+        ),
+    };
+    Ok(backend)
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct TonBackendMetrics {
+    pub mc_time_diff: i64,
+    pub shard_client_time_diff: i64,
+    pub last_mc_block_seqno: u32,
+    pub last_shard_client_mc_block_seqno: u32,
+}
+
+/// Embedded full node
+pub struct NodeBackend {
+    engine: Arc<ton_indexer::Engine>,
+    ton_subscriber: Arc<TonSubscriber>,
+    clock: Arc<dyn Clock>,
+}
+
+impl NodeBackend {
+    pub async fn new(
+        node_settings: NodeConfig,
+        global_config: ton_indexer::GlobalConfig,
+        ton_subscriber: Arc<TonSubscriber>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Arc<Self>> {
+        let engine = ton_indexer::Engine::new(
+            node_settings
+                .build_indexer_config()
+                .await
+                .context("Failed to build node config")?,
+            global_config,
+            vec![ton_subscriber.clone() as Arc<dyn ton_indexer::Subscriber>],
+        )
+        .await
+        .context("Failed to start TON node")?;
+
+        Ok(Arc::new(Self {
+            engine,
+            ton_subscriber,
+            clock,
+        }))
+    }
+
+    async fn load_block(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<Option<ton_block::Block>> {
+        let handle = match self.engine.load_block_handle(block_id)? {
+            Some(handle) if handle.meta().has_data() => handle,
+            _ => return Ok(None),
+        };
+        let block = self.engine.load_block_data(&handle).await?;
+        Ok(Some(block.block().clone()))
+    }
+}
+
+#[async_trait::async_trait]
+impl TonBackend for NodeBackend {
+    async fn start(self: Arc<Self>) -> Result<()> {
+        self.engine.start().await?;
+        self.ton_subscriber.start(self.clock.as_ref()).await?;
+        Ok(())
+    }
+
+    fn shutdown(&self) {
+        self.engine.shutdown();
+    }
+
+    async fn wait_shard_accounts(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<ton_block::ShardAccounts> {
+        let shard = self.engine.wait_state(block_id, None, false).await?;
+        let accounts = shard.state().read_accounts()?;
+        Ok(accounts)
+    }
+
+    async fn load_last_applied_shard_accounts(&self) -> Result<ShardAccountsMap> {
+        let mc_block_id = self.engine.load_last_applied_mc_block_id().await?;
+        let mc_state = self.engine.load_state(&mc_block_id).await?;
+
+        let block_ids = collect_shard_block_ids(mc_state.shards()?)?;
+
+        let mut shard_accounts =
+            FxHashMap::with_capacity_and_hasher(block_ids.len(), Default::default());
+        for block_id in block_ids {
+            let shard = self.engine.load_state(&block_id).await?;
+            let accounts = shard.state().read_accounts()?;
+            shard_accounts.insert(block_id.shard_id, accounts);
+        }
+
+        Ok(shard_accounts)
+    }
+
+    async fn broadcast_external_message(
+        &self,
+        to: &ton_block::AccountIdPrefixFull,
+        data: &[u8],
+    ) -> Result<()> {
+        self.engine.broadcast_external_message(to, data).await
+    }
+
+    async fn load_stored_block(&self, mc_seqno: u32) -> Result<Option<ExternalBlock>> {
+        let (mc_block_id, prev_mc_block_id) = {
+            let last_mc_block_id = self.engine.load_last_applied_mc_block_id().await?;
+            let last_mc_state = self.engine.load_state(&last_mc_block_id).await?;
+            let extra = last_mc_state
+                .state()
+                .read_custom()?
+                .ok_or(NodeBackendError::InvalidMasterchainState)?;
+
+            let find_block_id = |seqno: u32| -> Result<Option<ton_block::BlockIdExt>> {
+                Ok(extra.prev_blocks.get(&seqno)?.map(|item| {
+                    let blk_ref = item.blk_ref();
+                    ton_block::BlockIdExt::with_params(
+                        ton_block::ShardIdent::masterchain(),
+                        blk_ref.seq_no,
+                        blk_ref.root_hash,
+                        blk_ref.file_hash,
+                    )
+                }))
+            };
+
+            match find_block_id(mc_seqno)? {
+                Some(block_id) => (block_id, find_block_id(mc_seqno.saturating_sub(1))?),
+                None => return Ok(None),
+            }
+        };
+
+        let mc_block = match self.load_block(&mc_block_id).await? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+
+        // Shard blocks of the previous masterchain block are the lower bound
+        let prev_shards = match prev_mc_block_id {
+            Some(block_id) => match self.load_block(&block_id).await? {
+                Some(block) => extract_shards(&block, &block.info.read_struct()?)?.block_ids,
+                None => Default::default(),
+            },
+            None => Default::default(),
+        };
+
+        let mut shard_blocks = Vec::new();
+        for (shard, top_block_id) in
+            extract_shards(&mc_block, &mc_block.info.read_struct()?)?.block_ids
+        {
+            let lower_bound = prev_shards
+                .get(&shard)
+                .map(|id| id.seq_no)
+                .unwrap_or_default();
+
+            // Collect shard blocks in reverse order
+            let mut blocks = Vec::new();
+            let mut block_id = Some(top_block_id);
+            while let Some(id) = block_id.take() {
+                if id.seq_no <= lower_bound {
+                    break;
+                }
+
+                let block = self
+                    .load_block(&id)
+                    .await?
+                    .ok_or_else(|| NodeBackendError::ShardBlockNotAvailable(id.clone()))?;
+
+                let block_info = block.info.read_struct()?;
+                if !block_info.after_split() && !block_info.after_merge() {
+                    if let ton_block::BlkPrevInfo::Block { prev } = block_info.read_prev_ref()? {
+                        block_id = Some(ton_block::BlockIdExt::with_params(
+                            shard,
+                            prev.seq_no,
+                            prev.root_hash,
+                            prev.file_hash,
+                        ));
+                    }
+                }
+
+                // NOTE: states might have been removed by GC, so the replay must fail
+                // instead of silently skipping transactions
+                let state = self
+                    .engine
+                    .load_state(&id)
+                    .await
+                    .with_context(|| format!("Shard state {} is not available", id))?;
+                blocks.push(ExternalShardBlock {
+                    id,
+                    block,
+                    accounts: state.state().read_accounts()?,
+                });
+            }
+
+            shard_blocks.extend(blocks.into_iter().rev());
+        }
+
+        Ok(Some(ExternalBlock {
+            mc_block,
+            shard_blocks,
+        }))
+    }
+
+    fn metrics(&self) -> TonBackendMetrics {
+        let metrics = self.engine.metrics();
+        TonBackendMetrics {
+            mc_time_diff: metrics.mc_time_diff.load(Ordering::Acquire),
+            shard_client_time_diff: metrics.shard_client_time_diff.load(Ordering::Acquire),
+            last_mc_block_seqno: metrics.last_mc_block_seqno.load(Ordering::Acquire),
+            last_shard_client_mc_block_seqno: metrics
+                .last_shard_client_mc_block_seqno
+                .load(Ordering::Acquire),
+        }
+    }
+}
+
+/// Node DB opened as a RocksDB secondary instance.
+///
+/// Doesn't start the node and doesn't touch the network, so it can be used
+/// while the relay is running. Only already applied states are available
+pub struct ReadOnlyNodeBackend {
+    db: Arc<ton_indexer::Db>,
+    /// NOTE: must be dropped after the DB
+    _temp_secondary_dir: Option<TempDir>,
+}
+
+impl ReadOnlyNodeBackend {
+    /// Opens the node DB. Secondary instance files are stored in `secondary_path`
+    /// or in a new temporary directory, so nothing is written to the node DB directory
+    pub fn new(node_settings: &NodeConfig, secondary_path: Option<PathBuf>) -> Result<Self> {
+        let rocks_db_path = node_settings.db_path.join("rocksdb");
+        if !rocks_db_path.exists() {
+            return Err(NodeBackendError::DbNotFound.into());
+        }
+
+        let (secondary_path, temp_secondary_dir) = match secondary_path {
+            Some(path) => (path, None),
+            None => {
+                let path = std::env::temp_dir()
+                    .join(format!("relay-rocksdb-secondary-{}", uuid::Uuid::new_v4()));
+                (path.clone(), Some(TempDir(path)))
+            }
+        };
+        if secondary_path.starts_with(&node_settings.db_path) {
+            return Err(NodeBackendError::InvalidSecondaryPath.into());
+        }
+        std::fs::create_dir_all(&secondary_path)
+            .context("Failed to create secondary instance directory")?;
+
+        let db = ton_indexer::Db::open_secondary(
+            rocks_db_path,
+            &secondary_path,
+            node_settings.db_path.join("files"),
+        )
+        .context("Failed to open node DB as secondary instance")?;
+
+        Ok(Self {
+            db,
+            _temp_secondary_dir: temp_secondary_dir,
+        })
+    }
+
+    /// Loads shard accounts for the last applied masterchain block
+    pub async fn load_last_applied_shard_accounts(&self) -> Result<ShardAccountsMap> {
+        let mc_block_id = self.db.load_last_applied_mc_block_id()?;
+        let mc_state = self.db.load_shard_state(&mc_block_id).await?;
+
+        let mut shard_accounts = FxHashMap::default();
+        for block_id in collect_shard_block_ids(mc_state.shards()?)? {
+            let shard = self.db.load_shard_state(&block_id).await?;
+            shard_accounts.insert(block_id.shard_id, shard.state().read_accounts()?);
+        }
+
+        Ok(shard_accounts)
+    }
+}
+
+/// Directory which is removed on drop
+struct TempDir(PathBuf);
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.0) {
+            log::warn!("Failed to remove temporary directory {:?}: {:?}", self.0, e);
+        }
+    }
+}
+
+fn collect_shard_block_ids(shards: &ton_block::ShardHashes) -> Result<Vec<ton_block::BlockIdExt>> {
+    let mut block_ids = Vec::new();
+    shards.iterate_shards(|shard_ident, descr| {
+        block_ids.push(ton_block::BlockIdExt::with_params(
+            shard_ident,
+            descr.seq_no,
+            descr.root_hash,
+            descr.file_hash,
+        ));
+        Ok(true)
+    })?;
+    Ok(block_ids)
+}
+
+#[derive(thiserror::Error, Debug)]
+enum NodeBackendError {
+    #[error("Invalid masterchain state")]
+    InvalidMasterchainState,
+    #[error("Node DB not found")]
+    DbNotFound,
+    #[error("Secondary instance directory must be outside the node DB directory")]
+    InvalidSecondaryPath,
+    #[error("Shard block {0} is not available")]
+    ShardBlockNotAvailable(ton_block::BlockIdExt),
+}
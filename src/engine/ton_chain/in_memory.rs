@@ -0,0 +1,758 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use tiny_adnl::utils::*;
+use tokio::sync::Notify;
+use ton_block::{Deserializable, Serializable};
+use ton_executor::TransactionExecutor;
+use ton_types::UInt256;
+
+use super::{TonChain, TonChainMetrics};
+use crate::engine::ton_backend::TonBackendMetrics;
+use crate::engine::ton_subscriber::*;
+use crate::utils::*;
+
+/// Local basechain for tests.
+///
+/// Contract states are stored in memory and all get-methods are executed
+/// on them. Broadcasted messages are recorded and executed on the stored
+/// states with all produced internal messages. Each state change is a new block
+pub struct InMemoryTonChain {
+    messages_queue: Arc<PendingMessagesQueue>,
+    transaction_handlers: Arc<TransactionHandlers>,
+    executor: ton_executor::OrdinaryTransactionExecutor,
+    current_utime: AtomicU32,
+    state: Mutex<InMemoryChainState>,
+    state_changed: Notify,
+}
+
+#[derive(Default)]
+struct InMemoryChainState {
+    block_seqno: u32,
+    /// Logical time for the next transaction
+    next_lt: u64,
+    accounts: FxHashMap<UInt256, ton_block::ShardAccount>,
+    account_states: FxHashMap<UInt256, AccountStateChannel>,
+    subscriptions: FxHashMap<UInt256, Vec<Weak<dyn TransactionsSubscription>>>,
+    sent_messages: Vec<ton_block::Message>,
+}
+
+impl InMemoryTonChain {
+    pub fn new(messages_queue: Arc<PendingMessagesQueue>, current_utime: u32) -> Arc<Self> {
+        Arc::new(Self {
+            messages_queue,
+            transaction_handlers: TransactionHandlers::new(&Default::default()),
+            executor: ton_executor::OrdinaryTransactionExecutor::new(Default::default()),
+            current_utime: AtomicU32::new(current_utime),
+            state: Default::default(),
+            state_changed: Notify::new(),
+        })
+    }
+
+    /// Moves time forward. Expires pending messages
+    pub fn set_current_utime(&self, current_utime: u32) {
+        self.current_utime.store(current_utime, Ordering::Release);
+        self.messages_queue
+            .update(&ton_block::ShardIdent::full(0), current_utime);
+        self.next_block();
+    }
+
+    /// Deploys or replaces the account state
+    pub fn set_account(&self, account: &ton_block::Account) -> Result<UInt256> {
+        let address = account
+            .get_addr()
+            .ok_or(InMemoryChainError::AccountWithoutAddress)?;
+        let account_id = only_account_hash(address);
+
+        let shard_account = ton_block::ShardAccount::with_params(account, UInt256::default(), 0)?;
+        {
+            let mut state = self.state.lock();
+            state.accounts.insert(account_id, shard_account);
+            state.block_seqno += 1;
+            state.notify_account_state(&account_id);
+        }
+        self.state_changed.notify_waiters();
+
+        Ok(account_id)
+    }
+
+    /// Deploys the account from the base64 encoded BOC (e.g. real contract state)
+    pub fn set_account_from_boc(&self, boc: &str) -> Result<UInt256> {
+        let account =
+            ton_block::Account::construct_from_base64(boc).context("Invalid account BOC")?;
+        self.set_account(&account)
+    }
+
+    /// Updates the account state and passes the transaction to subscriptions
+    pub fn apply_transaction(
+        &self,
+        transaction: &ton_block::Transaction,
+        account: &ton_block::Account,
+    ) -> Result<()> {
+        let address = account
+            .get_addr()
+            .ok_or(InMemoryChainError::AccountWithoutAddress)?;
+        let account_id = only_account_hash(address);
+        let hash = transaction.serialize()?.repr_hash();
+
+        let shard_account =
+            ton_block::ShardAccount::with_params(account, hash, transaction.logical_time())?;
+
+        let (subscriptions, shard_accounts, block_info) = {
+            let mut state = self.state.lock();
+            state.accounts.insert(account_id, shard_account);
+            state.block_seqno += 1;
+            state.notify_account_state(&account_id);
+
+            let subscriptions = state
+                .subscriptions
+                .get(&account_id)
+                .map(|items| items.iter().filter_map(Weak::upgrade).collect::<Vec<_>>())
+                .unwrap_or_default();
+
+            (
+                subscriptions,
+                build_shard_accounts(&state.accounts)?,
+                self.make_block_info(state.block_seqno)?,
+            )
+        };
+
+        // NOTE: subscriptions are called outside the lock as in the real subscriber
+        dispatch_transaction(
+            Some(&self.messages_queue),
+            &self.transaction_handlers,
+            &subscriptions,
+            &shard_accounts,
+            &block_info,
+            &account_id,
+            &hash,
+            transaction,
+        );
+
+        self.state_changed.notify_waiters();
+        Ok(())
+    }
+
+    /// Executes the message on the destination account and then all produced
+    /// internal messages on the known accounts. Returns executed transactions.
+    ///
+    /// NOTE: messages to unknown accounts and not accepted external messages are skipped
+    pub fn execute_message(
+        &self,
+        message: &ton_block::Message,
+    ) -> Result<Vec<ton_block::Transaction>> {
+        let mut messages = VecDeque::from([message.clone()]);
+        let mut transactions = Vec::new();
+
+        while let Some(message) = messages.pop_front() {
+            let account_id = match message.dst_ref() {
+                Some(dst) => only_account_hash(dst),
+                None => continue,
+            };
+
+            let (transaction, account) = match self.execute_on_account(&account_id, &message) {
+                Ok(Some(result)) => result,
+                Ok(None) => continue,
+                Err(e) if message.is_inbound_external() => {
+                    log::warn!(
+                        "External message to {:x} was not accepted: {:?}",
+                        account_id,
+                        e
+                    );
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            transaction
+                .out_msgs
+                .iterate(|ton_block::InRefValue(message)| {
+                    if message.is_internal() {
+                        messages.push_back(message);
+                    }
+                    Ok(true)
+                })?;
+
+            self.apply_transaction(&transaction, &account)?;
+            transactions.push(transaction);
+        }
+
+        Ok(transactions)
+    }
+
+    /// Returns all broadcasted messages since the last call
+    pub fn take_sent_messages(&self) -> Vec<ton_block::Message> {
+        std::mem::take(&mut self.state.lock().sent_messages)
+    }
+
+    /// Produces an empty block
+    pub fn next_block(&self) {
+        self.state.lock().block_seqno += 1;
+        self.state_changed.notify_waiters();
+    }
+
+    fn execute_on_account(
+        &self,
+        account_id: &UInt256,
+        message: &ton_block::Message,
+    ) -> Result<Option<(ton_block::Transaction, ton_block::Account)>> {
+        let (shard_account, block_lt) = {
+            let state = self.state.lock();
+            match state.accounts.get(account_id) {
+                Some(shard_account) => (shard_account.clone(), state.next_lt),
+                None => return Ok(None),
+            }
+        };
+
+        let mut account_root = shard_account.account_cell();
+        let params = ton_executor::ExecuteParams {
+            block_unixtime: self.current_utime(),
+            block_lt,
+            last_tr_lt: Arc::new(AtomicU64::new(shard_account.last_trans_lt())),
+            ..Default::default()
+        };
+        let transaction =
+            self.executor
+                .execute_with_libs_and_params(Some(message), &mut account_root, params)?;
+        let account = ton_block::Account::construct_from_cell(account_root)?;
+
+        self.state.lock().next_lt = transaction.logical_time() + LT_STEP;
+        Ok(Some((transaction, account)))
+    }
+
+    fn make_block_info(&self, seqno: u32) -> Result<ton_block::BlockInfo> {
+        let mut block_info = ton_block::BlockInfo::default();
+        block_info.set_seq_no(seqno)?;
+        block_info.set_gen_utime(ton_block::UnixTime32(
+            self.current_utime.load(Ordering::Acquire),
+        ));
+        Ok(block_info)
+    }
+}
+
+impl InMemoryChainState {
+    fn notify_account_state(&self, account: &UInt256) {
+        let channel = match self.account_states.get(account) {
+            Some(channel) => channel,
+            None => return,
+        };
+
+        let shard_account = self.accounts.get(account).cloned();
+        let update = AccountStateUpdate {
+            block_id: ton_block::BlockIdExt::with_params(
+                ton_block::ShardIdent::full(0),
+                self.block_seqno,
+                Default::default(),
+                Default::default(),
+            ),
+            lt: shard_account
+                .as_ref()
+                .map(|account| account.last_trans_lt() + 1)
+                .unwrap_or_default(),
+            shard_account,
+        };
+        if let Err(e) = channel.send(update) {
+            log::error!("Failed to send account {:x} state: {:?}", account, e);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TonChain for InMemoryTonChain {
+    async fn start(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) {}
+
+    fn metrics(&self) -> TonChainMetrics {
+        TonChainMetrics {
+            subscriber: TonSubscriberMetrics {
+                ready: true,
+                current_utime: self.current_utime(),
+                pending_message_count: self.messages_queue.len(),
+                dispatch_queue_len: 0,
+            },
+            backend: TonBackendMetrics::default(),
+        }
+    }
+
+    fn current_utime(&self) -> u32 {
+        self.current_utime.load(Ordering::Acquire)
+    }
+
+    fn last_processed_mc_seqno(&self) -> u32 {
+        self.state.lock().block_seqno
+    }
+
+    /// NOTE: all blocks are live
+    async fn first_live_mc_seqno(&self) -> u32 {
+        self.state.lock().block_seqno
+    }
+
+    /// NOTE: blocks are not stored, so there is nothing to replay
+    async fn replay_blocks(&self, _: u32, _: u32) -> Result<()> {
+        Ok(())
+    }
+
+    async fn resume_live_dispatch(&self) {}
+
+    /// NOTE: waits for the next block as the live chain does
+    async fn get_contract_state(&self, account: UInt256) -> Result<Option<ExistingContract>> {
+        let block_seqno = self.state.lock().block_seqno;
+        loop {
+            let changed = self.state_changed.notified();
+
+            {
+                let state = self.state.lock();
+                if state.block_seqno > block_seqno {
+                    return match state.accounts.get(&account) {
+                        Some(shard_account) => ExistingContract::from_shard_account(shard_account),
+                        None => Ok(None),
+                    };
+                }
+            }
+
+            changed.await;
+        }
+    }
+
+    async fn wait_contract_state(&self, account: UInt256) -> Result<ExistingContract> {
+        loop {
+            if let Some(contract) = self.get_contract_state(account).await? {
+                match &contract.account.storage.state {
+                    ton_block::AccountState::AccountActive { .. } => return Ok(contract),
+                    ton_block::AccountState::AccountFrozen { .. } => {
+                        return Err(InMemoryChainError::AccountIsFrozen.into())
+                    }
+                    ton_block::AccountState::AccountUninit => {}
+                }
+            }
+        }
+    }
+
+    fn last_known_transaction_lt(&self, account: &UInt256) -> Option<u64> {
+        self.state
+            .lock()
+            .accounts
+            .get(account)
+            .map(|shard_account| shard_account.last_trans_lt())
+    }
+
+    fn last_known_contract_state(&self, account: &UInt256) -> Result<Option<ExistingContract>> {
+        match self.state.lock().accounts.get(account) {
+            Some(shard_account) => ExistingContract::from_shard_account(shard_account),
+            None => Ok(None),
+        }
+    }
+
+    fn subscribe_account_state_with_history(
+        &self,
+        account: UInt256,
+        depth: usize,
+    ) -> AccountStateSubscription {
+        self.state
+            .lock()
+            .account_states
+            .entry(account)
+            .or_default()
+            .subscribe_with_history(depth)
+    }
+
+    fn subscribe_transactions(
+        &self,
+        accounts: &[UInt256],
+        subscription: Weak<dyn TransactionsSubscription>,
+    ) {
+        let mut state = self.state.lock();
+        for account in accounts {
+            state
+                .subscriptions
+                .entry(*account)
+                .or_default()
+                .push(subscription.clone());
+        }
+    }
+
+    async fn wait_shards(&self, since: Option<u32>) -> Result<LatestShardBlocks> {
+        loop {
+            let changed = self.state_changed.notified();
+
+            let current_utime = self.current_utime();
+            if !matches!(since, Some(since) if current_utime < since) {
+                let mc_seqno = self.state.lock().block_seqno;
+                let shard = ton_block::ShardIdent::full(0);
+                let block_id = ton_block::BlockIdExt::with_params(
+                    shard,
+                    mc_seqno,
+                    Default::default(),
+                    Default::default(),
+                );
+
+                let mut block_ids = FxHashMap::with_capacity_and_hasher(1, Default::default());
+                block_ids.insert(shard, block_id);
+
+                return Ok(LatestShardBlocks {
+                    mc_seqno,
+                    current_utime,
+                    block_ids,
+                });
+            }
+
+            changed.await;
+        }
+    }
+
+    async fn wait_shard_accounts(
+        &self,
+        _: &ton_block::BlockIdExt,
+    ) -> Result<ton_block::ShardAccounts> {
+        build_shard_accounts(&self.state.lock().accounts)
+    }
+
+    async fn load_last_applied_shard_accounts(&self) -> Result<ShardAccountsMap> {
+        let mut result = FxHashMap::with_capacity_and_hasher(1, Default::default());
+        result.insert(
+            ton_block::ShardIdent::full(0),
+            build_shard_accounts(&self.state.lock().accounts)?,
+        );
+        Ok(result)
+    }
+
+    async fn broadcast_external_message(
+        &self,
+        _: &ton_block::AccountIdPrefixFull,
+        data: &[u8],
+    ) -> Result<()> {
+        let message = ton_block::Message::construct_from_bytes(data)?;
+        self.state.lock().sent_messages.push(message.clone());
+
+        // NOTE: execution errors are only visible through the messages queue
+        if let Err(e) = self.execute_message(&message) {
+            log::error!("Failed to execute external message: {:?}", e);
+        }
+        Ok(())
+    }
+}
+
+fn build_shard_accounts(
+    accounts: &FxHashMap<UInt256, ton_block::ShardAccount>,
+) -> Result<ton_block::ShardAccounts> {
+    let mut result = ton_block::ShardAccounts::default();
+    for shard_account in accounts.values() {
+        let account = shard_account.read_account()?;
+        result.insert(
+            0,
+            &account,
+            *shard_account.last_trans_hash(),
+            shard_account.last_trans_lt(),
+        )?;
+    }
+    Ok(result)
+}
+
+/// Logical time gap between transactions, enough for all produced messages
+const LT_STEP: u64 = 1000;
+
+#[derive(thiserror::Error, Debug)]
+enum InMemoryChainError {
+    #[error("Account without address")]
+    AccountWithoutAddress,
+    #[error("Account is frozen")]
+    AccountIsFrozen,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::keystore::UnsignedMessage;
+    use crate::engine::ton_contracts::*;
+
+    fn make_external_message(account: UInt256) -> Vec<u8> {
+        let message =
+            ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+                dst: ton_block::MsgAddressInt::with_standart(None, 0, account.into()).unwrap(),
+                ..Default::default()
+            });
+        message.write_to_bytes().unwrap()
+    }
+
+    #[tokio::test]
+    async fn messages_are_recorded() {
+        let chain = InMemoryTonChain::new(PendingMessagesQueue::new(16), 100);
+        let chain_dyn = chain.clone() as Arc<dyn TonChain>;
+
+        let account = UInt256::from([1; 32]);
+        let to = ton_block::AccountIdPrefixFull::workchain(0, 0);
+        chain_dyn
+            .broadcast_external_message(&to, &make_external_message(account))
+            .await
+            .unwrap();
+
+        let messages = chain.take_sent_messages();
+        assert_eq!(messages.len(), 1);
+        assert!(chain.take_sent_messages().is_empty());
+
+        // Unknown accounts are not deployed
+        let state = tokio::spawn(async move { chain_dyn.get_contract_state(account).await });
+        tokio::task::yield_now().await;
+        chain.next_block();
+        assert!(state.await.unwrap().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn last_known_state_doesnt_wait_for_block() {
+        let chain = InMemoryTonChain::new(PendingMessagesQueue::new(16), 100);
+
+        let address = deploy_contract(&chain, &[0xf8, 0x00]);
+        let account = only_account_hash(&address);
+
+        let chain = chain as Arc<dyn TonChain>;
+        assert!(chain.last_known_contract_state(&account).unwrap().is_some());
+        assert!(chain
+            .last_known_contract_state(&UInt256::from([2; 32]))
+            .unwrap()
+            .is_none());
+    }
+
+    /// Records transactions and function ids of their external messages
+    #[derive(Default)]
+    struct RecordingObserver {
+        transactions: Mutex<Vec<(UInt256, u32)>>,
+    }
+
+    impl TransactionsSubscription for RecordingObserver {
+        fn handle_transaction(&self, ctx: TxContext<'_>) -> Result<()> {
+            let function_id = nekoton_abi::read_function_id(&ctx.in_msg.body().unwrap())?;
+            self.transactions
+                .lock()
+                .push((*ctx.transaction_hash, function_id));
+            Ok(())
+        }
+    }
+
+    /// Deploys the contract with the specified raw code
+    fn deploy_contract(chain: &InMemoryTonChain, code: &[u8]) -> ton_block::MsgAddressInt {
+        let address =
+            ton_block::MsgAddressInt::with_standart(None, 0, UInt256::from([1; 32]).into())
+                .unwrap();
+
+        let code = ton_types::BuilderData::with_raw(code.to_vec(), code.len() * 8)
+            .unwrap()
+            .into_cell()
+            .unwrap();
+        let state_init = ton_block::StateInit {
+            code: Some(code),
+            data: Some(Default::default()),
+            ..Default::default()
+        };
+        let account = ton_block::Account::active_by_init_code_hash(
+            address.clone(),
+            ton_block::CurrencyCollection::with_grams(10_000_000_000),
+            100,
+            state_init,
+            false,
+        )
+        .unwrap();
+
+        chain.set_account(&account).unwrap();
+        address
+    }
+
+    async fn send_event_vote(
+        chain: &Arc<InMemoryTonChain>,
+        messages_queue: &PendingMessagesQueue,
+        address: &ton_block::MsgAddressInt,
+    ) -> MessageStatus {
+        let account = only_account_hash(address);
+        let message = UnsignedMessage::new(eth_event_contract::confirm(), account)
+            .arg(address.clone())
+            .build_without_signature(&SystemClock)
+            .unwrap();
+
+        let rx = messages_queue
+            .add_message(
+                account,
+                message.message.serialize().unwrap().repr_hash(),
+                message.expire_at,
+            )
+            .unwrap();
+
+        let chain = chain.clone() as Arc<dyn TonChain>;
+        chain
+            .broadcast_external_message(
+                &ton_block::AccountIdPrefixFull::workchain(0, 0),
+                &message.message.write_to_bytes().unwrap(),
+            )
+            .await
+            .unwrap();
+
+        rx.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn event_confirmation_is_executed() {
+        let messages_queue = PendingMessagesQueue::new(16);
+        let chain = InMemoryTonChain::new(messages_queue.clone(), 100);
+
+        // ACCEPT
+        let address = deploy_contract(&chain, &[0xf8, 0x00]);
+        let account = only_account_hash(&address);
+
+        let observer = Arc::new(RecordingObserver::default());
+        (chain.clone() as Arc<dyn TonChain>).add_transactions_subscription([account], &observer);
+
+        let transaction_hash = match send_event_vote(&chain, &messages_queue, &address).await {
+            MessageStatus::Delivered { transaction_hash } => transaction_hash,
+            status => panic!("Unexpected status: {:?}", status),
+        };
+
+        assert_eq!(
+            observer.transactions.lock().as_slice(),
+            &[(transaction_hash, eth_event_contract::confirm().input_id)]
+        );
+
+        // Account state is updated by the executed transaction
+        let chain_dyn = chain.clone() as Arc<dyn TonChain>;
+        assert!(chain_dyn.last_known_transaction_lt(&account).unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn failed_event_confirmation_is_reported() {
+        let messages_queue = PendingMessagesQueue::new(16);
+        let chain = InMemoryTonChain::new(messages_queue.clone(), 100);
+
+        // ACCEPT; THROW 50
+        let address = deploy_contract(&chain, &[0xf8, 0x00, 0xf2, 0x32]);
+
+        assert_eq!(
+            send_event_vote(&chain, &messages_queue, &address).await,
+            MessageStatus::Failed {
+                exit_code: 50,
+                phase: TransactionPhase::Compute
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn not_accepted_messages_expire() {
+        let messages_queue = PendingMessagesQueue::new(16);
+        let chain = InMemoryTonChain::new(messages_queue.clone(), 100);
+
+        // THROW 50 without ACCEPT
+        let address = deploy_contract(&chain, &[0xf2, 0x32]);
+
+        let status = {
+            let chain = chain.clone();
+            let messages_queue = messages_queue.clone();
+            tokio::spawn(async move { send_event_vote(&chain, &messages_queue, &address).await })
+        };
+        tokio::task::yield_now().await;
+        chain.set_current_utime(u32::MAX);
+
+        assert_eq!(status.await.unwrap(), MessageStatus::Expired);
+    }
+
+    #[tokio::test]
+    async fn shards_are_waited_by_time() {
+        let chain = InMemoryTonChain::new(PendingMessagesQueue::new(16), 100);
+
+        let shards = chain.wait_shards(Some(50)).await.unwrap();
+        assert_eq!(shards.current_utime, 100);
+        assert_eq!(shards.block_ids.len(), 1);
+
+        let waiter = {
+            let chain = chain.clone();
+            tokio::spawn(async move { chain.wait_shards(Some(200)).await.unwrap() })
+        };
+        tokio::task::yield_now().await;
+        chain.set_current_utime(200);
+
+        let shards = waiter.await.unwrap();
+        assert_eq!(shards.current_utime, 200);
+    }
+
+    #[tokio::test]
+    async fn pending_messages_expire() {
+        let messages_queue = PendingMessagesQueue::new(16);
+        let chain = InMemoryTonChain::new(messages_queue.clone(), 100);
+
+        let rx = messages_queue
+            .add_message(UInt256::from([1; 32]), UInt256::from([2; 32]), 110)
+            .unwrap();
+        chain.set_current_utime(111);
+
+        assert_eq!(rx.await.unwrap(), MessageStatus::Expired);
+    }
+
+    #[tokio::test]
+    async fn account_states_have_block_context() {
+        let chain = InMemoryTonChain::new(PendingMessagesQueue::new(16), 100);
+
+        let address =
+            ton_block::MsgAddressInt::with_standart(None, 0, UInt256::from([1; 32]).into())
+                .unwrap();
+        let account = only_account_hash(&address);
+
+        let mut states = chain.subscribe_account_state(account);
+        for _ in 0..3 {
+            chain
+                .set_account(&ton_block::Account::with_address(address.clone()))
+                .unwrap();
+        }
+
+        // Only the latest state is delivered
+        let update = states.recv().await.unwrap();
+        assert_eq!(update.block_id.seq_no, 3);
+        assert!(update.shard_account.is_some());
+    }
+
+    #[tokio::test]
+    async fn waiting_for_active_state_starts_from_known_states() {
+        use futures::FutureExt;
+
+        let chain = InMemoryTonChain::new(PendingMessagesQueue::new(16), 100);
+        let account = UInt256::from([1; 32]);
+
+        // State which was sent before the subscription is used
+        drop(chain.subscribe_account_state(account));
+        deploy_contract(&chain, &[0xf8, 0x00]);
+
+        let mut states = chain.subscribe_account_state(account);
+        let (update, _) = states.wait_active_after(0).now_or_never().unwrap().unwrap();
+        assert_eq!(update.block_id.seq_no, 1);
+
+        // The oldest state from the history is used
+        let mut states = chain.subscribe_account_state_with_history(account, 4);
+        for _ in 0..2 {
+            deploy_contract(&chain, &[0xf8, 0x00]);
+        }
+        let (update, _) = states.wait_active_after(0).now_or_never().unwrap().unwrap();
+        assert_eq!(update.block_id.seq_no, 2);
+    }
+
+    #[tokio::test]
+    async fn account_states_are_kept_in_history() {
+        let chain = InMemoryTonChain::new(PendingMessagesQueue::new(16), 100);
+
+        let address =
+            ton_block::MsgAddressInt::with_standart(None, 0, UInt256::from([1; 32]).into())
+                .unwrap();
+        let account = only_account_hash(&address);
+
+        let states = chain.subscribe_account_state_with_history(account, 2);
+        for _ in 0..3 {
+            chain
+                .set_account(&ton_block::Account::with_address(address.clone()))
+                .unwrap();
+        }
+
+        let history = states.history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].block_id.seq_no, 2);
+        assert_eq!(history[1].block_id.seq_no, 3);
+    }
+}
@@ -0,0 +1,258 @@
+use std::sync::{Arc, Weak};
+
+use anyhow::{Context, Result};
+use tiny_adnl::utils::*;
+use ton_types::UInt256;
+
+#[cfg(test)]
+pub use self::in_memory::*;
+pub use self::shard_accounts_cache::*;
+use super::ton_backend::*;
+use super::ton_subscriber::*;
+use crate::utils::*;
+
+#[cfg(test)]
+mod in_memory;
+mod shard_accounts_cache;
+
+/// TON blockchain view used by the bridge and staking
+#[async_trait::async_trait]
+pub trait TonChain: Send + Sync {
+    /// Starts blocks processing. Returns when states are fresh
+    async fn start(&self) -> Result<()>;
+
+    fn shutdown(&self);
+
+    fn metrics(&self) -> TonChainMetrics;
+
+    /// Current masterchain block time
+    fn current_utime(&self) -> u32;
+
+    /// Seqno of the last processed masterchain block
+    fn last_processed_mc_seqno(&self) -> u32;
+
+    /// Waits for the first masterchain block processed after start
+    async fn first_live_mc_seqno(&self) -> u32;
+
+    /// Passes transactions from the stored masterchain blocks in the specified
+    /// range (inclusive) to subscriptions. Fails if any block is not available
+    async fn replay_blocks(&self, from_mc_seqno: u32, to_mc_seqno: u32) -> Result<()>;
+
+    /// Passes deferred live transactions to subscriptions and stops deferring them
+    async fn resume_live_dispatch(&self);
+
+    /// Returns the account state as of the next block
+    async fn get_contract_state(&self, account: UInt256) -> Result<Option<ExistingContract>>;
+
+    /// Waits until the account is deployed
+    async fn wait_contract_state(&self, account: UInt256) -> Result<ExistingContract>;
+
+    /// Last transaction lt of the account as of the latest processed block.
+    /// `None` if the account state was not processed yet
+    fn last_known_transaction_lt(&self, account: &UInt256) -> Option<u64>;
+
+    /// Account state as of the latest processed block. Doesn't wait for the next block.
+    /// `None` if the account state was not processed yet or the account doesn't exist
+    fn last_known_contract_state(&self, account: &UInt256) -> Result<Option<ExistingContract>>;
+
+    /// Subscribes to the account states with block context
+    fn subscribe_account_state(&self, account: UInt256) -> AccountStateSubscription {
+        self.subscribe_account_state_with_history(account, 0)
+    }
+
+    /// Subscribes to the account states with block context and keeps
+    /// up to `depth` recent states in the subscription history
+    fn subscribe_account_state_with_history(
+        &self,
+        account: UInt256,
+        depth: usize,
+    ) -> AccountStateSubscription;
+
+    fn subscribe_transactions(
+        &self,
+        accounts: &[UInt256],
+        subscription: Weak<dyn TransactionsSubscription>,
+    );
+
+    /// Waits for the masterchain block (optionally not older than `since`)
+    async fn wait_shards(&self, since: Option<u32>) -> Result<LatestShardBlocks>;
+
+    async fn wait_shard_accounts(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<ton_block::ShardAccounts>;
+
+    /// Loads shard accounts for the last applied masterchain block
+    async fn load_last_applied_shard_accounts(&self) -> Result<ShardAccountsMap>;
+
+    async fn broadcast_external_message(
+        &self,
+        to: &ton_block::AccountIdPrefixFull,
+        data: &[u8],
+    ) -> Result<()>;
+
+    /// Waits for the next masterchain block and loads all its shard states
+    async fn load_shard_accounts_snapshot(&self) -> Result<ShardAccountsSnapshot> {
+        let shards = self.wait_shards(None).await?;
+
+        let mut shard_accounts =
+            FxHashMap::with_capacity_and_hasher(shards.block_ids.len(), Default::default());
+        for (shard_ident, block_id) in shards.block_ids {
+            let accounts = self.wait_shard_accounts(&block_id).await?;
+            shard_accounts.insert(shard_ident, accounts);
+        }
+
+        Ok(ShardAccountsSnapshot {
+            mc_seqno: shards.mc_seqno,
+            gen_utime: shards.current_utime,
+            shard_accounts,
+        })
+    }
+}
+
+impl dyn TonChain {
+    pub fn add_transactions_subscription<I, T>(&self, accounts: I, subscription: &Arc<T>)
+    where
+        I: IntoIterator<Item = UInt256>,
+        T: TransactionsSubscription + 'static,
+    {
+        let accounts = accounts.into_iter().collect::<Vec<_>>();
+        let weak = Arc::downgrade(subscription) as Weak<dyn TransactionsSubscription>;
+        self.subscribe_transactions(&accounts, weak);
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TonChainMetrics {
+    pub subscriber: TonSubscriberMetrics,
+    pub backend: TonBackendMetrics,
+}
+
+/// Blocks from the backend processed by the subscriber
+pub struct LiveTonChain {
+    ton_subscriber: Arc<TonSubscriber>,
+    ton_backend: Arc<dyn TonBackend>,
+}
+
+impl LiveTonChain {
+    pub fn new(ton_subscriber: Arc<TonSubscriber>, ton_backend: Arc<dyn TonBackend>) -> Arc<Self> {
+        Arc::new(Self {
+            ton_subscriber,
+            ton_backend,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl TonChain for LiveTonChain {
+    async fn start(&self) -> Result<()> {
+        self.ton_backend.clone().start().await
+    }
+
+    fn shutdown(&self) {
+        self.ton_backend.shutdown();
+    }
+
+    fn metrics(&self) -> TonChainMetrics {
+        TonChainMetrics {
+            subscriber: self.ton_subscriber.metrics(),
+            backend: self.ton_backend.metrics(),
+        }
+    }
+
+    fn current_utime(&self) -> u32 {
+        self.ton_subscriber.current_utime()
+    }
+
+    fn last_processed_mc_seqno(&self) -> u32 {
+        self.ton_subscriber.last_processed_mc_seqno()
+    }
+
+    async fn first_live_mc_seqno(&self) -> u32 {
+        self.ton_subscriber.wait_first_live_mc_seqno().await
+    }
+
+    async fn replay_blocks(&self, from_mc_seqno: u32, to_mc_seqno: u32) -> Result<()> {
+        for mc_seqno in from_mc_seqno..=to_mc_seqno {
+            let block = self
+                .ton_backend
+                .load_stored_block(mc_seqno)
+                .await
+                .with_context(|| format!("Failed to load masterchain block {}", mc_seqno))?
+                .ok_or(TonChainError::BlockNotAvailable(mc_seqno))?;
+
+            for shard_block in &block.shard_blocks {
+                self.ton_subscriber
+                    .replay_shard_block(&shard_block.block, &shard_block.accounts)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn resume_live_dispatch(&self) {
+        self.ton_subscriber.resume_live_dispatch().await;
+    }
+
+    async fn get_contract_state(&self, account: UInt256) -> Result<Option<ExistingContract>> {
+        self.ton_subscriber.get_contract_state(account).await
+    }
+
+    async fn wait_contract_state(&self, account: UInt256) -> Result<ExistingContract> {
+        self.ton_subscriber.wait_contract_state(account).await
+    }
+
+    fn last_known_transaction_lt(&self, account: &UInt256) -> Option<u64> {
+        self.ton_subscriber.last_known_transaction_lt(account)
+    }
+
+    fn last_known_contract_state(&self, account: &UInt256) -> Result<Option<ExistingContract>> {
+        self.ton_subscriber.last_known_contract_state(account)
+    }
+
+    fn subscribe_account_state_with_history(
+        &self,
+        account: UInt256,
+        depth: usize,
+    ) -> AccountStateSubscription {
+        self.ton_subscriber
+            .subscribe_account_state_with_history(account, depth)
+    }
+
+    fn subscribe_transactions(
+        &self,
+        accounts: &[UInt256],
+        subscription: Weak<dyn TransactionsSubscription>,
+    ) {
+        self.ton_subscriber
+            .add_transactions_subscription(accounts, subscription);
+    }
+
+    async fn wait_shards(&self, since: Option<u32>) -> Result<LatestShardBlocks> {
+        self.ton_subscriber.wait_shards(since).await
+    }
+
+    async fn wait_shard_accounts(
+        &self,
+        block_id: &ton_block::BlockIdExt,
+    ) -> Result<ton_block::ShardAccounts> {
+        self.ton_backend.wait_shard_accounts(block_id).await
+    }
+
+    async fn load_last_applied_shard_accounts(&self) -> Result<ShardAccountsMap> {
+        self.ton_backend.load_last_applied_shard_accounts().await
+    }
+
+    async fn broadcast_external_message(
+        &self,
+        to: &ton_block::AccountIdPrefixFull,
+        data: &[u8],
+    ) -> Result<()> {
+        self.ton_backend.broadcast_external_message(to, data).await
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+enum TonChainError {
+    #[error("Masterchain block {0} is not available for replay")]
+    BlockNotAvailable(u32),
+}
@@ -0,0 +1,162 @@
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use super::TonChain;
+use crate::utils::*;
+
+/// All shard states at some masterchain block
+#[derive(Clone)]
+pub struct ShardAccountsSnapshot {
+    pub mc_seqno: u32,
+    pub gen_utime: u32,
+    pub shard_accounts: ShardAccountsMap,
+}
+
+/// Shared shard states snapshot, reused by concurrent callers while it is fresh
+pub struct ShardAccountsCache {
+    max_age_sec: u32,
+    clock: Arc<dyn Clock>,
+    snapshot: tokio::sync::Mutex<Weak<ShardAccountsSnapshot>>,
+}
+
+impl ShardAccountsCache {
+    pub fn new(max_age_sec: u32, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            max_age_sec,
+            clock,
+            snapshot: Default::default(),
+        }
+    }
+
+    /// Returns the cached snapshot if it is not older than `max_age_sec`
+    /// (and newer than `newer_than` block if specified), otherwise loads a new one
+    pub async fn get(
+        &self,
+        ton_chain: &dyn TonChain,
+        newer_than: Option<u32>,
+    ) -> Result<Arc<ShardAccountsSnapshot>> {
+        // NOTE: lock is held while loading so that concurrent callers wait
+        // for the same snapshot instead of loading their own
+        let mut cached = self.snapshot.lock().await;
+
+        if let Some(snapshot) = cached.upgrade() {
+            let is_newer = !matches!(newer_than, Some(seqno) if snapshot.mc_seqno <= seqno);
+            let age = ton_chain.current_utime().saturating_sub(snapshot.gen_utime);
+            if is_newer && age <= self.max_age_sec {
+                return Ok(snapshot);
+            }
+        }
+
+        let snapshot = Arc::new(ton_chain.load_shard_accounts_snapshot().await?);
+        log::debug!(
+            "Loaded shard accounts snapshot for masterchain block {}",
+            snapshot.mc_seqno
+        );
+
+        // NOTE: cache only holds a weak reference, so the snapshot is dropped
+        // after `max_age_sec` unless someone still uses it
+        let clock = self.clock.clone();
+        let keep_alive = snapshot.clone();
+        let max_age = Duration::from_secs(self.max_age_sec as u64);
+        tokio::spawn(async move {
+            clock.sleep(max_age).await;
+            drop(keep_alive);
+        });
+
+        *cached = Arc::downgrade(&snapshot);
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ton_chain::InMemoryTonChain;
+
+    const START_TIME: u32 = 100;
+    const MAX_AGE_SEC: u32 = 10;
+
+    fn make_cache() -> (Arc<InMemoryTonChain>, Arc<VirtualClock>, ShardAccountsCache) {
+        let chain = InMemoryTonChain::new(PendingMessagesQueue::new(16), START_TIME);
+        let clock = Arc::new(VirtualClock::new(START_TIME));
+        let cache = ShardAccountsCache::new(MAX_AGE_SEC, clock.clone());
+        (chain, clock, cache)
+    }
+
+    #[tokio::test]
+    async fn fresh_snapshot_is_reused() {
+        let (chain, _clock, cache) = make_cache();
+
+        let first = cache.get(chain.as_ref(), None).await.unwrap();
+        chain.set_current_utime(START_TIME + MAX_AGE_SEC);
+        let second = cache.get(chain.as_ref(), None).await.unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[tokio::test]
+    async fn old_snapshot_is_reloaded() {
+        let (chain, _clock, cache) = make_cache();
+
+        let first = cache.get(chain.as_ref(), None).await.unwrap();
+        chain.set_current_utime(START_TIME + MAX_AGE_SEC + 1);
+        let second = cache.get(chain.as_ref(), None).await.unwrap();
+
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert!(second.mc_seqno > first.mc_seqno);
+    }
+
+    #[tokio::test]
+    async fn snapshot_is_reloaded_when_not_newer_than_requested() {
+        let (chain, _clock, cache) = make_cache();
+
+        let first = cache.get(chain.as_ref(), None).await.unwrap();
+        chain.next_block();
+        let second = cache
+            .get(chain.as_ref(), Some(first.mc_seqno))
+            .await
+            .unwrap();
+
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert!(second.mc_seqno > first.mc_seqno);
+
+        let third = cache
+            .get(chain.as_ref(), Some(first.mc_seqno))
+            .await
+            .unwrap();
+        assert!(Arc::ptr_eq(&second, &third));
+    }
+
+    #[tokio::test]
+    async fn concurrent_callers_share_single_load() {
+        let (chain, _clock, cache) = make_cache();
+
+        let (first, second) = futures::future::join(
+            cache.get(chain.as_ref(), None),
+            cache.get(chain.as_ref(), None),
+        )
+        .await;
+
+        assert!(Arc::ptr_eq(&first.unwrap(), &second.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn snapshot_is_dropped_after_max_age() {
+        let (chain, clock, cache) = make_cache();
+
+        let snapshot = Arc::downgrade(&cache.get(chain.as_ref(), None).await.unwrap());
+        tokio::task::yield_now().await;
+        assert!(snapshot.upgrade().is_some());
+
+        clock.advance(Duration::from_secs(MAX_AGE_SEC as u64));
+        for _ in 0..10 {
+            if snapshot.upgrade().is_none() {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert!(snapshot.upgrade().is_none());
+    }
+}
@@ -22,6 +22,16 @@ pub fn round_number() -> &'static ton_abi::Function {
     })
 }
 
+/// External function
+pub fn required_votes() -> &'static ton_abi::Function {
+    crate::once!(ton_abi::Function, || {
+        FunctionBuilder::new("requiredVotes")
+            .default_headers()
+            .output("requiredVotes", u32::param_type())
+            .build()
+    })
+}
+
 /// External responsible function
 pub fn get_voters() -> &'static ton_abi::Function {
     crate::once!(ton_abi::Function, || {
@@ -17,6 +17,7 @@ pub mod staking_contract;
 pub mod ton_event_configuration_contract;
 pub mod ton_event_contract;
 pub mod user_data_contract;
+pub mod wallet_contract;
 
 mod models;
 
@@ -35,6 +36,12 @@ impl EventBaseContract<'_> {
         Ok(result)
     }
 
+    pub fn required_votes(&self) -> Result<u32> {
+        let function = base_event_contract::required_votes();
+        let result = self.0.run_local(function, &[])?.unpack_first()?;
+        Ok(result)
+    }
+
     pub fn get_voters(&self, vote: EventVote) -> Result<Vec<UInt256>> {
         let function = base_event_contract::get_voters();
         let inputs = [answer_id(), vote.token_value().named("vote")];
@@ -232,6 +239,25 @@ impl RelayRoundContract<'_> {
         let end_time = self.0.run_local(function, &[])?.unpack_first()?;
         Ok(end_time)
     }
+
+    /// Computes the relay share of the round reward
+    pub fn relay_reward(&self, staked_tokens: u128) -> Result<u128> {
+        let round_reward: u128 = self
+            .0
+            .run_local(relay_round_contract::round_reward(), &[])?
+            .unpack_first()?;
+        let total_tokens_staked: u128 = self
+            .0
+            .run_local(relay_round_contract::total_tokens_staked(), &[])?
+            .unpack_first()?;
+
+        if total_tokens_staked == 0 {
+            return Ok(0);
+        }
+
+        let reward = num_bigint::BigUint::from(staked_tokens) * round_reward / total_tokens_staked;
+        Ok(num_traits::ToPrimitive::to_u128(&reward).unwrap_or(u128::MAX))
+    }
 }
 
 pub struct UserDataContract<'a>(pub &'a ExistingContract);
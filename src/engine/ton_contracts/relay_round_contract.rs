@@ -32,3 +32,23 @@ pub fn end_time() -> &'static ton_abi::Function {
             .build()
     })
 }
+
+/// External function
+pub fn round_reward() -> &'static ton_abi::Function {
+    crate::once!(ton_abi::Function, || {
+        FunctionBuilder::new("round_reward")
+            .time_header()
+            .output("round_reward", u128::param_type())
+            .build()
+    })
+}
+
+/// External function
+pub fn total_tokens_staked() -> &'static ton_abi::Function {
+    crate::once!(ton_abi::Function, || {
+        FunctionBuilder::new("total_tokens_staked")
+            .time_header()
+            .output("total_tokens_staked", u128::param_type())
+            .build()
+    })
+}
@@ -0,0 +1,15 @@
+use nekoton_abi::*;
+
+/// EverWallet and SafeMultisig transfer method
+pub fn send_transaction() -> &'static ton_abi::Function {
+    crate::once!(ton_abi::Function, || {
+        FunctionBuilder::new("sendTransaction")
+            .default_headers()
+            .input("dest", ton_block::MsgAddressInt::param_type())
+            .input("value", u128::param_type())
+            .input("bounce", bool::param_type())
+            .input("flags", u8::param_type())
+            .input("payload", ton_types::Cell::param_type())
+            .build()
+    })
+}
@@ -0,0 +1,334 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use tokio::sync::watch;
+
+use super::TonSubscriberError;
+use crate::utils::*;
+
+/// Account state as of the end of the shard block
+#[derive(Debug, Clone)]
+pub struct AccountStateUpdate {
+    pub block_id: ton_block::BlockIdExt,
+    /// Block end logical time. State includes all account transactions with lower lt
+    pub lt: u64,
+    /// `None` if the account doesn't exist
+    pub shard_account: Option<ton_block::ShardAccount>,
+}
+
+impl AccountStateUpdate {
+    pub fn contract(&self) -> Result<Option<ExistingContract>> {
+        ExistingContract::from_shard_account_opt(&self.shard_account)
+    }
+
+    /// Whether the transaction with the specified lt is already applied to this state
+    pub fn includes(&self, transaction_lt: u64) -> bool {
+        transaction_lt < self.lt
+    }
+}
+
+/// Max number of recent states which can be kept for a single account
+pub const MAX_ACCOUNT_STATE_HISTORY_DEPTH: usize = 16;
+
+/// Account state updates sender with an optional bounded history
+pub struct AccountStateChannel {
+    tx: AccountStateTx,
+    rx: AccountStateRx,
+    history: Arc<Mutex<AccountStateHistory>>,
+}
+
+impl AccountStateChannel {
+    pub fn new() -> Self {
+        let (tx, rx) = watch::channel(None);
+        Self {
+            tx,
+            rx,
+            history: Default::default(),
+        }
+    }
+
+    /// Creates a subscription which receives all further updates
+    pub fn subscribe(&self) -> AccountStateSubscription {
+        self.subscribe_with_history(0)
+    }
+
+    /// Creates a subscription which receives all further updates and
+    /// keeps up to `depth` recent states (see [`MAX_ACCOUNT_STATE_HISTORY_DEPTH`]).
+    ///
+    /// NOTE: history is shared between subscriptions of the same account
+    /// and is only recorded while some of them requested it
+    pub fn subscribe_with_history(&self, depth: usize) -> AccountStateSubscription {
+        let depth = std::cmp::min(depth, MAX_ACCOUNT_STATE_HISTORY_DEPTH);
+        if depth > 0 {
+            self.history.lock().depths.push(depth);
+        }
+
+        AccountStateSubscription {
+            rx: self.tx.subscribe(),
+            depth,
+            history: self.history.clone(),
+        }
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        // NOTE: one receiver is always stored in the channel itself
+        self.tx.receiver_count() > 1
+    }
+
+    pub fn send(&self, update: AccountStateUpdate) -> Result<()> {
+        self.history.lock().push(&update);
+        self.tx
+            .send(Some(update))
+            .map_err(|_| TonSubscriberError::AccountStateChannelClosed.into())
+    }
+
+    /// Marks the current value as seen for receivers created by [`receiver`]
+    ///
+    /// [`receiver`]: AccountStateChannel::receiver
+    pub fn mark_seen(&mut self) {
+        self.rx.borrow_and_update();
+    }
+
+    /// Last transaction lt from the latest update
+    pub fn last_transaction_lt(&self) -> Option<u64> {
+        self.rx
+            .borrow()
+            .as_ref()?
+            .shard_account
+            .as_ref()
+            .map(|shard_account| shard_account.last_trans_lt())
+    }
+
+    /// The latest update without waiting for a new one
+    pub fn last_update(&self) -> Option<AccountStateUpdate> {
+        self.rx.borrow().clone()
+    }
+
+    pub fn receiver(&self) -> AccountStateRx {
+        self.rx.clone()
+    }
+}
+
+impl Default for AccountStateChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Account state updates with block context
+pub struct AccountStateSubscription {
+    rx: AccountStateRx,
+    depth: usize,
+    history: Arc<Mutex<AccountStateHistory>>,
+}
+
+impl Drop for AccountStateSubscription {
+    fn drop(&mut self) {
+        if self.depth > 0 {
+            self.history.lock().remove_depth(self.depth);
+        }
+    }
+}
+
+impl AccountStateSubscription {
+    /// Waits for the next state update
+    pub async fn recv(&mut self) -> Result<AccountStateUpdate> {
+        loop {
+            self.rx.changed().await?;
+            if let Some(update) = self.rx.borrow_and_update().clone() {
+                return Ok(update);
+            }
+        }
+    }
+
+    /// Waits for the oldest active state which includes the transaction with the specified lt.
+    ///
+    /// NOTE: the current state is checked first because it could be sent before the
+    /// subscription was created. States which were replaced before this task was polled
+    /// are taken from the history
+    pub async fn wait_active_after(
+        &mut self,
+        transaction_lt: u64,
+    ) -> Result<(AccountStateUpdate, ExistingContract)> {
+        let mut latest = self.rx.borrow_and_update().clone();
+        loop {
+            if let Some(latest) = latest.take() {
+                for update in self.history().into_iter().chain(std::iter::once(latest)) {
+                    if !update.includes(transaction_lt) {
+                        continue;
+                    }
+                    if let Some(contract) = active_contract(&update)? {
+                        return Ok((update, contract));
+                    }
+                }
+            }
+
+            self.rx.changed().await?;
+            latest = self.rx.borrow_and_update().clone();
+        }
+    }
+
+    /// Up to `depth` recent states received after the subscription, oldest first
+    pub fn history(&self) -> Vec<AccountStateUpdate> {
+        let history = self.history.lock();
+        let skip = history.items.len().saturating_sub(self.depth);
+        history.items.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// Contract from the update if the account is deployed
+fn active_contract(update: &AccountStateUpdate) -> Result<Option<ExistingContract>> {
+    let contract = match update.contract()? {
+        Some(contract) => contract,
+        None => return Ok(None),
+    };
+
+    match &contract.account.storage.state {
+        ton_block::AccountState::AccountActive { .. } => Ok(Some(contract)),
+        ton_block::AccountState::AccountFrozen { .. } => {
+            Err(TonSubscriberError::AccountIsFrozen.into())
+        }
+        ton_block::AccountState::AccountUninit => Ok(None),
+    }
+}
+
+#[derive(Default)]
+struct AccountStateHistory {
+    /// History depths requested by alive subscriptions
+    depths: Vec<usize>,
+    items: VecDeque<AccountStateUpdate>,
+}
+
+impl AccountStateHistory {
+    fn capacity(&self) -> usize {
+        self.depths.iter().copied().max().unwrap_or_default()
+    }
+
+    fn push(&mut self, update: &AccountStateUpdate) {
+        let capacity = self.capacity();
+        if capacity == 0 {
+            return;
+        }
+
+        while self.items.len() >= capacity {
+            self.items.pop_front();
+        }
+        self.items.push_back(update.clone());
+    }
+
+    fn remove_depth(&mut self, depth: usize) {
+        if let Some(index) = self.depths.iter().position(|item| *item == depth) {
+            self.depths.swap_remove(index);
+        }
+
+        let capacity = self.capacity();
+        while self.items.len() > capacity {
+            self.items.pop_front();
+        }
+    }
+}
+
+type AccountStateTx = watch::Sender<Option<AccountStateUpdate>>;
+type AccountStateRx = watch::Receiver<Option<AccountStateUpdate>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_update(seq_no: u32, lt: u64) -> AccountStateUpdate {
+        AccountStateUpdate {
+            block_id: ton_block::BlockIdExt {
+                seq_no,
+                ..Default::default()
+            },
+            lt,
+            shard_account: None,
+        }
+    }
+
+    #[test]
+    fn update_includes_only_older_transactions() {
+        let update = make_update(1, 100);
+        assert!(update.includes(99));
+        assert!(!update.includes(100));
+        assert!(!update.includes(101));
+    }
+
+    #[tokio::test]
+    async fn subscription_receives_latest_update() {
+        let channel = AccountStateChannel::new();
+        assert!(!channel.has_subscribers());
+
+        let mut subscription = channel.subscribe();
+        assert!(channel.has_subscribers());
+
+        channel.send(make_update(1, 100)).unwrap();
+        channel.send(make_update(2, 200)).unwrap();
+
+        let update = subscription.recv().await.unwrap();
+        assert_eq!(update.block_id.seq_no, 2);
+        assert_eq!(update.lt, 200);
+
+        drop(subscription);
+        assert!(!channel.has_subscribers());
+    }
+
+    #[tokio::test]
+    async fn history_is_bounded_and_opt_in() {
+        let channel = AccountStateChannel::new();
+
+        // History is not recorded without subscriptions which requested it
+        let plain = channel.subscribe();
+        channel.send(make_update(1, 100)).unwrap();
+        assert!(plain.history().is_empty());
+
+        let short = channel.subscribe_with_history(1);
+        let long = channel.subscribe_with_history(2);
+        for i in 2..=4 {
+            channel.send(make_update(i, i as u64 * 100)).unwrap();
+        }
+
+        let history = long.history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].block_id.seq_no, 3);
+        assert_eq!(history[1].block_id.seq_no, 4);
+
+        let history = short.history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].block_id.seq_no, 4);
+
+        // History is shrunk when the deepest subscription is dropped
+        drop(long);
+        assert_eq!(short.history().len(), 1);
+        drop(short);
+        channel.send(make_update(5, 500)).unwrap();
+        assert!(channel.history.lock().items.is_empty());
+
+        // Depth is limited
+        let deep = channel.subscribe_with_history(usize::MAX);
+        for i in 0..2 * MAX_ACCOUNT_STATE_HISTORY_DEPTH as u32 {
+            channel.send(make_update(i, i as u64)).unwrap();
+        }
+        assert_eq!(deep.history().len(), MAX_ACCOUNT_STATE_HISTORY_DEPTH);
+    }
+
+    #[tokio::test]
+    async fn mark_seen_skips_current_value() {
+        use futures::FutureExt;
+
+        let mut channel = AccountStateChannel::new();
+        let mut rx_before = channel.receiver();
+
+        channel.send(make_update(1, 100)).unwrap();
+        channel.mark_seen();
+
+        // Receivers cloned after `mark_seen` start from the seen version
+        let mut rx_after = channel.receiver();
+        assert!(rx_after.changed().now_or_never().is_none());
+
+        assert!(rx_before.changed().now_or_never().unwrap().is_ok());
+        assert_eq!(rx_before.borrow().as_ref().unwrap().lt, 100);
+    }
+}
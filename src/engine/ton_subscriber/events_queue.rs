@@ -0,0 +1,412 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+use ton_types::UInt256;
+
+use crate::config::*;
+
+/// Bounded account events queues factory with per-listener statistics
+pub struct AccountEventsQueues {
+    config: EventsQueueConfig,
+    stats: Mutex<Vec<(&'static str, Arc<EventsQueueStats>)>>,
+}
+
+impl AccountEventsQueues {
+    pub fn new(config: EventsQueueConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config,
+            stats: Default::default(),
+        })
+    }
+
+    /// Creates new bounded channel for the listener.
+    ///
+    /// NOTE: channels with the same name share statistics
+    pub fn channel<T>(&self, name: &'static str) -> (AccountEventsTx<T>, AccountEventsRx<T>) {
+        let stats = {
+            let mut stats = self.stats.lock();
+            match stats.iter().find(|(item, _)| *item == name) {
+                Some((_, stats)) => stats.clone(),
+                None => {
+                    let item = Arc::new(EventsQueueStats::default());
+                    stats.push((name, item.clone()));
+                    item
+                }
+            }
+        };
+        stats
+            .capacity
+            .fetch_add(self.config.capacity, Ordering::Release);
+
+        let (tx, rx) = mpsc::channel(self.config.capacity);
+
+        let tx = AccountEventsTx {
+            name,
+            tx,
+            full_policy: self.config.full_policy,
+            max_overflow: self.config.max_overflow,
+            overflow: Default::default(),
+            stats: stats.clone(),
+        };
+        let rx = AccountEventsRx {
+            rx,
+            capacity: self.config.capacity,
+            stats,
+        };
+
+        (tx, rx)
+    }
+
+    pub fn metrics(&self) -> Vec<(&'static str, EventsQueueMetrics)> {
+        self.stats
+            .lock()
+            .iter()
+            .map(|(name, stats)| (*name, stats.metrics()))
+            .collect()
+    }
+}
+
+pub struct AccountEventsTx<T> {
+    name: &'static str,
+    tx: mpsc::Sender<QueuedEvent<T>>,
+    full_policy: EventsQueueFullPolicy,
+    max_overflow: Option<usize>,
+    /// Events which are waiting for a free space with the `Buffer` policy
+    overflow: Arc<Mutex<EventsOverflow<T>>>,
+    stats: Arc<EventsQueueStats>,
+}
+
+impl<T> Clone for AccountEventsTx<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            tx: self.tx.clone(),
+            full_policy: self.full_policy,
+            max_overflow: self.max_overflow,
+            overflow: self.overflow.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<T: Send + 'static> AccountEventsTx<T> {
+    /// Pushes event to the queue, following the configured policy when it is full.
+    ///
+    /// NOTE: never blocks the calling thread, because it is called from the
+    /// blocks processing which can run on any runtime flavor
+    pub fn send(&self, account: UInt256, event: T) -> Result<(), EventsQueueError> {
+        let item = QueuedEvent {
+            account,
+            event,
+            enqueued_at: Instant::now(),
+        };
+
+        // NOTE: increment depth before sending to prevent underflow in receiver
+        self.stats.depth.fetch_add(1, Ordering::Release);
+
+        let result = match self.full_policy {
+            EventsQueueFullPolicy::Buffer => self.send_ordered(item),
+            EventsQueueFullPolicy::Drop => match self.tx.try_send(item) {
+                Ok(()) => Ok(()),
+                Err(mpsc::error::TrySendError::Full(_)) => Err(self.on_dropped(&account)),
+                Err(mpsc::error::TrySendError::Closed(_)) => Err(EventsQueueError::ChannelClosed),
+            },
+        };
+
+        if result.is_err() {
+            self.stats.depth.fetch_sub(1, Ordering::Release);
+        }
+        result
+    }
+
+    /// Sends event directly if there are no waiting events and there is a free space.
+    /// Otherwise, appends it to the overflow which is forwarded in a separate task.
+    ///
+    /// NOTE: must be called within the tokio runtime context
+    fn send_ordered(&self, item: QueuedEvent<T>) -> Result<(), EventsQueueError> {
+        let mut overflow = self.overflow.lock();
+        if overflow.forwarding {
+            if matches!(self.max_overflow, Some(max) if overflow.items.len() >= max) {
+                return Err(self.on_dropped(&item.account));
+            }
+            overflow.items.push_back(item);
+            return Ok(());
+        }
+
+        match self.tx.try_send(item) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(item)) => {
+                if self.max_overflow == Some(0) {
+                    return Err(self.on_dropped(&item.account));
+                }
+
+                self.stats.overflowed.fetch_add(1, Ordering::Release);
+                log::warn!("{}: Events queue is full, buffering", self.name);
+
+                overflow.items.push_back(item);
+                overflow.forwarding = true;
+                tokio::spawn(forward_overflow(
+                    self.tx.clone(),
+                    self.overflow.clone(),
+                    self.stats.clone(),
+                ));
+                Ok(())
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(EventsQueueError::ChannelClosed),
+        }
+    }
+
+    fn on_dropped(&self, account: &UInt256) -> EventsQueueError {
+        self.stats.dropped.fetch_add(1, Ordering::Release);
+        log::error!(
+            "{}: Events queue is full, dropping event for account {:x}",
+            self.name,
+            account
+        );
+        EventsQueueError::QueueIsFull
+    }
+}
+
+/// Waits for a free space for each overflowed event in order
+async fn forward_overflow<T>(
+    tx: mpsc::Sender<QueuedEvent<T>>,
+    overflow: Arc<Mutex<EventsOverflow<T>>>,
+    stats: Arc<EventsQueueStats>,
+) {
+    loop {
+        let item = {
+            let mut overflow = overflow.lock();
+            match overflow.items.pop_front() {
+                Some(item) => item,
+                None => {
+                    // NOTE: new events will be sent directly after this point
+                    overflow.forwarding = false;
+                    return;
+                }
+            }
+        };
+
+        if tx.send(item).await.is_err() {
+            let mut overflow = overflow.lock();
+            let dropped = overflow.items.len() + 1;
+            overflow.items.clear();
+            overflow.forwarding = false;
+            stats.depth.fetch_sub(dropped, Ordering::Release);
+            return;
+        }
+    }
+}
+
+struct EventsOverflow<T> {
+    items: VecDeque<QueuedEvent<T>>,
+    /// Whether the forwarding task is running
+    forwarding: bool,
+}
+
+impl<T> Default for EventsOverflow<T> {
+    fn default() -> Self {
+        Self {
+            items: Default::default(),
+            forwarding: false,
+        }
+    }
+}
+
+pub struct AccountEventsRx<T> {
+    rx: mpsc::Receiver<QueuedEvent<T>>,
+    capacity: usize,
+    stats: Arc<EventsQueueStats>,
+}
+
+impl<T> AccountEventsRx<T> {
+    pub async fn recv(&mut self) -> Option<(UInt256, T)> {
+        let item = self.rx.recv().await?;
+
+        self.stats.depth.fetch_sub(1, Ordering::Release);
+        self.stats.wait_time_us.fetch_add(
+            item.enqueued_at.elapsed().as_micros() as u64,
+            Ordering::Release,
+        );
+
+        Some((item.account, item.event))
+    }
+
+    pub fn close(&mut self) {
+        self.rx.close();
+    }
+
+    pub(super) fn stats(&self) -> &EventsQueueStats {
+        &self.stats
+    }
+}
+
+impl<T> Drop for AccountEventsRx<T> {
+    fn drop(&mut self) {
+        self.stats
+            .capacity
+            .fetch_sub(self.capacity, Ordering::Release);
+    }
+}
+
+struct QueuedEvent<T> {
+    account: UInt256,
+    event: T,
+    enqueued_at: Instant,
+}
+
+#[derive(Default)]
+pub(super) struct EventsQueueStats {
+    capacity: AtomicUsize,
+    depth: AtomicUsize,
+    processed: AtomicU64,
+    dropped: AtomicU64,
+    overflowed: AtomicU64,
+    wait_time_us: AtomicU64,
+    processing_time_us: AtomicU64,
+    max_processing_time_us: AtomicU64,
+}
+
+impl EventsQueueStats {
+    pub(super) fn on_processed(&self, started_at: Instant) {
+        let elapsed = started_at.elapsed().as_micros() as u64;
+        self.processed.fetch_add(1, Ordering::Release);
+        self.processing_time_us
+            .fetch_add(elapsed, Ordering::Release);
+        self.max_processing_time_us
+            .fetch_max(elapsed, Ordering::Release);
+    }
+
+    fn metrics(&self) -> EventsQueueMetrics {
+        EventsQueueMetrics {
+            capacity: self.capacity.load(Ordering::Acquire),
+            depth: self.depth.load(Ordering::Acquire),
+            processed: self.processed.load(Ordering::Acquire),
+            dropped: self.dropped.load(Ordering::Acquire),
+            overflowed: self.overflowed.load(Ordering::Acquire),
+            wait_time_us: self.wait_time_us.load(Ordering::Acquire),
+            processing_time_us: self.processing_time_us.load(Ordering::Acquire),
+            max_processing_time_us: self.max_processing_time_us.load(Ordering::Acquire),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct EventsQueueMetrics {
+    /// Total capacity of all queues with this name
+    pub capacity: usize,
+    /// Number of events waiting in queues
+    pub depth: usize,
+    /// Total number of handled events
+    pub processed: u64,
+    /// Total number of events dropped due to the full queue
+    pub dropped: u64,
+    /// Total number of times the queue was full and events were buffered
+    pub overflowed: u64,
+    /// Total time events spent in the queue
+    pub wait_time_us: u64,
+    /// Total time spent in handlers
+    pub processing_time_us: u64,
+    /// The longest handler execution time
+    pub max_processing_time_us: u64,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EventsQueueError {
+    #[error("Events queue is full")]
+    QueueIsFull,
+    #[error("Channel is dropped")]
+    ChannelClosed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_queues(
+        full_policy: EventsQueueFullPolicy,
+        max_overflow: Option<usize>,
+    ) -> Arc<AccountEventsQueues> {
+        AccountEventsQueues::new(EventsQueueConfig {
+            capacity: 1,
+            full_policy,
+            max_overflow,
+        })
+    }
+
+    #[tokio::test]
+    async fn full_queue_buffers_events_on_current_thread_runtime() {
+        let queues = make_queues(EventsQueueFullPolicy::Buffer, None);
+        let (tx, mut rx) = queues.channel::<u32>("test");
+
+        // Unbounded overflow keeps all events
+        let account = UInt256::default();
+        for i in 0..100 {
+            tx.send(account, i).unwrap();
+        }
+
+        let metrics = &queues.metrics()[0].1;
+        assert_eq!(metrics.depth, 100);
+        assert_eq!(metrics.overflowed, 1);
+        assert_eq!(metrics.dropped, 0);
+
+        // Events are received in the same order
+        for i in 0..100 {
+            assert_eq!(rx.recv().await.unwrap().1, i);
+        }
+        assert_eq!(queues.metrics()[0].1.depth, 0);
+
+        // Queue is used directly after the overflow is forwarded
+        tokio::task::yield_now().await;
+        tx.send(account, 100).unwrap();
+        assert_eq!(rx.recv().await.unwrap().1, 100);
+    }
+
+    #[tokio::test]
+    async fn full_queue_drops_events() {
+        let queues = make_queues(EventsQueueFullPolicy::Drop, None);
+        let (tx, mut rx) = queues.channel::<u32>("test");
+
+        let account = UInt256::default();
+        tx.send(account, 0).unwrap();
+        assert!(matches!(
+            tx.send(account, 1),
+            Err(EventsQueueError::QueueIsFull)
+        ));
+
+        let metrics = &queues.metrics()[0].1;
+        assert_eq!(metrics.depth, 1);
+        assert_eq!(metrics.dropped, 1);
+
+        assert_eq!(rx.recv().await.unwrap().1, 0);
+    }
+
+    #[tokio::test]
+    async fn full_bounded_overflow_drops_events() {
+        let queues = make_queues(EventsQueueFullPolicy::Buffer, Some(3));
+        let (tx, mut rx) = queues.channel::<u32>("test");
+
+        // One event in the queue and three in the overflow
+        let account = UInt256::default();
+        for i in 0..4 {
+            tx.send(account, i).unwrap();
+        }
+        assert!(matches!(
+            tx.send(account, 4),
+            Err(EventsQueueError::QueueIsFull)
+        ));
+
+        let metrics = &queues.metrics()[0].1;
+        assert_eq!(metrics.depth, 4);
+        assert_eq!(metrics.overflowed, 1);
+        assert_eq!(metrics.dropped, 1);
+
+        for i in 0..4 {
+            assert_eq!(rx.recv().await.unwrap().1, i);
+        }
+        assert_eq!(queues.metrics()[0].1.depth, 0);
+    }
+}
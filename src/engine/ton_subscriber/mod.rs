@@ -1,4 +1,3 @@
-use std::collections::hash_map;
 use std::ops::Deref;
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::{Arc, Weak};
@@ -6,29 +5,62 @@ use std::sync::{Arc, Weak};
 use anyhow::{Context, Result};
 use parking_lot::Mutex;
 use tiny_adnl::utils::*;
-use tokio::sync::{mpsc, oneshot, watch, Notify};
-use ton_block::{BinTreeType, Deserializable, HashmapAugType};
+use tokio::sync::{mpsc, oneshot, Notify};
+use ton_block::{Deserializable, HashmapAugType};
 use ton_indexer::utils::{BlockIdExtExtension, BlockProofStuff, BlockStuff, ShardStateStuff};
 use ton_indexer::{BriefBlockMeta, EngineStatus};
 use ton_types::{HashmapType, UInt256};
 
+use crate::config::*;
 use crate::utils::*;
 
+pub use self::account_state::*;
+pub use self::events_queue::*;
+pub use self::transaction_handlers::*;
+
+mod account_state;
+mod events_queue;
+mod transaction_handlers;
+
 pub struct TonSubscriber {
     ready: AtomicBool,
     ready_signal: Notify,
     current_utime: AtomicU32,
+    last_processed_mc_seqno: AtomicU32,
     state_subscriptions: Mutex<FxHashMap<UInt256, StateSubscription>>,
     mc_block_awaiters: Mutex<FxHashMap<usize, Box<dyn BlockAwaiter>>>,
     messages_queue: Arc<PendingMessagesQueue>,
+    transaction_handlers: Arc<TransactionHandlers>,
+    /// Background dispatcher queue. Transactions are handled inline if not set
+    transactions_dispatcher: Option<TransactionsDispatcher>,
+    first_live_mc_seqno: AtomicU32,
+    first_live_signal: Notify,
+    /// Live transactions which wait for the missed blocks replay.
+    /// Live dispatch is enabled when it is `None`
+    deferred_transactions: tokio::sync::Mutex<Option<Vec<BlockTransactions>>>,
 }
 
 impl TonSubscriber {
-    pub fn new(messages_queue: Arc<PendingMessagesQueue>) -> Arc<Self> {
+    /// Live transactions are deferred until [`TonSubscriber::resume_live_dispatch`]
+    /// if `defer_live_dispatch` is set, so that transactions from the replayed
+    /// blocks are handled first.
+    ///
+    /// NOTE: messages queue and subscriptions which track messages are still handled live
+    pub fn new(
+        messages_queue: Arc<PendingMessagesQueue>,
+        config: &TransactionHandlersConfig,
+        defer_live_dispatch: bool,
+    ) -> Arc<Self> {
+        let transaction_handlers = TransactionHandlers::new(config);
+        let transactions_dispatcher = config.background_dispatch.then(|| {
+            TransactionsDispatcher::start(config.background_queue_capacity, &transaction_handlers)
+        });
+
         Arc::new(Self {
             ready: AtomicBool::new(false),
             ready_signal: Notify::new(),
             current_utime: AtomicU32::new(0),
+            last_processed_mc_seqno: AtomicU32::new(0),
             state_subscriptions: Mutex::new(FxHashMap::with_capacity_and_hasher(
                 128,
                 Default::default(),
@@ -38,21 +70,35 @@ impl TonSubscriber {
                 Default::default(),
             )),
             messages_queue,
+            transaction_handlers,
+            transactions_dispatcher,
+            first_live_mc_seqno: AtomicU32::new(0),
+            first_live_signal: Notify::new(),
+            deferred_transactions: tokio::sync::Mutex::new(defer_live_dispatch.then(Vec::new)),
         })
     }
 
+    pub fn transaction_handlers(&self) -> &Arc<TransactionHandlers> {
+        &self.transaction_handlers
+    }
+
     pub fn metrics(&self) -> TonSubscriberMetrics {
         TonSubscriberMetrics {
             ready: self.ready.load(Ordering::Acquire),
             current_utime: self.current_utime(),
             pending_message_count: self.messages_queue.len(),
+            dispatch_queue_len: self
+                .transactions_dispatcher
+                .as_ref()
+                .map(TransactionsDispatcher::queue_len)
+                .unwrap_or_default(),
         }
     }
 
-    pub async fn start(self: &Arc<Self>) -> Result<()> {
+    pub async fn start(self: &Arc<Self>, clock: &dyn Clock) -> Result<()> {
         self.wait_sync().await;
 
-        let now = chrono::Utc::now().timestamp() as u32;
+        let now = clock.now_sec();
         log::info!("Waiting masterchain block for {}", now);
         self.wait_shards(Some(now)).await?;
         log::info!("Finished waiting masterchain block for {}", now);
@@ -64,6 +110,40 @@ impl TonSubscriber {
         self.current_utime.load(Ordering::Acquire)
     }
 
+    /// Seqno of the last masterchain block processed after sync
+    pub fn last_processed_mc_seqno(&self) -> u32 {
+        self.last_processed_mc_seqno.load(Ordering::Acquire)
+    }
+
+    /// Waits for the first masterchain block processed after sync
+    pub async fn wait_first_live_mc_seqno(&self) -> u32 {
+        loop {
+            let signal = self.first_live_signal.notified();
+
+            let seqno = self.first_live_mc_seqno.load(Ordering::Acquire);
+            if seqno != 0 {
+                return seqno;
+            }
+
+            signal.await;
+        }
+    }
+
+    /// Dispatches all deferred transactions and enables live dispatch
+    pub async fn resume_live_dispatch(&self) {
+        // NOTE: the lock is held so that newer blocks are not dispatched before the deferred ones
+        let mut deferred_transactions = self.deferred_transactions.lock().await;
+        let deferred = match deferred_transactions.take() {
+            Some(deferred) => deferred,
+            None => return,
+        };
+
+        log::info!("Dispatching {} deferred shard blocks", deferred.len());
+        for block_transactions in deferred {
+            self.dispatch_block(block_transactions).await;
+        }
+    }
+
     pub async fn wait_shards(&self, since: Option<u32>) -> Result<LatestShardBlocks> {
         struct Handler {
             since: Option<u32>,
@@ -87,53 +167,6 @@ impl TonSubscriber {
             }
         }
 
-        fn extract_shards(
-            block: &ton_block::Block,
-            block_info: &ton_block::BlockInfo,
-        ) -> Result<LatestShardBlocks> {
-            let current_utime = block_info.gen_utime().0;
-            let extra = block.extra.read_struct()?;
-            let custom = match extra.read_custom()? {
-                Some(custom) => custom,
-                None => {
-                    return Ok(LatestShardBlocks {
-                        current_utime,
-                        block_ids: Default::default(),
-                    })
-                }
-            };
-
-            let mut block_ids = FxHashMap::with_capacity_and_hasher(16, Default::default());
-
-            custom.shards().iterate_with_keys(
-                |wc_id: i32, ton_block::InRefValue(shards_tree)| {
-                    if wc_id == ton_block::MASTERCHAIN_ID {
-                        return Ok(true);
-                    }
-
-                    shards_tree.iterate(|prefix, descr| {
-                        let shard_id = ton_block::ShardIdent::with_prefix_slice(wc_id, prefix)?;
-
-                        block_ids.insert(
-                            shard_id,
-                            ton_block::BlockIdExt::with_params(
-                                shard_id,
-                                descr.seq_no,
-                                descr.root_hash,
-                                descr.file_hash,
-                            ),
-                        );
-                        Ok(true)
-                    })
-                },
-            )?;
-
-            Ok(LatestShardBlocks {
-                current_utime,
-                block_ids,
-            })
-        }
-
         let (tx, rx) = oneshot::channel();
         self.mc_block_awaiters.lock().insert(
             BLOCK_AWAITER_ID.fetch_add(1, Ordering::Relaxed),
@@ -145,74 +178,88 @@ impl TonSubscriber {
         rx.await?
     }
 
-    pub fn add_transactions_subscription<I, T>(&self, accounts: I, subscription: &Arc<T>)
-    where
-        I: IntoIterator<Item = UInt256>,
-        T: TransactionsSubscription + 'static,
-    {
+    pub fn add_transactions_subscription(
+        &self,
+        accounts: &[UInt256],
+        subscription: Weak<dyn TransactionsSubscription>,
+    ) {
         let mut state_subscriptions = self.state_subscriptions.lock();
 
-        let weak = Arc::downgrade(subscription) as Weak<dyn TransactionsSubscription>;
-
         for account in accounts {
-            match state_subscriptions.entry(account) {
-                hash_map::Entry::Vacant(entry) => {
-                    let (state_tx, state_rx) = watch::channel(None);
-                    entry.insert(StateSubscription {
-                        state_tx,
-                        state_rx,
-                        transaction_subscriptions: vec![weak.clone()],
-                    });
-                }
-                hash_map::Entry::Occupied(mut entry) => {
-                    entry.get_mut().transaction_subscriptions.push(weak.clone());
-                }
-            };
+            state_subscriptions
+                .entry(*account)
+                .or_default()
+                .transaction_subscriptions
+                .push(subscription.clone());
         }
     }
 
     pub async fn get_contract_state(&self, account: UInt256) -> Result<Option<ExistingContract>> {
-        let mut state_rx = match self.state_subscriptions.lock().entry(account) {
-            hash_map::Entry::Vacant(entry) => {
-                let (state_tx, state_rx) = watch::channel(None);
-                entry
-                    .insert(StateSubscription {
-                        state_tx,
-                        state_rx,
-                        transaction_subscriptions: Vec::new(),
-                    })
-                    .state_rx
-                    .clone()
-            }
-            hash_map::Entry::Occupied(entry) => entry.get().state_rx.clone(),
-        };
+        let mut state_rx = self
+            .state_subscriptions
+            .lock()
+            .entry(account)
+            .or_default()
+            .state
+            .receiver();
 
         state_rx.changed().await?;
-        let account = state_rx.borrow_and_update();
-        ExistingContract::from_shard_account_opt(account.deref())
+        match state_rx.borrow_and_update().deref() {
+            Some(update) => update.contract(),
+            None => Ok(None),
+        }
+    }
+
+    pub fn last_known_transaction_lt(&self, account: &UInt256) -> Option<u64> {
+        self.state_subscriptions
+            .lock()
+            .get(account)
+            .and_then(|subscription| subscription.state.last_transaction_lt())
+    }
+
+    /// Account state as of the latest processed block. Doesn't wait for the next block.
+    /// `None` if the account state was not processed yet or the account doesn't exist
+    pub fn last_known_contract_state(&self, account: &UInt256) -> Result<Option<ExistingContract>> {
+        let update = self
+            .state_subscriptions
+            .lock()
+            .get(account)
+            .and_then(|subscription| subscription.state.last_update());
+        match update {
+            Some(update) => update.contract(),
+            None => Ok(None),
+        }
+    }
+
+    /// Subscribes to the account states with block context and keeps
+    /// up to `depth` recent states in the subscription history
+    pub fn subscribe_account_state_with_history(
+        &self,
+        account: UInt256,
+        depth: usize,
+    ) -> AccountStateSubscription {
+        self.state_subscriptions
+            .lock()
+            .entry(account)
+            .or_default()
+            .state
+            .subscribe_with_history(depth)
     }
 
     pub async fn wait_contract_state(&self, account: UInt256) -> Result<ExistingContract> {
-        let mut state_rx = match self.state_subscriptions.lock().entry(account) {
-            hash_map::Entry::Vacant(entry) => {
-                let (state_tx, state_rx) = watch::channel(None);
-                entry
-                    .insert(StateSubscription {
-                        state_tx,
-                        state_rx,
-                        transaction_subscriptions: Vec::new(),
-                    })
-                    .state_rx
-                    .clone()
-            }
-            hash_map::Entry::Occupied(entry) => entry.get().state_rx.clone(),
-        };
+        let mut state_rx = self
+            .state_subscriptions
+            .lock()
+            .entry(account)
+            .or_default()
+            .state
+            .receiver();
 
         loop {
             state_rx.changed().await?;
 
             let shard_account = match state_rx.borrow_and_update().deref() {
-                Some(shard_account) => ExistingContract::from_shard_account(shard_account)?,
+                Some(update) => update.contract()?,
                 None => continue,
             };
 
@@ -231,12 +278,7 @@ impl TonSubscriber {
         }
     }
 
-    fn handle_masterchain_block(
-        &self,
-        meta: BriefBlockMeta,
-        block: &ton_block::Block,
-    ) -> Result<()> {
-        let gen_utime = meta.gen_utime();
+    pub fn handle_masterchain_block(&self, gen_utime: u32, block: &ton_block::Block) -> Result<()> {
         self.current_utime.store(gen_utime, Ordering::Release);
 
         if !self.ready.load(Ordering::Acquire) {
@@ -244,6 +286,16 @@ impl TonSubscriber {
         }
 
         let block_info = block.info.read_struct()?;
+        self.last_processed_mc_seqno
+            .store(block_info.seq_no(), Ordering::Release);
+
+        if self
+            .first_live_mc_seqno
+            .compare_exchange(0, block_info.seq_no(), Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            self.first_live_signal.notify_waiters();
+        }
 
         let mut mc_block_awaiters = self.mc_block_awaiters.lock();
         mc_block_awaiters.retain(
@@ -259,10 +311,13 @@ impl TonSubscriber {
         Ok(())
     }
 
-    fn handle_shard_block(
+    /// Updates account states and passes block transactions to subscriptions.
+    /// Shard accounts must contain at least the accounts changed in this block
+    pub async fn handle_shard_block(
         &self,
+        block_id: &ton_block::BlockIdExt,
         block: &ton_block::Block,
-        shard_state: &ton_block::ShardStateUnsplit,
+        shard_accounts: &ton_block::ShardAccounts,
     ) -> Result<()> {
         if !self.ready.load(Ordering::Acquire) {
             return Ok(());
@@ -271,8 +326,9 @@ impl TonSubscriber {
         let block_info = block.info.read_struct()?;
         let extra = block.extra.read_struct()?;
         let account_blocks = extra.read_account_blocks()?;
-        let shard_accounts = shard_state.read_accounts()?;
+        let shard_accounts = shard_accounts.clone();
 
+        let mut transaction_subscriptions = Vec::new();
         {
             let mut subscriptions = self.state_subscriptions.lock();
             subscriptions.retain(|account, subscription| {
@@ -289,8 +345,13 @@ impl TonSubscriber {
 
                 if subscription_status == StateSubscriptionStatus::Alive {
                     match shard_accounts.get(account) {
-                        Ok(account) => {
-                            if subscription.state_tx.send(account).is_err() {
+                        Ok(shard_account) => {
+                            let update = AccountStateUpdate {
+                                block_id: block_id.clone(),
+                                lt: block_info.end_lt(),
+                                shard_account,
+                            };
+                            if subscription.state.send(update).is_err() {
                                 log::error!("Shard subscription somehow dropped");
                                 keep = false;
                             }
@@ -300,25 +361,93 @@ impl TonSubscriber {
                         }
                     };
                 } else {
-                    subscription.state_rx.borrow_and_update();
+                    subscription.state.mark_seen();
                 }
 
-                if let Err(e) = subscription.handle_block(
-                    &self.messages_queue,
-                    &shard_accounts,
-                    &block_info,
-                    &account_blocks,
-                    account,
-                ) {
-                    log::error!("Failed to handle block: {:?}", e);
+                if !subscription.transaction_subscriptions.is_empty() {
+                    transaction_subscriptions.push((
+                        *account,
+                        subscription
+                            .iter_transaction_subscriptions()
+                            .collect::<Vec<_>>(),
+                    ));
                 }
 
                 keep
             });
         }
 
-        self.messages_queue
-            .update(block_info.shard(), block_info.gen_utime().0);
+        // NOTE: transactions are handled outside the subscriptions lock because
+        // observers may wait for a free space in bounded events queues
+        let block_transactions = BlockTransactions {
+            block_info,
+            account_blocks,
+            shard_accounts,
+            subscriptions: transaction_subscriptions,
+        };
+
+        // NOTE: messages are always tracked in the blocks processing path,
+        // so that their statuses are updated in the blocks order
+        let (tracking, block_transactions) = block_transactions.split_message_tracking();
+        tracking.dispatch(Some(&self.messages_queue), &self.transaction_handlers);
+
+        let mut deferred_transactions = self.deferred_transactions.lock().await;
+        match deferred_transactions.as_mut() {
+            Some(deferred) => {
+                if !block_transactions.subscriptions.is_empty() {
+                    deferred.push(block_transactions);
+                }
+            }
+            None => self.dispatch_block(block_transactions).await,
+        }
+
+        Ok(())
+    }
+
+    async fn dispatch_block(&self, block_transactions: BlockTransactions) {
+        if block_transactions.subscriptions.is_empty() {
+            return;
+        }
+
+        match &self.transactions_dispatcher {
+            Some(dispatcher) => dispatcher.send(block_transactions).await,
+            None => block_transactions.dispatch(None, &self.transaction_handlers),
+        }
+    }
+
+    /// Passes transactions from the already applied block to subscriptions.
+    /// Account states are not updated because they are newer than the block
+    pub fn replay_shard_block(
+        &self,
+        block: &ton_block::Block,
+        shard_accounts: &ton_block::ShardAccounts,
+    ) -> Result<()> {
+        let block_info = block.info.read_struct()?;
+        let extra = block.extra.read_struct()?;
+        let account_blocks = extra.read_account_blocks()?;
+
+        let transaction_subscriptions = self
+            .state_subscriptions
+            .lock()
+            .iter()
+            .filter(|(account, _)| contains_account(block_info.shard(), account))
+            .map(|(account, subscription)| {
+                let subscriptions = subscription
+                    .iter_transaction_subscriptions()
+                    .collect::<Vec<_>>();
+                (*account, subscriptions)
+            })
+            .filter(|(_, subscriptions)| !subscriptions.is_empty())
+            .collect::<Vec<_>>();
+
+        dispatch_block_transactions(
+            Some(&self.messages_queue),
+            &self.transaction_handlers,
+            &transaction_subscriptions,
+            shard_accounts,
+            &block_info,
+            &account_blocks,
+        );
 
         Ok(())
     }
@@ -349,9 +478,11 @@ impl ton_indexer::Subscriber for TonSubscriber {
         shard_state: &ShardStateStuff,
     ) -> Result<()> {
         if block.id().is_masterchain() {
-            self.handle_masterchain_block(meta, block.block())?;
+            self.handle_masterchain_block(meta.gen_utime(), block.block())?;
         } else {
-            self.handle_shard_block(block.block(), shard_state.state())?;
+            let shard_accounts = shard_state.state().read_accounts()?;
+            self.handle_shard_block(block.id(), block.block(), &shard_accounts)
+                .await?;
         }
 
         Ok(())
@@ -363,13 +494,15 @@ pub struct TonSubscriberMetrics {
     pub ready: bool,
     pub current_utime: u32,
     pub pending_message_count: usize,
+    /// Number of shard blocks waiting for the background dispatch
+    pub dispatch_queue_len: usize,
 }
 
 static BLOCK_AWAITER_ID: AtomicUsize = AtomicUsize::new(0);
 
+#[derive(Default)]
 struct StateSubscription {
-    state_tx: ShardAccountTx,
-    state_rx: ShardAccountRx,
+    state: AccountStateChannel,
     transaction_subscriptions: Vec<Weak<dyn TransactionsSubscription>>,
 }
 
@@ -378,7 +511,7 @@ impl StateSubscription {
         self.transaction_subscriptions
             .retain(|item| item.strong_count() > 0);
 
-        if self.state_tx.receiver_count() > 1 {
+        if self.state.has_subscribers() {
             StateSubscriptionStatus::Alive
         } else if !self.transaction_subscriptions.is_empty() {
             StateSubscriptionStatus::PartlyAlive
@@ -387,99 +520,281 @@ impl StateSubscription {
         }
     }
 
-    fn handle_block(
-        &self,
-        messages_queue: &PendingMessagesQueue,
-        shard_accounts: &ton_block::ShardAccounts,
-        block_info: &ton_block::BlockInfo,
-        account_blocks: &ton_block::ShardAccountBlocks,
-        account: &UInt256,
-    ) -> Result<()> {
-        if self.transaction_subscriptions.is_empty() {
-            return Ok(());
+    fn iter_transaction_subscriptions(
+        &'_ self,
+    ) -> impl Iterator<Item = Arc<dyn TransactionsSubscription>> + '_ {
+        self.transaction_subscriptions
+            .iter()
+            .map(Weak::upgrade)
+            .flatten()
+    }
+}
+
+/// Transactions of the shard block for subscribed accounts
+struct BlockTransactions {
+    block_info: ton_block::BlockInfo,
+    account_blocks: ton_block::ShardAccountBlocks,
+    shard_accounts: ton_block::ShardAccounts,
+    subscriptions: Vec<(UInt256, Vec<Arc<dyn TransactionsSubscription>>)>,
+}
+
+impl BlockTransactions {
+    /// Passes transactions to subscriptions and updates messages status if
+    /// the queue is specified
+    fn dispatch(
+        self,
+        messages_queue: Option<&PendingMessagesQueue>,
+        handlers: &TransactionHandlers,
+    ) {
+        dispatch_block_transactions(
+            messages_queue,
+            handlers,
+            &self.subscriptions,
+            &self.shard_accounts,
+            &self.block_info,
+            &self.account_blocks,
+        );
+
+        // NOTE: must be called after all messages in this block are delivered
+        if let Some(messages_queue) = messages_queue {
+            messages_queue.update(self.block_info.shard(), self.block_info.gen_utime().0);
         }
+    }
 
-        let account_block = match account_blocks
-            .get_with_aug(account)
-            .with_context(|| format!("Failed to get account block for {:x}", account))?
-        {
-            Some((account_block, _)) => account_block,
-            None => return Ok(()),
-        };
+    /// Splits transactions into the part with all accounts for the messages queue
+    /// and subscriptions which track messages, and the part with other subscriptions
+    fn split_message_tracking(self) -> (Self, Self) {
+        let mut tracking = Vec::with_capacity(self.subscriptions.len());
+        let mut other = Vec::with_capacity(self.subscriptions.len());
+        for (account, subscriptions) in self.subscriptions {
+            let (tracking_subscriptions, other_subscriptions): (Vec<_>, Vec<_>) = subscriptions
+                .into_iter()
+                .partition(|subscription| subscription.tracks_messages());
+
+            // NOTE: all accounts are left in the tracking part for the messages queue
+            tracking.push((account, tracking_subscriptions));
+            if !other_subscriptions.is_empty() {
+                other.push((account, other_subscriptions));
+            }
+        }
 
-        for transaction in account_block.transactions().iter() {
-            let (hash, transaction) = match transaction.and_then(|(_, value)| {
-                let cell = value.into_cell().reference(0)?;
-                let hash = cell.repr_hash();
+        let other = Self {
+            block_info: self.block_info.clone(),
+            account_blocks: self.account_blocks.clone(),
+            shard_accounts: self.shard_accounts.clone(),
+            subscriptions: other,
+        };
+        let tracking = Self {
+            subscriptions: tracking,
+            ..self
+        };
+        (tracking, other)
+    }
+}
 
-                ton_block::Transaction::construct_from_cell(cell)
-                    .map(|transaction| (hash, transaction))
-            }) {
-                Ok(tx) => tx,
-                Err(e) => {
-                    log::error!(
-                        "Failed to parse transaction in block {} for account {:x}: {:?}",
-                        block_info.seq_no(),
-                        account,
-                        e
-                    );
-                    continue;
-                }
-            };
+/// Handles block transactions in order outside the blocks processing path
+struct TransactionsDispatcher<T = BlockTransactions> {
+    tx: mpsc::Sender<T>,
+    queue_len: Arc<AtomicUsize>,
+}
 
-            // Skip non-ordinary or aborted transactions
-            let transaction_info = match transaction.description.read_struct() {
-                Ok(ton_block::TransactionDescr::Ordinary(info)) if !info.aborted => info,
-                _ => continue,
-            };
+impl TransactionsDispatcher {
+    fn start(capacity: usize, handlers: &Arc<TransactionHandlers>) -> Self {
+        let handlers = handlers.clone();
+        Self::start_with(capacity, move |block_transactions: BlockTransactions| {
+            block_transactions.dispatch(None, &handlers)
+        })
+    }
+}
 
-            let in_msg = match transaction
-                .in_msg
-                .as_ref()
-                .map(|message| (message, message.read_struct()))
-            {
-                Some((message_cell, Ok(message))) => {
-                    if matches!(message.header(), ton_block::CommonMsgInfo::ExtInMsgInfo(_)) {
-                        messages_queue.deliver_message(*account, message_cell.hash());
+impl<T: Send + 'static> TransactionsDispatcher<T> {
+    /// Spawns a thread which passes queued items to `f` in order.
+    ///
+    /// NOTE: must be called within the tokio runtime context
+    fn start_with<F>(capacity: usize, mut f: F) -> Self
+    where
+        F: FnMut(T) + Send + 'static,
+    {
+        let (tx, mut rx) = mpsc::channel::<T>(capacity.max(1));
+        let queue_len = Arc::new(AtomicUsize::new(0));
+
+        // NOTE: handlers are synchronous and may be slow, so they are executed
+        // in a separate thread instead of the runtime. The runtime context is
+        // entered there because handlers spawn tasks (e.g. on full events queues)
+        let runtime = tokio::runtime::Handle::current();
+        std::thread::Builder::new()
+            .name("transactions-dispatcher".to_owned())
+            .spawn({
+                let queue_len = queue_len.clone();
+                move || {
+                    let _runtime_guard = runtime.enter();
+                    while let Some(item) = rx.blocking_recv() {
+                        f(item);
+                        queue_len.fetch_sub(1, Ordering::Release);
                     }
-                    message
                 }
-                _ => continue,
-            };
+            })
+            .expect("Failed to start transactions dispatcher");
 
-            let ctx = TxContext {
-                shard_accounts,
-                block_info,
-                account,
-                transaction_hash: &hash,
-                transaction_info: &transaction_info,
-                transaction: &transaction,
-                in_msg: &in_msg,
-            };
+        Self { tx, queue_len }
+    }
+
+    /// Waits until there is free space in the queue
+    async fn send(&self, item: T) {
+        self.queue_len.fetch_add(1, Ordering::Release);
+        if self.tx.send(item).await.is_err() {
+            self.queue_len.fetch_sub(1, Ordering::Release);
+            log::error!("Transactions dispatcher somehow stopped");
+        }
+    }
+
+    fn queue_len(&self) -> usize {
+        self.queue_len.load(Ordering::Acquire)
+    }
+}
+
+/// Passes block transactions of the subscribed accounts to subscriptions.
+///
+/// NOTE: transactions are dispatched in the logical time order, so that internal
+/// messages are found after the transactions which produced them
+fn dispatch_block_transactions(
+    messages_queue: Option<&PendingMessagesQueue>,
+    handlers: &TransactionHandlers,
+    subscriptions: &[(UInt256, Vec<Arc<dyn TransactionsSubscription>>)],
+    shard_accounts: &ton_block::ShardAccounts,
+    block_info: &ton_block::BlockInfo,
+    account_blocks: &ton_block::ShardAccountBlocks,
+) {
+    let mut transactions = Vec::new();
+    for (account, subscriptions) in subscriptions {
+        if let Err(e) =
+            read_account_transactions(block_info, account_blocks, account, |hash, tx| {
+                transactions.push((account, subscriptions, hash, tx))
+            })
+        {
+            log::error!("Failed to handle block: {:?}", e);
+        }
+    }
+
+    transactions.sort_unstable_by_key(|(_, _, _, transaction)| transaction.logical_time());
 
-            // Handle transaction
-            for subscription in self.iter_transaction_subscriptions() {
-                if let Err(e) = subscription.handle_transaction(ctx) {
-                    log::error!(
-                        "Failed to handle transaction {:x} for account {:x}: {:?}",
-                        hash,
-                        account,
-                        e
-                    );
+    for (account, subscriptions, hash, transaction) in transactions {
+        dispatch_transaction(
+            messages_queue,
+            handlers,
+            subscriptions,
+            shard_accounts,
+            block_info,
+            account,
+            &hash,
+            &transaction,
+        );
+    }
+}
+
+fn read_account_transactions<F>(
+    block_info: &ton_block::BlockInfo,
+    account_blocks: &ton_block::ShardAccountBlocks,
+    account: &UInt256,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(UInt256, ton_block::Transaction),
+{
+    let account_block = match account_blocks
+        .get_with_aug(account)
+        .with_context(|| format!("Failed to get account block for {:x}", account))?
+    {
+        Some((account_block, _)) => account_block,
+        None => return Ok(()),
+    };
+
+    for transaction in account_block.transactions().iter() {
+        match transaction.and_then(|(_, value)| {
+            let cell = value.into_cell().reference(0)?;
+            let hash = cell.repr_hash();
+
+            ton_block::Transaction::construct_from_cell(cell).map(|transaction| (hash, transaction))
+        }) {
+            Ok((hash, transaction)) => f(hash, transaction),
+            Err(e) => {
+                log::error!(
+                    "Failed to parse transaction in block {} for account {:x}: {:?}",
+                    block_info.seq_no(),
+                    account,
+                    e
+                );
+            }
+        };
+    }
+
+    Ok(())
+}
+
+/// Updates sent messages status and passes the transaction to subscriptions
+pub fn dispatch_transaction(
+    messages_queue: Option<&PendingMessagesQueue>,
+    handlers: &TransactionHandlers,
+    subscriptions: &[Arc<dyn TransactionsSubscription>],
+    shard_accounts: &ton_block::ShardAccounts,
+    block_info: &ton_block::BlockInfo,
+    account: &UInt256,
+    hash: &UInt256,
+    transaction: &ton_block::Transaction,
+) {
+    // Skip non-ordinary transactions
+    let transaction_info = match transaction.description.read_struct() {
+        Ok(ton_block::TransactionDescr::Ordinary(info)) => info,
+        _ => return,
+    };
+
+    let in_msg = match transaction
+        .in_msg
+        .as_ref()
+        .map(|message| (message, message.read_struct()))
+    {
+        Some((message_cell, Ok(message))) => {
+            // NOTE: internal messages are tracked for the wallet sender
+            match messages_queue {
+                Some(messages_queue) if transaction_info.aborted => {
+                    let (exit_code, phase) = TransactionPhase::from_aborted(&transaction_info);
+                    messages_queue.fail_message(*account, message_cell.hash(), exit_code, phase);
                 }
+                Some(messages_queue) => {
+                    messages_queue.deliver_message(*account, message_cell.hash(), *hash)
+                }
+                None => {}
             }
+            message
         }
+        _ => return,
+    };
 
-        Ok(())
+    // Skip aborted transactions
+    if transaction_info.aborted {
+        return;
     }
 
-    fn iter_transaction_subscriptions(
-        &'_ self,
-    ) -> impl Iterator<Item = Arc<dyn TransactionsSubscription>> + '_ {
-        self.transaction_subscriptions
-            .iter()
-            .map(Weak::upgrade)
-            .flatten()
+    let ctx = TxContext {
+        shard_accounts,
+        block_info,
+        account,
+        transaction_hash: hash,
+        transaction_info: &transaction_info,
+        transaction,
+        in_msg: &in_msg,
+    };
+
+    // Handle transaction
+    for subscription in subscriptions {
+        if let Err(e) = handlers.handle(subscription.as_ref(), ctx) {
+            log::error!(
+                "Failed to handle transaction {:x} for account {:x}: {:?}",
+                hash,
+                account,
+                e
+            );
+        }
     }
 }
 
@@ -505,6 +820,19 @@ enum BlockAwaiterAction {
 }
 
 pub trait TransactionsSubscription: Send + Sync {
+    /// Name used in logs and metrics
+    fn name(&self) -> &'static str {
+        short_type_name::<Self>()
+    }
+
+    /// Whether the subscription tracks messages delivery. Such subscriptions are
+    /// handled together with the messages queue in the blocks processing path,
+    /// so they are neither deferred until the missed blocks are replayed nor
+    /// dispatched in background
+    fn tracks_messages(&self) -> bool {
+        false
+    }
+
     fn handle_transaction(&self, ctx: TxContext<'_>) -> Result<()>;
 }
 
@@ -519,8 +847,12 @@ impl<T> AccountObserver<T> {
 
 impl<T> TransactionsSubscription for AccountObserver<T>
 where
-    T: ReadFromTransaction + std::fmt::Debug + Send + Sync,
+    T: ReadFromTransaction + std::fmt::Debug + Send + Sync + 'static,
 {
+    fn name(&self) -> &'static str {
+        short_type_name::<T>()
+    }
+
     fn handle_transaction(&self, ctx: TxContext<'_>) -> Result<()> {
         let event = T::read_from_transaction(&ctx);
 
@@ -528,8 +860,8 @@ where
 
         // Send event to event manager if it exist
         if let Some(event) = event {
-            if self.0.send((*ctx.account, event)).is_err() {
-                log::error!("Failed to send event: channel is dropped");
+            if let Err(e) = self.0.send(*ctx.account, event) {
+                log::error!("Failed to send event: {}", e);
             }
         }
 
@@ -541,8 +873,8 @@ where
 pub fn start_listening_events<S, E, R>(
     service: &Arc<S>,
     name: &'static str,
-    mut events_rx: mpsc::UnboundedReceiver<E>,
-    handler: fn(Arc<S>, E) -> R,
+    mut events_rx: AccountEventsRx<E>,
+    handler: fn(Arc<S>, (UInt256, E)) -> R,
 ) where
     S: Send + Sync + 'static,
     E: Send + 'static,
@@ -557,9 +889,11 @@ pub fn start_listening_events<S, E, R>(
                 None => break,
             };
 
+            let started_at = std::time::Instant::now();
             if let Err(e) = handler(service, event).await {
                 log::error!("{}: Failed to handle event: {:?}", name, e);
             }
+            events_rx.stats().on_processed(started_at);
         }
 
         log::warn!("{}: Stopped listening for events", name);
@@ -569,13 +903,76 @@ pub fn start_listening_events<S, E, R>(
     });
 }
 
-type ShardAccountTx = watch::Sender<Option<ton_block::ShardAccount>>;
-type ShardAccountRx = watch::Receiver<Option<ton_block::ShardAccount>>;
-
-pub type AccountEventsTx<T> = mpsc::UnboundedSender<(UInt256, T)>;
+/// Type name without the module path
+fn short_type_name<T: ?Sized>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
 
 #[derive(thiserror::Error, Debug)]
 enum TonSubscriberError {
     #[error("Account is frozen")]
     AccountIsFrozen,
+    #[error("Account state channel closed")]
+    AccountStateChannelClosed,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    struct Observer;
+
+    impl TransactionsSubscription for Observer {
+        fn handle_transaction(&self, _: TxContext<'_>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn short_type_name_strips_module_path_and_generics() {
+        assert_eq!(short_type_name::<u32>(), "u32");
+        assert_eq!(short_type_name::<Observer>(), "Observer");
+        assert_eq!(short_type_name::<AccountObserver<u32>>(), "AccountObserver");
+        assert_eq!(
+            short_type_name::<std::collections::HashMap<String, Vec<u8>>>(),
+            "HashMap"
+        );
+        assert_eq!(Observer.name(), "Observer");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn background_dispatch_into_full_events_queue() {
+        let queues = AccountEventsQueues::new(EventsQueueConfig {
+            capacity: 1,
+            ..Default::default()
+        });
+        let (events_tx, mut events_rx) = queues.channel::<u32>("test");
+        let (handled_tx, mut handled_rx) = mpsc::unbounded_channel();
+
+        let dispatcher = TransactionsDispatcher::start_with(1, move |events: Vec<u32>| {
+            for event in events {
+                events_tx.send(UInt256::default(), event).unwrap();
+            }
+            handled_tx.send(()).ok();
+        });
+
+        // The queue becomes full after the first event
+        dispatcher.send(vec![0, 1, 2, 3]).await;
+        tokio::time::timeout(Duration::from_secs(10), handled_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let metrics = &queues.metrics()[0].1;
+        assert_eq!(metrics.depth, 4);
+        assert_eq!(metrics.overflowed, 1);
+
+        for i in 0..4 {
+            assert_eq!(events_rx.recv().await.unwrap().1, i);
+        }
+    }
 }
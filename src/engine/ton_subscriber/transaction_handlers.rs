@@ -0,0 +1,233 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use ton_types::UInt256;
+
+use super::TransactionsSubscription;
+use crate::config::*;
+use crate::utils::*;
+
+/// Transactions subscriptions runner with per-subscription statistics
+pub struct TransactionHandlers {
+    slow_threshold: Duration,
+    stats: Mutex<Vec<(&'static str, Arc<TransactionHandlerStats>)>>,
+}
+
+impl TransactionHandlers {
+    pub fn new(config: &TransactionHandlersConfig) -> Arc<Self> {
+        Arc::new(Self {
+            slow_threshold: Duration::from_millis(config.slow_handler_threshold_ms),
+            stats: Default::default(),
+        })
+    }
+
+    /// Passes the transaction to the subscription and measures its execution time
+    pub fn handle(
+        &self,
+        subscription: &dyn TransactionsSubscription,
+        ctx: TxContext<'_>,
+    ) -> Result<()> {
+        let started_at = Instant::now();
+        let result = subscription.handle_transaction(ctx);
+        self.on_handled(
+            subscription.name(),
+            ctx.transaction_hash,
+            started_at.elapsed(),
+        );
+        result
+    }
+
+    fn on_handled(&self, name: &'static str, transaction_hash: &UInt256, elapsed: Duration) {
+        let is_slow = elapsed > self.slow_threshold;
+        if is_slow {
+            log::warn!(
+                "{}: Transaction {:x} handler is too slow: {:?}",
+                name,
+                transaction_hash,
+                elapsed
+            );
+        }
+
+        self.get_stats(name).on_handled(elapsed, is_slow);
+    }
+
+    pub fn metrics(&self) -> Vec<(&'static str, TransactionHandlerMetrics)> {
+        self.stats
+            .lock()
+            .iter()
+            .map(|(name, stats)| (*name, stats.metrics()))
+            .collect()
+    }
+
+    fn get_stats(&self, name: &'static str) -> Arc<TransactionHandlerStats> {
+        let mut stats = self.stats.lock();
+        match stats.iter().find(|(item, _)| *item == name) {
+            Some((_, stats)) => stats.clone(),
+            None => {
+                let item = Arc::new(TransactionHandlerStats::default());
+                stats.push((name, item.clone()));
+                item
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct TransactionHandlerStats {
+    handled: AtomicU64,
+    slow: AtomicU64,
+    processing_time_us: AtomicU64,
+    max_processing_time_us: AtomicU64,
+}
+
+impl TransactionHandlerStats {
+    fn on_handled(&self, elapsed: Duration, is_slow: bool) {
+        let elapsed = elapsed.as_micros() as u64;
+        self.handled.fetch_add(1, Ordering::Release);
+        if is_slow {
+            self.slow.fetch_add(1, Ordering::Release);
+        }
+        self.processing_time_us
+            .fetch_add(elapsed, Ordering::Release);
+        self.max_processing_time_us
+            .fetch_max(elapsed, Ordering::Release);
+    }
+
+    fn metrics(&self) -> TransactionHandlerMetrics {
+        TransactionHandlerMetrics {
+            handled: self.handled.load(Ordering::Acquire),
+            slow: self.slow.load(Ordering::Acquire),
+            processing_time_us: self.processing_time_us.load(Ordering::Acquire),
+            max_processing_time_us: self.max_processing_time_us.load(Ordering::Acquire),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TransactionHandlerMetrics {
+    /// Total number of handled transactions
+    pub handled: u64,
+    /// Number of transactions which were handled longer than the threshold
+    pub slow: u64,
+    /// Total time spent in the handler
+    pub processing_time_us: u64,
+    /// The longest handler execution time
+    pub max_processing_time_us: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestSubscription {
+        name: &'static str,
+        fail: bool,
+    }
+
+    impl TransactionsSubscription for TestSubscription {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn handle_transaction(&self, _: TxContext<'_>) -> Result<()> {
+            if self.fail {
+                anyhow::bail!("Test error");
+            }
+            Ok(())
+        }
+    }
+
+    fn handle_transactions(
+        handlers: &TransactionHandlers,
+        subscription: &TestSubscription,
+        count: usize,
+    ) -> Vec<Result<()>> {
+        let shard_accounts = Default::default();
+        let block_info = Default::default();
+        let account = UInt256::default();
+        let transaction_hash = UInt256::default();
+        let transaction_info = Default::default();
+        let transaction = Default::default();
+        let in_msg = Default::default();
+
+        let ctx = TxContext {
+            shard_accounts: &shard_accounts,
+            block_info: &block_info,
+            account: &account,
+            transaction_hash: &transaction_hash,
+            transaction_info: &transaction_info,
+            transaction: &transaction,
+            in_msg: &in_msg,
+        };
+
+        (0..count)
+            .map(|_| handlers.handle(subscription, ctx))
+            .collect()
+    }
+
+    fn find_metrics(handlers: &TransactionHandlers, name: &str) -> TransactionHandlerMetrics {
+        handlers
+            .metrics()
+            .into_iter()
+            .find(|(item, _)| *item == name)
+            .map(|(_, metrics)| metrics)
+            .unwrap()
+    }
+
+    #[test]
+    fn handled_transactions_are_counted_per_subscription() {
+        let handlers = TransactionHandlers::new(&TransactionHandlersConfig {
+            slow_handler_threshold_ms: 1000,
+            ..Default::default()
+        });
+
+        let fast = TestSubscription {
+            name: "Fast",
+            fail: false,
+        };
+        let failing = TestSubscription {
+            name: "Failing",
+            fail: true,
+        };
+
+        assert!(handle_transactions(&handlers, &fast, 3)
+            .iter()
+            .all(Result::is_ok));
+        assert!(handle_transactions(&handlers, &failing, 2)
+            .iter()
+            .all(Result::is_err));
+
+        assert_eq!(handlers.metrics().len(), 2);
+
+        let metrics = find_metrics(&handlers, "Fast");
+        assert_eq!(metrics.handled, 3);
+        assert_eq!(metrics.slow, 0);
+
+        // Failed transactions are counted too
+        let metrics = find_metrics(&handlers, "Failing");
+        assert_eq!(metrics.handled, 2);
+        assert_eq!(metrics.slow, 0);
+    }
+
+    #[test]
+    fn slow_handlers_are_detected() {
+        let handlers = TransactionHandlers::new(&TransactionHandlersConfig {
+            slow_handler_threshold_ms: 10,
+            ..Default::default()
+        });
+
+        let transaction_hash = UInt256::default();
+        for elapsed_ms in [20, 5, 30] {
+            handlers.on_handled("Slow", &transaction_hash, Duration::from_millis(elapsed_ms));
+        }
+
+        let metrics = find_metrics(&handlers, "Slow");
+        assert_eq!(metrics.handled, 3);
+        assert_eq!(metrics.slow, 2);
+        assert_eq!(metrics.max_processing_time_us, 30_000);
+        assert_eq!(metrics.processing_time_us, 55_000);
+    }
+}
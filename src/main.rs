@@ -1,4 +1,6 @@
 use std::borrow::Cow;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
@@ -7,6 +9,7 @@ use dialoguer::{Confirm, Input, Password};
 use pkey_mprotect::*;
 use relay::config::*;
 use relay::engine::*;
+use relay::utils::{AbiCheckStatus, AbiTestVector, SystemClock};
 use serde::{Deserialize, Serialize};
 use tokio::signal::unix;
 use tokio::sync::mpsc;
@@ -20,6 +23,9 @@ fn main() -> Result<()> {
         Subcommand::Run(run) => run.execute(),
         Subcommand::Generate(generate) => generate.execute(),
         Subcommand::Export(export) => export.execute(),
+        Subcommand::InspectEvent(inspect_event) => inspect_event.execute(),
+        Subcommand::AbiCheck(abi_check) => abi_check.execute(),
+        Subcommand::Staking(staking) => staking.execute(),
     }
 }
 
@@ -36,6 +42,9 @@ enum Subcommand {
     Run(CmdRun),
     Generate(CmdGenerate),
     Export(CmdExport),
+    InspectEvent(CmdInspectEvent),
+    AbiCheck(CmdAbiCheck),
+    Staking(CmdStaking),
 }
 
 #[derive(Debug, PartialEq, FromArgs)]
@@ -236,6 +245,247 @@ impl CmdExport {
     }
 }
 
+#[derive(Debug, PartialEq, FromArgs)]
+/// Prints what the relay would vote for the event, using the local node DB
+#[argh(subcommand, name = "inspect-event")]
+struct CmdInspectEvent {
+    /// event contract address
+    #[argh(positional)]
+    event: String,
+
+    /// path to config file ('config.yaml' by default)
+    #[argh(option, short = 'c', default = "String::from(\"config.yaml\")")]
+    config: String,
+
+    /// directory for the node DB secondary instance files (new temporary directory by default)
+    #[argh(option)]
+    secondary_db_path: Option<String>,
+}
+
+impl CmdInspectEvent {
+    fn execute(self) -> Result<()> {
+        let config: AppConfig = read_config(&self.config)?;
+
+        let event_address = ton_block::MsgAddressInt::from_str(&self.event)
+            .map_err(|_| InitError::InvalidEventAddress)?;
+
+        // NOTE: protection keys must be called in the main thread
+        let protection_keys = ProtectionKeys::new(config.require_protected_keystore)
+            .context("Failed to create protection keys")?;
+
+        let inspection = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(Engine::inspect_event(
+                config,
+                protection_keys,
+                event_address,
+                self.secondary_db_path.map(PathBuf::from),
+            ))
+            .context("Failed to inspect event")?;
+
+        println!("{}", serde_json::to_string_pretty(&inspection)?);
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// Runs TON<->EVM ABI mapping test vectors
+#[argh(subcommand, name = "abi-check")]
+struct CmdAbiCheck {
+    /// paths to JSON files with test vectors
+    #[argh(positional)]
+    vectors: Vec<String>,
+}
+
+impl CmdAbiCheck {
+    fn execute(self) -> Result<()> {
+        let mut failed = 0;
+        for path in &self.vectors {
+            let vectors = AbiTestVector::load_all(path)
+                .with_context(|| format!("Failed to load test vectors from {}", path))?;
+
+            for vector in vectors {
+                match vector.check() {
+                    Ok(AbiCheckStatus::Passed) => println!("OK {}", vector.name()),
+                    Ok(AbiCheckStatus::Failed { actual }) => {
+                        failed += 1;
+                        println!("FAILED {}: actual {}", vector.name(), actual);
+                    }
+                    Ok(AbiCheckStatus::Generated { actual }) => {
+                        println!("GENERATED {}: {}", vector.name(), actual)
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        println!("ERROR {}: {:?}", vector.name(), e);
+                    }
+                }
+            }
+        }
+
+        if failed > 0 {
+            return Err(InitError::AbiCheckFailed(failed).into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// Staking related tools
+#[argh(subcommand, name = "staking")]
+struct CmdStaking {
+    #[argh(subcommand)]
+    command: StakingSubcommand,
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+#[argh(subcommand)]
+enum StakingSubcommand {
+    Report(CmdStakingReport),
+    Forecast(CmdStakingForecast),
+}
+
+impl CmdStaking {
+    fn execute(self) -> Result<()> {
+        match self.command {
+            StakingSubcommand::Report(report) => report.execute(),
+            StakingSubcommand::Forecast(forecast) => forecast.execute(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// Prints participation and rewards by relay rounds
+#[argh(subcommand, name = "report")]
+struct CmdStakingReport {
+    /// first relay round to include (0 by default)
+    #[argh(option, default = "0")]
+    from_round: u32,
+
+    /// output format: 'csv' or 'json' ('csv' by default)
+    #[argh(option, default = "ReportFormat::Csv")]
+    format: ReportFormat,
+
+    /// staker address (the main staker by default)
+    #[argh(option)]
+    staker: Option<String>,
+
+    /// path to config file ('config.yaml' by default)
+    #[argh(option, short = 'c', default = "String::from(\"config.yaml\")")]
+    config: String,
+}
+
+impl CmdStakingReport {
+    fn execute(self) -> Result<()> {
+        let config: AppConfig = read_config(&self.config)?;
+        let profile = find_staker_profile(&config, self.staker.as_deref())?;
+
+        let accounting = StakingAccounting::load(&profile.staking_accounting_path)
+            .context("Failed to load staking accounting")?;
+
+        match self.format {
+            ReportFormat::Csv => print!("{}", accounting.to_csv(self.from_round)),
+            ReportFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(&accounting.since(self.from_round))?
+            ),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+/// Prints the estimated schedule of the next relay rounds using the admin API
+#[argh(subcommand, name = "forecast")]
+struct CmdStakingForecast {
+    /// number of rounds to forecast (5 by default)
+    #[argh(option, default = "5")]
+    rounds: usize,
+
+    /// staker address (the main staker by default)
+    #[argh(option)]
+    staker: Option<String>,
+
+    /// path to config file ('config.yaml' by default)
+    #[argh(option, short = 'c', default = "String::from(\"config.yaml\")")]
+    config: String,
+}
+
+impl CmdStakingForecast {
+    fn execute(self) -> Result<()> {
+        let config: AppConfig = read_config(&self.config)?;
+        let profile = find_staker_profile(&config, self.staker.as_deref())?;
+        let admin_api_settings = config
+            .admin_api_settings
+            .ok_or(InitError::AdminApiDisabled)?;
+
+        let mut url = url::Url::parse(&format!(
+            "http://{}/staking/forecast",
+            admin_api_settings.listen_address
+        ))?;
+        url.query_pairs_mut()
+            .append_pair("rounds", &self.rounds.to_string())
+            .append_pair("staker", &profile.staker_address.to_string());
+
+        let token = admin_api_settings.token;
+
+        let forecast = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the Runtime")
+            .block_on(async move {
+                let mut request = reqwest::Client::new().get(url);
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+
+                request
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<serde_json::Value>()
+                    .await
+            })
+            .context("Failed to get forecast from the admin API")?;
+
+        println!("{}", serde_json::to_string_pretty(&forecast)?);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ReportFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = InitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(InitError::InvalidReportFormat),
+        }
+    }
+}
+
+/// Finds hosted staker by address. Returns the main staker if address is not specified
+fn find_staker_profile(config: &AppConfig, staker: Option<&str>) -> Result<StakerProfileConfig> {
+    let mut profiles = config.staker_profiles()?.into_iter();
+    let profile = match staker {
+        Some(staker) => {
+            let staker = ton_block::MsgAddressInt::from_str(staker)
+                .map_err(|_| InitError::InvalidStakerAddress)?;
+            profiles.find(|profile| profile.staker_address == staker)
+        }
+        None => profiles.next(),
+    };
+    profile.ok_or_else(|| InitError::UnknownStaker.into())
+}
+
 trait BriefAppConfigExt {
     fn ask_password(&self, with_confirmation: bool) -> Result<Cow<secstr::SecUtf8>>;
 }
@@ -286,9 +536,15 @@ impl Relay {
     ) -> Result<ShutdownRequestsRx> {
         let (shutdown_requests_tx, shutdown_requests_rx) = mpsc::unbounded_channel();
 
-        let engine = Engine::new(config, global_config, protection_keys, shutdown_requests_tx)
-            .await
-            .context("Failed to create engine")?;
+        let engine = Engine::new(
+            config,
+            global_config,
+            protection_keys,
+            shutdown_requests_tx,
+            Arc::new(SystemClock),
+        )
+        .await
+        .context("Failed to create engine")?;
         *self.engine.lock().await = Some(engine.clone());
 
         engine.start().await.context("Failed to start engine")?;
@@ -408,4 +664,16 @@ fn parse_logger_config(value: serde_yaml::Value) -> Result<log4rs::Config> {
 enum InitError {
     #[error("Errors found when deserializing the logger config")]
     Deserializing,
+    #[error("Invalid event address")]
+    InvalidEventAddress,
+    #[error("{0} ABI test vectors failed")]
+    AbiCheckFailed(usize),
+    #[error("Invalid report format. Expected 'csv' or 'json'")]
+    InvalidReportFormat,
+    #[error("Admin API is not configured")]
+    AdminApiDisabled,
+    #[error("Invalid staker address")]
+    InvalidStakerAddress,
+    #[error("Staker is not hosted by this relay")]
+    UnknownStaker,
 }
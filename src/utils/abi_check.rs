@@ -0,0 +1,475 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use eth_ton_abi_converter::*;
+use serde::{Deserialize, Serialize};
+use ton_types::UInt256;
+
+use super::shard_utils::*;
+
+/// ABI mapping test vector
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "direction", rename_all = "snake_case", deny_unknown_fields)]
+pub enum AbiTestVector {
+    /// EVM event log data to TVM cell (as in ETH event verification)
+    EthToTon {
+        /// Test vector name
+        name: String,
+        /// EVM event ABI (JSON object or string)
+        abi: serde_json::Value,
+        /// Hex encoded event log data
+        input: String,
+        /// Base64 encoded BOC of the expected event data cell.
+        /// Actual value is printed if it is not specified
+        #[serde(default)]
+        expected: Option<String>,
+    },
+    /// TVM cell to ETH ABI encoded bytes (as in TON event signing)
+    TonToEth {
+        /// Test vector name
+        name: String,
+        /// TON event data ABI (JSON array or string)
+        abi: serde_json::Value,
+        /// Base64 encoded BOC of the event data cell
+        input: String,
+        /// If specified, the whole signed payload will be produced instead of only event data
+        #[serde(default)]
+        event: Option<TonEventContext>,
+        /// Hex encoded expected bytes.
+        /// Actual value is printed if it is not specified
+        #[serde(default)]
+        expected: Option<String>,
+    },
+}
+
+/// Additional data for the TON event payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TonEventContext {
+    pub event_transaction_lt: u64,
+    pub event_timestamp: u32,
+    /// Event configuration address
+    pub configuration: String,
+    /// Event contract address
+    pub event_contract: String,
+    /// Hex encoded EVM proxy address
+    pub proxy: String,
+    pub round_number: u32,
+}
+
+impl AbiTestVector {
+    /// Loads either a single vector or an array of vectors from the JSON file
+    pub fn load_all<P>(path: P) -> Result<Vec<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Vectors {
+            One(AbiTestVector),
+            Many(Vec<AbiTestVector>),
+        }
+
+        let file = std::fs::File::open(path)?;
+        let reader = std::io::BufReader::new(file);
+        Ok(match serde_json::from_reader(reader)? {
+            Vectors::One(vector) => vec![vector],
+            Vectors::Many(vectors) => vectors,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::EthToTon { name, .. } | Self::TonToEth { name, .. } => name,
+        }
+    }
+
+    /// Runs conversion and compares it with the expected value
+    pub fn check(&self) -> Result<AbiCheckStatus> {
+        match self {
+            Self::EthToTon {
+                abi,
+                input,
+                expected,
+                ..
+            } => {
+                let event_abi = EthEventAbi::new(&abi_to_string(abi))
+                    .context("Failed to parse EVM event ABI")?;
+                let data = decode_hex(input).context("Invalid input")?;
+
+                let cell = event_abi
+                    .decode_and_map(&data)
+                    .context("Failed to map event data")?;
+                let actual = base64::encode(ton_types::serialize_toc(&cell)?);
+
+                Ok(match expected {
+                    // NOTE: compare cells by hash because BOC serialization may differ
+                    Some(expected) => {
+                        let expected_cell = decode_boc(expected).context("Invalid expected")?;
+                        if expected_cell.repr_hash() == cell.repr_hash() {
+                            AbiCheckStatus::Passed
+                        } else {
+                            AbiCheckStatus::Failed { actual }
+                        }
+                    }
+                    None => AbiCheckStatus::Generated { actual },
+                })
+            }
+            Self::TonToEth {
+                abi,
+                input,
+                event,
+                expected,
+                ..
+            } => {
+                let event_abi = decode_ton_event_abi(&abi_to_string(abi))
+                    .context("Failed to parse TON event ABI")?;
+                let cell = decode_boc(input).context("Invalid input")?;
+
+                let tokens = ton_abi::TokenValue::decode_params(
+                    &event_abi,
+                    cell.into(),
+                    &ton_abi::contract::ABI_VERSION_2_1,
+                    false,
+                )
+                .context("Failed to decode event data")?;
+                let data = map_ton_tokens_to_eth_bytes(tokens).context("Failed to map tokens")?;
+
+                let data = match event {
+                    Some(event) => make_mapped_ton_event(
+                        event.event_transaction_lt,
+                        event.event_timestamp,
+                        data,
+                        parse_address(&event.configuration).context("Invalid configuration")?,
+                        parse_address(&event.event_contract).context("Invalid event contract")?,
+                        decode_eth_address(&event.proxy).context("Invalid proxy")?,
+                        event.round_number,
+                    ),
+                    None => data,
+                };
+                let actual = hex::encode(&data);
+
+                Ok(match expected {
+                    Some(expected) => {
+                        if decode_hex(expected).context("Invalid expected")? == data {
+                            AbiCheckStatus::Passed
+                        } else {
+                            AbiCheckStatus::Failed { actual }
+                        }
+                    }
+                    None => AbiCheckStatus::Generated { actual },
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AbiCheckStatus {
+    /// Conversion result matches the expected value
+    Passed,
+    /// Conversion result differs from the expected value
+    Failed { actual: String },
+    /// There was no expected value
+    Generated { actual: String },
+}
+
+fn abi_to_string(abi: &serde_json::Value) -> String {
+    match abi {
+        serde_json::Value::String(abi) => abi.clone(),
+        abi => abi.to_string(),
+    }
+}
+
+fn decode_hex(data: &str) -> Result<Vec<u8>> {
+    Ok(hex::decode(data.strip_prefix("0x").unwrap_or(data))?)
+}
+
+fn decode_boc(boc: &str) -> Result<ton_types::Cell> {
+    let bytes = base64::decode(boc)?;
+    Ok(ton_types::deserialize_tree_of_cells(
+        &mut std::io::Cursor::new(bytes),
+    )?)
+}
+
+fn decode_eth_address(address: &str) -> Result<[u8; 20]> {
+    let bytes = decode_hex(address)?;
+    if bytes.len() != 20 {
+        return Err(AbiCheckError::InvalidEthAddress.into());
+    }
+
+    let mut result = [0; 20];
+    result.copy_from_slice(&bytes);
+    Ok(result)
+}
+
+fn parse_address(address: &str) -> Result<UInt256> {
+    let address = ton_block::MsgAddressInt::from_str(address)
+        .map_err(|_| AbiCheckError::InvalidTonAddress)?;
+    Ok(only_account_hash(address))
+}
+
+#[derive(thiserror::Error, Debug)]
+enum AbiCheckError {
+    #[error("Invalid ETH address")]
+    InvalidEthAddress,
+    #[error("Invalid TON address")]
+    InvalidTonAddress,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETH_EVENT_ABI: &str = r#"{
+        "name": "TokenLock",
+        "anonymous": false,
+        "type": "event",
+        "inputs": [
+            { "name": "amount", "type": "uint128", "indexed": false },
+            { "name": "wid", "type": "int8", "indexed": false }
+        ]
+    }"#;
+
+    const TON_EVENT_ABI: &str = r#"[
+        { "name": "amount", "type": "uint128" },
+        { "name": "wid", "type": "int8" }
+    ]"#;
+
+    fn make_cell(amount: u128, wid: i8) -> ton_types::Cell {
+        let mut builder = ton_types::BuilderData::new();
+        builder.append_u128(amount).unwrap();
+        builder.append_i8(wid).unwrap();
+        builder.into_cell().unwrap()
+    }
+
+    fn make_eth_data(amount: u128, wid: i8) -> Vec<u8> {
+        ethabi::encode(&[
+            ethabi::Token::Uint(amount.into()),
+            ethabi::Token::Int(ethabi::Int::from(wid as i64)),
+        ])
+    }
+
+    fn make_boc(cell: &ton_types::Cell) -> String {
+        base64::encode(ton_types::serialize_toc(cell).unwrap())
+    }
+
+    #[test]
+    fn eth_to_ton_mapping() {
+        let vector = AbiTestVector::EthToTon {
+            name: "simple".to_owned(),
+            abi: serde_json::Value::String(ETH_EVENT_ABI.to_owned()),
+            input: hex::encode(make_eth_data(1000, 0)),
+            expected: Some(make_boc(&make_cell(1000, 0))),
+        };
+        assert_eq!(vector.check().unwrap(), AbiCheckStatus::Passed);
+
+        let vector = AbiTestVector::EthToTon {
+            name: "mismatch".to_owned(),
+            abi: serde_json::Value::String(ETH_EVENT_ABI.to_owned()),
+            input: hex::encode(make_eth_data(1000, 0)),
+            expected: Some(make_boc(&make_cell(1001, 0))),
+        };
+        assert!(matches!(
+            vector.check().unwrap(),
+            AbiCheckStatus::Failed { .. }
+        ));
+    }
+
+    #[test]
+    fn ton_to_eth_mapping() {
+        let vector = AbiTestVector::TonToEth {
+            name: "simple".to_owned(),
+            abi: serde_json::from_str(TON_EVENT_ABI).unwrap(),
+            input: make_boc(&make_cell(1000, -1)),
+            event: None,
+            expected: Some(format!("0x{}", hex::encode(make_eth_data(1000, -1)))),
+        };
+        assert_eq!(vector.check().unwrap(), AbiCheckStatus::Passed);
+    }
+
+    #[test]
+    fn generated_output_round_trip() {
+        let vector = AbiTestVector::EthToTon {
+            name: "generated".to_owned(),
+            abi: serde_json::Value::String(ETH_EVENT_ABI.to_owned()),
+            input: hex::encode(make_eth_data(42, 0)),
+            expected: None,
+        };
+        let cell = match vector.check().unwrap() {
+            AbiCheckStatus::Generated { actual } => actual,
+            status => panic!("Unexpected status: {:?}", status),
+        };
+
+        let vector = AbiTestVector::TonToEth {
+            name: "generated".to_owned(),
+            abi: serde_json::Value::String(TON_EVENT_ABI.to_owned()),
+            input: cell,
+            event: None,
+            expected: Some(hex::encode(make_eth_data(42, 0))),
+        };
+        assert_eq!(vector.check().unwrap(), AbiCheckStatus::Passed);
+    }
+
+    const ETH_TUPLE_EVENT_ABI: &str = r#"{
+        "name": "TupleEvent",
+        "anonymous": false,
+        "type": "event",
+        "inputs": [
+            { "name": "flags", "type": "bytes1", "indexed": false },
+            {
+                "name": "pair",
+                "type": "tuple",
+                "indexed": false,
+                "components": [
+                    { "name": "first", "type": "uint32" },
+                    { "name": "second", "type": "uint32" }
+                ]
+            },
+            { "name": "tail", "type": "uint8", "indexed": false }
+        ]
+    }"#;
+
+    const ETH_BYTES_EVENT_ABI: &str = r#"{
+        "name": "BytesEvent",
+        "anonymous": false,
+        "type": "event",
+        "inputs": [
+            { "name": "flags", "type": "bytes1", "indexed": false },
+            { "name": "payload", "type": "bytes", "indexed": false }
+        ]
+    }"#;
+
+    fn check_eth_to_ton(abi: &str, input: Vec<u8>, expected: &ton_types::Cell) -> AbiCheckStatus {
+        AbiTestVector::EthToTon {
+            name: "context flags".to_owned(),
+            abi: serde_json::Value::String(abi.to_owned()),
+            input: hex::encode(input),
+            expected: Some(make_boc(expected)),
+        }
+        .check()
+        .unwrap()
+    }
+
+    fn make_tuple_event_data(flags: u8, first: u32, second: u32, tail: u8) -> Vec<u8> {
+        ethabi::encode(&[
+            ethabi::Token::FixedBytes(vec![flags]),
+            ethabi::Token::Tuple(vec![
+                ethabi::Token::Uint(first.into()),
+                ethabi::Token::Uint(second.into()),
+            ]),
+            ethabi::Token::Uint(tail.into()),
+        ])
+    }
+
+    fn make_bytes_event_data(flags: u8, payload: Vec<u8>) -> Vec<u8> {
+        ethabi::encode(&[
+            ethabi::Token::FixedBytes(vec![flags]),
+            ethabi::Token::Bytes(payload),
+        ])
+    }
+
+    #[test]
+    fn tuples_are_inlined_by_default() {
+        // NOTE: `bytes1` only sets context flags and is not written to the cell
+        let mut builder = ton_types::BuilderData::new();
+        builder.append_u32(1).unwrap();
+        builder.append_u32(2).unwrap();
+        builder.append_u8(3).unwrap();
+        let expected = builder.into_cell().unwrap();
+
+        let status = check_eth_to_ton(
+            ETH_TUPLE_EVENT_ABI,
+            make_tuple_event_data(0x00, 1, 2, 3),
+            &expected,
+        );
+        assert_eq!(status, AbiCheckStatus::Passed);
+    }
+
+    #[test]
+    fn tuples_are_placed_to_new_cell_with_flag() {
+        let mut tuple = ton_types::BuilderData::new();
+        tuple.append_u32(1).unwrap();
+        tuple.append_u32(2).unwrap();
+
+        let mut builder = ton_types::BuilderData::new();
+        builder.append_u8(3).unwrap();
+        builder.append_reference_cell(tuple.into_cell().unwrap());
+        let expected = builder.into_cell().unwrap();
+
+        let status = check_eth_to_ton(
+            ETH_TUPLE_EVENT_ABI,
+            make_tuple_event_data(0x01, 1, 2, 3),
+            &expected,
+        );
+        assert_eq!(status, AbiCheckStatus::Passed);
+    }
+
+    #[test]
+    fn bytes_are_interpreted_as_cell_with_flag() {
+        let payload = make_cell(1000, 0);
+
+        let mut builder = ton_types::BuilderData::new();
+        builder.append_reference_cell(payload.clone());
+        let expected = builder.into_cell().unwrap();
+
+        let status = check_eth_to_ton(
+            ETH_BYTES_EVENT_ABI,
+            make_bytes_event_data(0x02, ton_types::serialize_toc(&payload).unwrap()),
+            &expected,
+        );
+        assert_eq!(status, AbiCheckStatus::Passed);
+    }
+
+    #[test]
+    fn invalid_cell_bytes_are_rejected_without_default_flag() {
+        let vector = AbiTestVector::EthToTon {
+            name: "invalid cell".to_owned(),
+            abi: serde_json::Value::String(ETH_BYTES_EVENT_ABI.to_owned()),
+            input: hex::encode(make_bytes_event_data(0x02, vec![0xde, 0xad])),
+            expected: None,
+        };
+        assert!(vector.check().is_err());
+    }
+
+    #[test]
+    fn invalid_cell_bytes_are_replaced_with_default_cell_with_flag() {
+        let mut builder = ton_types::BuilderData::new();
+        builder.append_reference_cell(ton_types::Cell::default());
+        let expected = builder.into_cell().unwrap();
+
+        let status = check_eth_to_ton(
+            ETH_BYTES_EVENT_ABI,
+            make_bytes_event_data(0x02 | 0x04, vec![0xde, 0xad]),
+            &expected,
+        );
+        assert_eq!(status, AbiCheckStatus::Passed);
+    }
+
+    #[test]
+    fn parse_vectors() {
+        let vectors: Vec<AbiTestVector> = serde_json::from_str(
+            r#"[
+                {
+                    "direction": "eth_to_ton",
+                    "name": "first",
+                    "abi": "{}",
+                    "input": "0x00"
+                },
+                {
+                    "direction": "ton_to_eth",
+                    "name": "second",
+                    "abi": [],
+                    "input": "",
+                    "expected": "0x00"
+                }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(vectors.len(), 2);
+        assert_eq!(vectors[0].name(), "first");
+        assert_eq!(vectors[1].name(), "second");
+    }
+}
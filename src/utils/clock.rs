@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+/// Time source for the logic which depends on wall clock
+#[async_trait::async_trait]
+pub trait Clock: Send + Sync {
+    /// Unix timestamp in milliseconds
+    fn now_ms(&self) -> u64;
+
+    /// Unix timestamp in seconds
+    fn now_sec(&self) -> u32 {
+        (self.now_ms() / 1000) as u32
+    }
+
+    async fn sleep(&self, duration: Duration);
+
+    /// Sleeps until the specified unix timestamp in seconds
+    async fn sleep_until(&self, timestamp: u32) {
+        let deadline_ms = timestamp as u64 * 1000;
+        let delay_ms = deadline_ms.saturating_sub(self.now_ms());
+        self.sleep(Duration::from_millis(delay_ms)).await
+    }
+}
+
+/// System time with tokio timers
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+#[async_trait::async_trait]
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        chrono::Utc::now().timestamp_millis() as u64
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// Manually advanced time. Sleeps are completed only by [`VirtualClock::advance`]
+pub struct VirtualClock {
+    now_ms_tx: watch::Sender<u64>,
+    // NOTE: keeps channel open
+    _now_ms_rx: watch::Receiver<u64>,
+}
+
+impl VirtualClock {
+    pub fn new(now_sec: u32) -> Self {
+        let (now_ms_tx, now_ms_rx) = watch::channel(now_sec as u64 * 1000);
+        Self {
+            now_ms_tx,
+            _now_ms_rx: now_ms_rx,
+        }
+    }
+
+    /// Moves time forward and wakes all sleeps which are due
+    pub fn advance(&self, duration: Duration) {
+        let now_ms = *self.now_ms_tx.borrow() + duration.as_millis() as u64;
+        self.now_ms_tx.send(now_ms).ok();
+    }
+}
+
+#[async_trait::async_trait]
+impl Clock for VirtualClock {
+    fn now_ms(&self) -> u64 {
+        *self.now_ms_tx.borrow()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let mut now_ms_rx = self.now_ms_tx.subscribe();
+        let deadline_ms = *now_ms_rx.borrow_and_update() + duration.as_millis() as u64;
+        while *now_ms_rx.borrow_and_update() < deadline_ms {
+            if now_ms_rx.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn virtual_sleep_completes_after_advance() {
+        let clock = VirtualClock::new(100);
+
+        let sleep = clock.sleep_until(110);
+        futures::pin_mut!(sleep);
+        assert!(futures::poll!(&mut sleep).is_pending());
+
+        clock.advance(Duration::from_secs(5));
+        assert!(futures::poll!(&mut sleep).is_pending());
+
+        clock.advance(Duration::from_secs(5));
+        assert!(futures::poll!(&mut sleep).is_ready());
+        assert_eq!(clock.now_sec(), 110);
+    }
+}
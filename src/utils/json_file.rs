@@ -0,0 +1,131 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::watch;
+
+/// Loads the JSON file. Returns `None` if it doesn't exist
+pub fn load_json<T, P>(path: P) -> Result<Option<T>>
+where
+    T: DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let file = std::fs::OpenOptions::new().read(true).open(path)?;
+    let reader = std::io::BufReader::new(file);
+    Ok(Some(serde_json::from_reader(reader)?))
+}
+
+/// Writes the value to the temp file and then replaces the target file with it,
+/// so that the state is not corrupted on crash
+pub fn save_json_atomically<T, P>(path: P, value: &T) -> Result<()>
+where
+    T: Serialize,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let temp_path = path.with_extension("tmp");
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&temp_path)?;
+    let mut writer = std::io::BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+
+    std::fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+/// Saves the latest value in background. Intermediate values are skipped
+/// if the file is still being written.
+///
+/// NOTE: must be called within the tokio runtime context
+pub fn start_json_writer<T>(name: &'static str, path: PathBuf, value: T) -> watch::Sender<T>
+where
+    T: Serialize + Clone + Send + Sync + 'static,
+{
+    async fn save<T>(name: &'static str, path: &Path, value: T)
+    where
+        T: Serialize + Send + 'static,
+    {
+        let path = path.to_owned();
+        match tokio::task::spawn_blocking(move || save_json_atomically(path, &value)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::error!("Failed to save {}: {:?}", name, e),
+            Err(e) => log::error!("{} writer panicked: {:?}", name, e),
+        }
+    }
+
+    let (tx, mut rx) = watch::channel(value);
+
+    tokio::spawn(async move {
+        while rx.changed().await.is_ok() {
+            let value = rx.borrow().clone();
+            save(name, &path, value).await;
+        }
+
+        // NOTE: save the last value in case it was sent right before the sender was dropped
+        let value = rx.borrow().clone();
+        save(name, &path, value).await;
+    });
+
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct State {
+        value: u32,
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+
+        assert_eq!(load_json::<State, _>(&path).unwrap(), None);
+
+        save_json_atomically(&path, &State { value: 1 }).unwrap();
+        save_json_atomically(&path, &State { value: 2 }).unwrap();
+        assert_eq!(load_json(&path).unwrap(), Some(State { value: 2 }));
+
+        // Temp file is replaced
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn writer_saves_latest_value() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+
+        let tx = start_json_writer("state", path.clone(), State { value: 0 });
+        for value in 1..=3 {
+            tx.send(State { value }).unwrap();
+        }
+        drop(tx);
+
+        // The last value is saved after the sender is dropped
+        tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            while load_json::<State, _>(&path).unwrap() != Some(State { value: 3 }) {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
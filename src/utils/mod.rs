@@ -1,5 +1,8 @@
+pub use self::abi_check::*;
+pub use self::clock::*;
 pub use self::eth_address::*;
 pub use self::existing_contract::*;
+pub use self::json_file::*;
 pub use self::pending_messages_queue::*;
 pub use self::retry::*;
 pub use self::serde_helpers::*;
@@ -7,8 +10,11 @@ pub use self::shard_utils::*;
 pub use self::tristate::*;
 pub use self::tx_context::*;
 
+mod abi_check;
+mod clock;
 mod eth_address;
 mod existing_contract;
+mod json_file;
 mod pending_messages_queue;
 mod retry;
 mod serde_helpers;
@@ -60,7 +60,35 @@ impl PendingMessagesQueue {
         }
     }
 
-    pub fn deliver_message(&self, account: UInt256, message_hash: UInt256) {
+    pub fn deliver_message(
+        &self,
+        account: UInt256,
+        message_hash: UInt256,
+        transaction_hash: UInt256,
+    ) {
+        self.complete_message(
+            account,
+            message_hash,
+            MessageStatus::Delivered { transaction_hash },
+        );
+    }
+
+    /// Marks message as included into the aborted transaction
+    pub fn fail_message(
+        &self,
+        account: UInt256,
+        message_hash: UInt256,
+        exit_code: i32,
+        phase: TransactionPhase,
+    ) {
+        self.complete_message(
+            account,
+            message_hash,
+            MessageStatus::Failed { exit_code, phase },
+        );
+    }
+
+    fn complete_message(&self, account: UInt256, message_hash: UInt256, status: MessageStatus) {
         let mut entries = self.entries.lock();
         let mut message = match entries.remove(&PendingMessageId {
             account,
@@ -73,7 +101,7 @@ impl PendingMessagesQueue {
         self.entry_count.fetch_sub(1, Ordering::Release);
 
         if let Some(tx) = message.tx.take() {
-            tx.send(MessageStatus::Delivered).ok();
+            tx.send(status).ok();
         }
 
         let current_min_expire_at = self.min_expire_at.load(Ordering::Acquire);
@@ -121,8 +149,50 @@ impl PendingMessagesQueue {
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum MessageStatus {
-    Delivered,
+    /// Message was included into the successful transaction
+    Delivered {
+        transaction_hash: UInt256,
+    },
     Expired,
+    /// Message was included into the aborted transaction
+    Failed {
+        exit_code: i32,
+        phase: TransactionPhase,
+    },
+}
+
+/// Transaction phase in which the message execution failed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TransactionPhase {
+    Compute,
+    Action,
+    Other,
+}
+
+impl TransactionPhase {
+    /// Extracts exit code and failed phase from the aborted transaction
+    pub fn from_aborted(info: &ton_block::TransactionDescrOrdinary) -> (i32, Self) {
+        if let ton_block::TrComputePhase::Vm(compute) = &info.compute_ph {
+            if !compute.success {
+                return (compute.exit_code, Self::Compute);
+            }
+        }
+
+        match &info.action {
+            Some(action) if !action.success => (action.result_code, Self::Action),
+            _ => (0, Self::Other),
+        }
+    }
+}
+
+impl std::fmt::Display for TransactionPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Compute => f.write_str("compute"),
+            Self::Action => f.write_str("action"),
+            Self::Other => f.write_str("other"),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -137,7 +207,7 @@ struct PendingMessage {
 }
 
 type MessageStatusTx = oneshot::Sender<MessageStatus>;
-type MessageStatusRx = oneshot::Receiver<MessageStatus>;
+pub type MessageStatusRx = oneshot::Receiver<MessageStatus>;
 
 #[derive(thiserror::Error, Debug)]
 enum PendingMessagesQueueError {
@@ -174,9 +244,34 @@ mod tests {
         assert_eq!(queue.min_expire_at.load(Ordering::Acquire), 10);
 
         // Deliver message
-        queue.deliver_message(make_hash(0), make_hash(0));
+        queue.deliver_message(make_hash(0), make_hash(0), make_hash(2));
         assert_eq!(queue.min_expire_at.load(Ordering::Acquire), u32::MAX);
-        assert_eq!(rx.await.unwrap(), MessageStatus::Delivered);
+        assert_eq!(
+            rx.await.unwrap(),
+            MessageStatus::Delivered {
+                transaction_hash: make_hash(2)
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_message_flow() {
+        let queue = make_queue();
+
+        // Add message
+        let rx = queue.add_message(make_hash(0), make_hash(0), 10).unwrap();
+
+        // Fail message
+        queue.fail_message(make_hash(0), make_hash(0), 100, TransactionPhase::Compute);
+        assert_eq!(queue.min_expire_at.load(Ordering::Acquire), u32::MAX);
+        assert_eq!(queue.len(), 0);
+        assert_eq!(
+            rx.await.unwrap(),
+            MessageStatus::Failed {
+                exit_code: 100,
+                phase: TransactionPhase::Compute
+            }
+        );
     }
 
     #[tokio::test]
@@ -231,26 +326,41 @@ mod tests {
         queue.update(&ton_block::ShardIdent::masterchain(), 5);
         assert_eq!(queue.min_expire_at.load(Ordering::Acquire), 10);
 
-        queue.deliver_message(make_hash(1), make_hash(1));
+        queue.deliver_message(make_hash(1), make_hash(1), make_hash(3));
         assert_eq!(queue.min_expire_at.load(Ordering::Acquire), 10);
 
         queue.update(&ton_block::ShardIdent::masterchain(), 15);
         assert_eq!(queue.min_expire_at.load(Ordering::Acquire), u32::MAX);
 
         assert_eq!(rx1.await.unwrap(), MessageStatus::Expired);
-        assert_eq!(rx2.await.unwrap(), MessageStatus::Delivered);
+        assert_eq!(
+            rx2.await.unwrap(),
+            MessageStatus::Delivered {
+                transaction_hash: make_hash(3)
+            }
+        );
 
         // Add messages
         let rx1 = queue.add_message(make_hash(0), make_hash(0), 10).unwrap();
         let rx2 = queue.add_message(make_hash(1), make_hash(1), 20).unwrap();
 
-        queue.deliver_message(make_hash(0), make_hash(0));
+        queue.deliver_message(make_hash(0), make_hash(0), make_hash(2));
         assert_eq!(queue.min_expire_at.load(Ordering::Acquire), 20);
 
-        queue.deliver_message(make_hash(1), make_hash(1));
+        queue.deliver_message(make_hash(1), make_hash(1), make_hash(3));
         assert_eq!(queue.min_expire_at.load(Ordering::Acquire), u32::MAX);
 
-        assert_eq!(rx1.await.unwrap(), MessageStatus::Delivered);
-        assert_eq!(rx2.await.unwrap(), MessageStatus::Delivered);
+        assert_eq!(
+            rx1.await.unwrap(),
+            MessageStatus::Delivered {
+                transaction_hash: make_hash(2)
+            }
+        );
+        assert_eq!(
+            rx2.await.unwrap(),
+            MessageStatus::Delivered {
+                transaction_hash: make_hash(3)
+            }
+        );
     }
 }
@@ -25,3 +25,75 @@ pub mod serde_url {
         PathAndQuery::from_str(&data).map_err(D::Error::custom)
     }
 }
+
+/// Serializes `Option<u128>` as a decimal string, because JSON numbers
+/// can't represent it precisely. Plain numbers are accepted for compatibility
+pub mod serde_optional_u128_string {
+    use std::fmt;
+
+    use serde::de::{Error, Visitor};
+
+    pub fn serialize<S>(data: &Option<u128>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match data {
+            Some(data) => serializer.serialize_some(&data.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<u128>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct OptionalVisitor;
+
+        impl<'de> Visitor<'de> for OptionalVisitor {
+            type Value = Option<u128>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("optional u128 as a string or a number")
+            }
+
+            fn visit_none<E: Error>(self) -> Result<Self::Value, E> {
+                Ok(None)
+            }
+
+            fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+                Ok(None)
+            }
+
+            fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                deserializer.deserialize_any(ValueVisitor).map(Some)
+            }
+        }
+
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = u128;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("u128 as a string or a number")
+            }
+
+            fn visit_u64<E: Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(value as u128)
+            }
+
+            fn visit_u128<E: Error>(self, value: u128) -> Result<Self::Value, E> {
+                Ok(value)
+            }
+
+            fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_option(OptionalVisitor)
+    }
+}
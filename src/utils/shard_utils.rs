@@ -2,7 +2,7 @@ use std::borrow::Borrow;
 
 use anyhow::{Context, Result};
 use tiny_adnl::utils::*;
-use ton_block::HashmapAugType;
+use ton_block::{BinTreeType, Deserializable, HashmapAugType};
 use ton_types::UInt256;
 
 use super::existing_contract::*;
@@ -11,12 +11,64 @@ pub type ShardsMap = FxHashMap<ton_block::ShardIdent, ton_block::BlockIdExt>;
 
 #[derive(Debug, Clone)]
 pub struct LatestShardBlocks {
+    pub mc_seqno: u32,
     pub current_utime: u32,
     pub block_ids: ShardsMap,
 }
 
 pub type ShardAccountsMap = FxHashMap<ton_block::ShardIdent, ton_block::ShardAccounts>;
 
+/// Extracts the latest shard blocks from the masterchain block
+pub fn extract_shards(
+    block: &ton_block::Block,
+    block_info: &ton_block::BlockInfo,
+) -> Result<LatestShardBlocks> {
+    let mc_seqno = block_info.seq_no();
+    let current_utime = block_info.gen_utime().0;
+    let extra = block.extra.read_struct()?;
+    let custom = match extra.read_custom()? {
+        Some(custom) => custom,
+        None => {
+            return Ok(LatestShardBlocks {
+                mc_seqno,
+                current_utime,
+                block_ids: Default::default(),
+            })
+        }
+    };
+
+    let mut block_ids = FxHashMap::with_capacity_and_hasher(16, Default::default());
+
+    custom
+        .shards()
+        .iterate_with_keys(|wc_id: i32, ton_block::InRefValue(shards_tree)| {
+            if wc_id == ton_block::MASTERCHAIN_ID {
+                return Ok(true);
+            }
+
+            shards_tree.iterate(|prefix, descr| {
+                let shard_id = ton_block::ShardIdent::with_prefix_slice(wc_id, prefix)?;
+
+                block_ids.insert(
+                    shard_id,
+                    ton_block::BlockIdExt::with_params(
+                        shard_id,
+                        descr.seq_no,
+                        descr.root_hash,
+                        descr.file_hash,
+                    ),
+                );
+                Ok(true)
+            })
+        })?;
+
+    Ok(LatestShardBlocks {
+        mc_seqno,
+        current_utime,
+        block_ids,
+    })
+}
+
 /// Helper trait to reduce boilerplate for getting accounts from shards state
 pub trait ShardAccountsMapExt {
     /// Looks for a suitable shard and tries to extract information about the contract from it